@@ -14,38 +14,236 @@
  * limitations under the License.
  */
 
-use crate::types::OrderState;
+use crate::catalogue::ProductCatalogue;
+use crate::couriers::{AssignCourier, CourierPool};
+use crate::kitchen::{KitchenTicket, TicketPrinter};
+use crate::notifications::{notify_all, send_all, Channel, MessageTemplate, Notification, Notifier};
+use crate::payments::{idempotency_key, Capture, PaymentGateway, Refund, RefundRequest};
+use crate::pricing::{price_order, Amount, PricedOrder};
+use crate::promotions::{Promotion, PromotionError, PromotionStore};
+use crate::receipts::{receipt_key, BlobStore, Receipt, SentReceipt, StoredReceipt};
+use crate::sla::SlaPolicy;
+use crate::streaming::StatusPublisher;
+use crate::types::{OrderProduct, OrderState, StatusChange};
 use anyhow::Result;
+use chrono::Utc;
+use std::sync::Arc;
 use temporal_sdk::{ActContext, ActivityError};
-use tokio::time::{sleep, Duration};
-use tracing::info;
+use tracing::{info, warn};
 
-pub async fn refund_payment(_ctx: ActContext, _: ()) -> Result<(), ActivityError> {
-    info!("Refund payment activity started");
+/// Price the basket from the catalogue. This is an activity so the price charged is
+/// recorded in history and does not change on replay if the catalogue does.
+pub async fn price_basket(
+    _ctx: ActContext,
+    catalogue: Arc<dyn ProductCatalogue>,
+    products: Vec<OrderProduct>,
+) -> Result<PricedOrder, ActivityError> {
+    info!("Price basket activity started for {} product(s)", products.len());
 
-    // Simulate payment processing time
-    sleep(Duration::from_secs(5)).await;
+    let priced = price_order(catalogue.as_ref(), &products).map_err(|e| ActivityError::NonRetryable(e.into()))?;
 
-    info!("Refund payment activity finished");
+    info!("Price basket activity finished: {}", priced.total());
+    Ok(priced)
+}
+
+/// The deadlines orders are held to, as this worker loaded them. This is an activity
+/// so the policy an order is held to is recorded in its history, and the order replays
+/// the same on a worker started with a different one.
+pub async fn load_sla_policy(_ctx: ActContext, sla: Arc<SlaPolicy>, _: ()) -> Result<SlaPolicy, ActivityError> {
+    info!("Load SLA policy activity started");
+    Ok(sla.as_ref().clone())
+}
+
+/// Look up a discount code the customer entered. Whether the basket qualifies is
+/// checked by the workflow, against the basket as it is when the order is priced.
+pub async fn find_promotion(
+    _ctx: ActContext,
+    store: Arc<dyn PromotionStore>,
+    code: String,
+) -> Result<Promotion, ActivityError> {
+    info!("Find promotion activity started for {}", code);
+
+    let promotion = store.find(&code).ok_or(PromotionError::UnknownCode(code))?;
+
+    info!("Find promotion activity finished: {:?}", promotion.discount);
+    Ok(promotion)
+}
+
+/// Print an accepted order's ticket in the restaurant's kitchen
+pub async fn print_kitchen_ticket(
+    ctx: ActContext,
+    printer: Arc<dyn TicketPrinter>,
+    ticket: KitchenTicket,
+) -> Result<(), ActivityError> {
+    info!("Print kitchen ticket activity started for {}", ticket.order_id);
+
+    printer.print(&activity_key(&ctx, "print"), &ticket).await?;
+
+    info!("Print kitchen ticket activity finished");
     Ok(())
 }
 
-pub async fn send_text_message(_ctx: ActContext, status: OrderState) -> Result<(), ActivityError> {
-    info!("Send text message activity started for status: {}", status.status);
+/// Key for an external call - the same on every retry of this activity, and never
+/// the same for another run of the order
+fn activity_key(ctx: &ActContext, operation: &str) -> String {
+    idempotency_key(&workflow_id(ctx), &run_id(ctx), &ctx.get_info().activity_id, operation)
+}
+
+/// ID of the workflow that scheduled this activity, which is the order's ID
+fn workflow_id(ctx: &ActContext) -> String {
+    ctx.get_info()
+        .workflow_execution
+        .as_ref()
+        .map(|e| e.workflow_id.clone())
+        .unwrap_or_default()
+}
+
+/// ID of the run of the workflow that scheduled this activity
+fn run_id(ctx: &ActContext) -> String {
+    ctx.get_info()
+        .workflow_execution
+        .as_ref()
+        .map(|e| e.run_id.clone())
+        .unwrap_or_default()
+}
+
+/// Find a courier to take a ready order to the customer, returning the courier's ID.
+/// Waits, by retrying, until one is free or the courier workflow gives up waiting.
+pub async fn assign_courier(
+    ctx: ActContext,
+    pool: Arc<dyn CourierPool>,
+    request: AssignCourier,
+) -> Result<String, ActivityError> {
+    info!("Assign courier activity started for {}", request.job.order_id);
 
-    // Simulate sending text message
-    sleep(Duration::from_secs(1)).await;
+    let courier_id = pool.assign(&activity_key(&ctx, "assign"), &request).await?;
 
-    info!("Send text message activity finished");
+    info!("Assign courier activity finished: {}", courier_id);
+    Ok(courier_id)
+}
+
+/// Free a courier who has finished, or been taken off, a delivery
+pub async fn release_courier(
+    _ctx: ActContext,
+    pool: Arc<dyn CourierPool>,
+    courier_id: String,
+) -> Result<(), ActivityError> {
+    info!("Release courier activity started for {}", courier_id);
+
+    pool.release(&courier_id).await?;
+
+    info!("Release courier activity finished");
     Ok(())
 }
 
-pub async fn take_payment(_ctx: ActContext, _: ()) -> Result<(), ActivityError> {
-    info!("Take payment activity started");
+/// Give the customer back money taken by `take_payment`
+pub async fn refund_payment(
+    ctx: ActContext,
+    gateway: Arc<dyn PaymentGateway>,
+    request: RefundRequest,
+) -> Result<Refund, ActivityError> {
+    info!("Refund payment activity started for {} on {}", request.amount, request.transaction_id);
 
-    // Simulate payment processing time
-    sleep(Duration::from_secs(5)).await;
+    let refund = gateway.refund(&activity_key(&ctx, "refund"), &request).await?;
 
-    info!("Take payment activity finished");
+    info!("Refund payment activity finished: {}", refund.refund_id);
+    Ok(refund)
+}
+
+/// Tell the customer about the order's current status on every configured channel,
+/// returning the messages sent so they are recorded in history
+pub async fn send_text_message(
+    ctx: ActContext,
+    notifiers: Arc<Vec<Arc<dyn Notifier>>>,
+    status: OrderState,
+) -> Result<Vec<Notification>, ActivityError> {
+    info!("Send text message activity started for status: {}", status.status);
+
+    let sent = notify_all(&notifiers, &activity_key(&ctx, "notify"), &status).await?;
+
+    info!("Send text message activity finished: {} notification(s) sent", sent.len());
+    Ok(sent)
+}
+
+/// Hand the order's status changes so far to the API, for the customers streaming them
+pub async fn publish_status_changes(
+    ctx: ActContext,
+    publisher: Arc<dyn StatusPublisher>,
+    changes: Vec<StatusChange>,
+) -> Result<(), ActivityError> {
+    let order_id = workflow_id(&ctx);
+    info!("Publish status changes activity started for {}", order_id);
+
+    publisher.publish(&order_id, &changes).await?;
+
+    info!("Publish status changes activity finished: {} change(s)", changes.len());
     Ok(())
 }
+
+/// Tell the customer, and any webhook, that the order has been in its status for
+/// longer than the SLA allows
+pub async fn escalate_order(
+    ctx: ActContext,
+    notifiers: Arc<Vec<Arc<dyn Notifier>>>,
+    status: OrderState,
+) -> Result<Vec<Notification>, ActivityError> {
+    warn!("Escalate order activity started: order overdue in {}", status.status);
+
+    let template = MessageTemplate::escalation_for(&status.status);
+    let sent = send_all(&notifiers, &activity_key(&ctx, "escalate"), &status, &template).await?;
+
+    info!("Escalate order activity finished: {} notification(s) sent", sent.len());
+    Ok(sent)
+}
+
+/// Render a completed order's receipt as HTML and plain text, store both, and email
+/// the customer a link. Receipts are stored under the order's ID and run, so a retry
+/// replaces them rather than storing them again.
+pub async fn send_receipt(
+    ctx: ActContext,
+    store: Arc<dyn BlobStore>,
+    notifiers: Arc<Vec<Arc<dyn Notifier>>>,
+    mut order: OrderState,
+) -> Result<SentReceipt, ActivityError> {
+    let order_id = workflow_id(&ctx);
+    let run_id = run_id(&ctx);
+    info!("Send receipt activity started for {}", order_id);
+
+    let receipt =
+        Receipt::for_order(&order_id, &order, Utc::now()).map_err(|e| ActivityError::NonRetryable(e.into()))?;
+    let stored = StoredReceipt {
+        html_url: store
+            .put(&receipt_key(&order_id, &run_id, "html"), "text/html", receipt.to_html().into_bytes())
+            .await?,
+        text_url: store
+            .put(&receipt_key(&order_id, &run_id, "txt"), "text/plain", receipt.to_text().into_bytes())
+            .await?,
+    };
+
+    order.receipt = Some(stored.clone());
+    let emailers: Vec<Arc<dyn Notifier>> =
+        notifiers.iter().filter(|n| n.channel() == Channel::Email).cloned().collect();
+    let sent = send_all(&emailers, &activity_key(&ctx, "receipt"), &order, &MessageTemplate::receipt()).await?;
+
+    info!("Send receipt activity finished: {}", stored.html_url);
+    Ok(SentReceipt {
+        receipt: stored,
+        notifications: sent,
+    })
+}
+
+/// Authorise and capture the order total. A declined card fails the activity without
+/// retrying; gateway timeouts and outages are retried.
+pub async fn take_payment(
+    ctx: ActContext,
+    gateway: Arc<dyn PaymentGateway>,
+    amount: Amount,
+) -> Result<Capture, ActivityError> {
+    info!("Take payment activity started for {}", amount);
+
+    let authorization = gateway.authorize(&activity_key(&ctx, "authorize"), amount).await?;
+    let capture = gateway.capture(&activity_key(&ctx, "capture"), &authorization).await?;
+
+    info!("Take payment activity finished: {}", capture.transaction_id);
+    Ok(capture)
+}
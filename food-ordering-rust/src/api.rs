@@ -0,0 +1,889 @@
+/*
+ * Copyright 2025 Simon Emms <simon@simonemms.com>
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! REST API for the web UI. Bodies use the web UI's camelCase JSON, so they are
+//! converted to and from the workflow's types at the edge. Status changes are pushed
+//! to the web UI as server-sent events.
+
+use crate::client::{
+    add_item, apply_discount, cancel_order, checkout, get_order_outcome, get_order_status, get_status_changes,
+    list_running_orders, order_is_running, remove_discount, remove_item, signal_checkout, start_order,
+    update_order_status, OrderNotFound, RunningOrder, UpdateRejected,
+};
+use crate::capacity::KitchenSlot;
+use crate::pricing::Amount;
+use crate::search::OrderFilter;
+use crate::streaming::{PublishError, StatusPublisher};
+use crate::types::{Address, BasketError, OrderProduct, OrderState, OrderStatus, StatusChange};
+use async_trait::async_trait;
+use axum::extract::{Path, Query, State};
+use axum::http::{header, HeaderMap, StatusCode};
+use axum::response::sse::{Event, KeepAlive, Sse};
+use axum::response::{IntoResponse, Response};
+use axum::routing::{delete, get, post};
+use axum::{Json, Router};
+use chrono::{DateTime, Utc};
+use futures::stream::{self, Stream, StreamExt};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use temporal_client::{Client, RetryClient};
+use thiserror::Error;
+use tokio::sync::broadcast::{self, error::RecvError};
+use tracing::{info, warn};
+use uuid::Uuid;
+
+/// A product in a basket
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BasketItem {
+    pub product_id: u32,
+    pub quantity: u32,
+}
+
+impl From<BasketItem> for OrderProduct {
+    fn from(item: BasketItem) -> Self {
+        Self {
+            product_id: item.product_id,
+            quantity: item.quantity,
+        }
+    }
+}
+
+impl From<&OrderProduct> for BasketItem {
+    fn from(product: &OrderProduct) -> Self {
+        Self {
+            product_id: product.product_id,
+            quantity: product.quantity,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DeliveryAddress {
+    pub line1: String,
+    #[serde(default)]
+    pub line2: Option<String>,
+    #[serde(default)]
+    pub line3: Option<String>,
+    pub town: String,
+    #[serde(default)]
+    pub county: Option<String>,
+    pub post_code: String,
+}
+
+impl From<DeliveryAddress> for Address {
+    fn from(address: DeliveryAddress) -> Self {
+        Self {
+            line1: address.line1,
+            line2: address.line2,
+            line3: address.line3,
+            town: address.town,
+            county: address.county,
+            post_code: address.post_code,
+        }
+    }
+}
+
+/// Body of `POST /api/order`. The web UI's order form sends a whole order, `status`
+/// and all, and never checks it out - so an order posted with a `status` is checked
+/// out as soon as it starts. The workflow still decides the status.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NewOrder {
+    #[serde(default)]
+    pub collection: bool,
+    #[serde(default)]
+    pub delivery_address: Option<DeliveryAddress>,
+    pub email: String,
+    #[serde(default)]
+    pub phone: Option<String>,
+    #[serde(default)]
+    pub products: Vec<BasketItem>,
+    /// The restaurant to order from - the central kitchen if not given
+    #[serde(default)]
+    pub restaurant_id: Option<String>,
+    #[serde(default)]
+    pub status: Option<String>,
+}
+
+impl NewOrder {
+    /// Whether the body came from the web UI's order form, which expects the order to
+    /// be checked out for it
+    pub fn checks_out(&self) -> bool {
+        self.status.is_some()
+    }
+}
+
+impl TryFrom<NewOrder> for OrderState {
+    type Error = BasketError;
+
+    fn try_from(order: NewOrder) -> Result<Self, Self::Error> {
+        let mut state = OrderState::new();
+        state.restaurant_id = order.restaurant_id;
+        state.collection = order.collection;
+        state.delivery_address = order.delivery_address.map(Address::from);
+        state.email = order.email;
+        state.phone = order.phone;
+        for item in order.products {
+            state.add_item(item.into())?;
+        }
+        Ok(state)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OrderCreated {
+    pub order_id: String,
+}
+
+/// Body of `POST /api/order/{orderId}/status`, and its response
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct StatusUpdate {
+    pub status: String,
+}
+
+/// An order as the customer sees it - `GET /api/order/{orderId}/status`. Anyone with
+/// the order ID can see it, so it leaves out the customer's contact details.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OrderView {
+    pub collection: bool,
+    pub products: Vec<BasketItem>,
+    pub status: String,
+    /// Where the restaurant can move the order from here, named as `status` is, so the
+    /// web UI only offers the moves UPDATE_STATUS will accept
+    #[serde(with = "status_names")]
+    pub next_statuses: Vec<OrderStatus>,
+    /// The basket total, formatted for display
+    pub total: Option<String>,
+    /// The discount code entered and what it takes off the total, formatted for display
+    pub discount_code: Option<String>,
+    pub discount: Option<String>,
+    pub courier_id: Option<String>,
+    pub restaurant_id: Option<String>,
+    /// While the kitchen is too busy to start on the order, how many orders are ahead of
+    /// it and roughly how long it will wait
+    pub orders_ahead: Option<usize>,
+    pub estimated_wait_minutes: Option<u64>,
+    /// When a paid-for order should arrive, or be ready to collect
+    pub eta: Option<DateTime<Utc>>,
+    /// Link to the receipt of a completed order
+    pub receipt_url: Option<String>,
+}
+
+impl From<&OrderState> for OrderView {
+    fn from(state: &OrderState) -> Self {
+        Self {
+            collection: state.collection,
+            products: state.products.iter().map(BasketItem::from).collect(),
+            status: state.status.to_string(),
+            next_statuses: state.restaurant_next_statuses(),
+            total: state.pricing.as_ref().map(|p| p.total().to_string()),
+            discount_code: state.promotion.as_ref().map(|p| p.code.clone()),
+            discount: state.pricing.as_ref().and_then(|p| {
+                let discount = p.discount.as_ref()?;
+                Some(
+                    Amount {
+                        amount_in_pence: discount.amount_in_pence,
+                        currency: p.currency,
+                    }
+                    .to_string(),
+                )
+            }),
+            courier_id: state.courier_id.clone(),
+            restaurant_id: state.restaurant_id.clone(),
+            orders_ahead: match state.kitchen_slot {
+                Some(KitchenSlot::Queued { ahead, .. }) => Some(ahead),
+                _ => None,
+            },
+            estimated_wait_minutes: match state.kitchen_slot {
+                Some(KitchenSlot::Queued { estimated_wait_secs, .. }) => Some(estimated_wait_secs.div_ceil(60)),
+                _ => None,
+            },
+            eta: state.eta.as_ref().map(|e| e.at),
+            receipt_url: state.receipt.as_ref().map(|r| r.html_url.clone()),
+        }
+    }
+}
+
+/// Statuses as the web UI names them, e.g. `OUT_FOR_DELIVERY`
+mod status_names {
+    use crate::types::OrderStatus;
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    pub fn serialize<S: Serializer>(statuses: &[OrderStatus], serializer: S) -> Result<S::Ok, S::Error> {
+        statuses.iter().map(|s| s.to_string()).collect::<Vec<_>>().serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<OrderStatus>, D::Error> {
+        Vec::<String>::deserialize(deserializer)?
+            .iter()
+            .map(|s| s.parse().map_err(serde::de::Error::custom))
+            .collect()
+    }
+}
+
+/// What the admin order list knows about an order - only what its search attributes
+/// and memo hold, so listing orders never has to ask each workflow. Prices are in
+/// pounds, as the web UI expects, once the basket has been priced.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OrderSummaryState {
+    pub status: String,
+    pub restaurant_id: Option<String>,
+    pub total: Option<f64>,
+    pub collection: bool,
+    pub products: Vec<SummaryProduct>,
+}
+
+/// A product in the admin order list. Until the basket is priced only its ID and
+/// quantity are known.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SummaryProduct {
+    pub id: u32,
+    pub name: Option<String>,
+    pub price: Option<f64>,
+    pub quantity: u32,
+}
+
+impl SummaryProduct {
+    /// The basket's products, named and priced from its pricing if it has one
+    fn from_order(order: &OrderState) -> Vec<Self> {
+        match &order.pricing {
+            Some(pricing) => pricing
+                .line_items
+                .iter()
+                .map(|line| Self {
+                    id: line.product_id,
+                    name: Some(line.name.clone()),
+                    price: Some(line.unit_price_in_pence as f64 / 100.0),
+                    quantity: line.quantity,
+                })
+                .collect(),
+            None => order
+                .products
+                .iter()
+                .map(|product| Self {
+                    id: product.product_id,
+                    name: None,
+                    price: None,
+                    quantity: product.quantity,
+                })
+                .collect(),
+        }
+    }
+}
+
+/// An order in the admin order list - `GET /api/order`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OrderSummary {
+    pub order_id: String,
+    pub state: OrderSummaryState,
+    pub created: Option<DateTime<Utc>>,
+}
+
+impl From<RunningOrder> for OrderSummary {
+    fn from(running: RunningOrder) -> Self {
+        Self {
+            order_id: running.workflow_id,
+            state: OrderSummaryState {
+                // An order that has not set its search attributes yet has not left DEFAULT
+                status: running.status.unwrap_or(OrderStatus::Default).to_string(),
+                restaurant_id: running.restaurant_id,
+                total: running.total_in_pence.map(|pence| pence as f64 / 100.0),
+                collection: running.order.as_ref().is_some_and(|order| order.collection),
+                products: running.order.as_ref().map(SummaryProduct::from_order).unwrap_or_default(),
+            },
+            created: running.started,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct OrderList {
+    pub orders: Vec<OrderSummary>,
+}
+
+/// A status change, as sent down `GET /api/order/{orderId}/events`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct StatusEvent {
+    pub status: String,
+    pub at: DateTime<Utc>,
+}
+
+impl From<&StatusChange> for StatusEvent {
+    fn from(change: &StatusChange) -> Self {
+        Self {
+            status: change.status.to_string(),
+            at: change.at,
+        }
+    }
+}
+
+/// How many status changes a client has seen, from the ID of the last event it got.
+/// Event IDs count the order's status changes from 0, so a client claiming the largest
+/// ID there is has seen everything.
+pub fn first_unseen(last_event_id: Option<&str>) -> Result<usize, ApiError> {
+    match last_event_id {
+        None => Ok(0),
+        Some(id) => match id.parse::<usize>() {
+            Ok(last) => Ok(match last.checked_add(1) {
+                Some(first) => first,
+                None => usize::MAX,
+            }),
+            Err(_) => Err(ApiError::BadRequest(format!("invalid event ID {}", id))),
+        },
+    }
+}
+
+/// Why a request failed, and the HTTP status it is answered with
+#[derive(Debug, Error)]
+pub enum ApiError {
+    #[error("order {0} not found")]
+    NotFound(String),
+    #[error("order {0} has finished")]
+    Finished(String),
+    #[error("{0}")]
+    BadRequest(String),
+    #[error("admin token required")]
+    Unauthorized,
+    #[error("admin endpoints are disabled")]
+    AdminDisabled,
+    #[error(transparent)]
+    Temporal(#[from] anyhow::Error),
+}
+
+impl ApiError {
+    /// Updates the workflow refused are the caller's fault, anything else is ours
+    fn from_update(e: anyhow::Error) -> Self {
+        if e.downcast_ref::<UpdateRejected>().is_some() {
+            ApiError::BadRequest(e.to_string())
+        } else {
+            ApiError::Temporal(e)
+        }
+    }
+
+    pub fn status_code(&self) -> StatusCode {
+        match self {
+            ApiError::NotFound(_) => StatusCode::NOT_FOUND,
+            ApiError::Finished(_) => StatusCode::CONFLICT,
+            ApiError::BadRequest(_) => StatusCode::BAD_REQUEST,
+            ApiError::Unauthorized => StatusCode::UNAUTHORIZED,
+            ApiError::AdminDisabled => StatusCode::FORBIDDEN,
+            ApiError::Temporal(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        if let ApiError::Temporal(e) = &self {
+            warn!("API request failed: {:#}", e);
+        }
+
+        (self.status_code(), Json(json!({ "error": self.to_string() }))).into_response()
+    }
+}
+
+/// Every status change an order has made so far, and whether it will make any more
+#[derive(Debug, Clone, Default)]
+struct StatusFeed {
+    changes: Arc<Vec<StatusChange>>,
+    finished: bool,
+}
+
+/// An order whose status changes are being streamed, and the clients following them
+struct WatchedOrder {
+    latest: StatusFeed,
+    sender: broadcast::Sender<StatusFeed>,
+}
+
+/// Who may use the admin endpoints
+#[derive(Debug, Clone)]
+enum AdminAccess {
+    /// Nobody - until a token is given, or they are opened up on purpose
+    Disabled,
+    Token(String),
+    /// Anyone - for local development only
+    Open,
+}
+
+/// What every request handler is given
+#[derive(Clone)]
+pub struct ApiState {
+    client: RetryClient<Client>,
+    admin: AdminAccess,
+    watched: Arc<Mutex<HashMap<String, WatchedOrder>>>,
+}
+
+impl ApiState {
+    /// State for an API whose admin endpoints are disabled until `with_admin_token` or
+    /// `with_open_admin` says otherwise
+    pub fn new(client: RetryClient<Client>) -> Self {
+        Self {
+            client,
+            admin: AdminAccess::Disabled,
+            watched: Arc::default(),
+        }
+    }
+
+    /// Require `Authorization: Bearer <token>` on the admin endpoints
+    pub fn with_admin_token(mut self, token: impl Into<String>) -> Self {
+        self.admin = AdminAccess::Token(token.into());
+        self
+    }
+
+    /// Let anyone use the admin endpoints, without a token. Only for local development.
+    pub fn with_open_admin(mut self) -> Self {
+        self.admin = AdminAccess::Open;
+        self
+    }
+
+    fn check_admin(&self, headers: &HeaderMap) -> Result<(), ApiError> {
+        let token = match &self.admin {
+            AdminAccess::Disabled => return Err(ApiError::AdminDisabled),
+            AdminAccess::Token(token) => token,
+            AdminAccess::Open => return Ok(()),
+        };
+
+        let bearer = headers
+            .get(header::AUTHORIZATION)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.strip_prefix("Bearer "));
+        if bearer == Some(token.as_str()) {
+            Ok(())
+        } else {
+            Err(ApiError::Unauthorized)
+        }
+    }
+
+    async fn is_running(&self, order_id: &str) -> Result<bool, ApiError> {
+        order_is_running(&self.client, order_id).await.map_err(|e| {
+            if e.is::<OrderNotFound>() {
+                ApiError::NotFound(order_id.to_string())
+            } else {
+                ApiError::Temporal(e)
+            }
+        })
+    }
+
+    /// Only running orders can be changed
+    async fn check_running(&self, order_id: &str) -> Result<(), ApiError> {
+        if self.is_running(order_id).await? {
+            Ok(())
+        } else {
+            Err(ApiError::Finished(order_id.to_string()))
+        }
+    }
+
+    /// Follow an order's status changes, returning what is known so far and a receiver
+    /// for what the workflow pushes next
+    fn follow(&self, order_id: &str) -> (StatusFeed, broadcast::Receiver<StatusFeed>) {
+        let mut watched = self.watched.lock().unwrap();
+        if let Some(order) = watched.get(order_id) {
+            return (order.latest.clone(), order.sender.subscribe());
+        }
+
+        // Orders that stopped moving after their last client went are forgotten here
+        watched.retain(|_, order| order.sender.receiver_count() > 0);
+
+        let (sender, receiver) = broadcast::channel(16);
+        let order = WatchedOrder {
+            latest: StatusFeed::default(),
+            sender,
+        };
+        watched.insert(order_id.to_string(), order);
+        (StatusFeed::default(), receiver)
+    }
+
+    /// Send a watched order's status changes to the clients following it, if they have
+    /// not seen them all already. Orders nobody is following are ignored.
+    fn record(&self, order_id: &str, feed: StatusFeed) {
+        let mut watched = self.watched.lock().unwrap();
+        let Some(order) = watched.get_mut(order_id) else {
+            return;
+        };
+
+        if feed.finished || feed.changes.len() > order.latest.changes.len() {
+            order.latest = feed.clone();
+            let _ = order.sender.send(feed);
+        }
+        if order.latest.finished || order.sender.receiver_count() == 0 {
+            watched.remove(order_id);
+        }
+    }
+
+    /// Take the status changes an order's workflow pushed, for the clients streaming them
+    pub fn push_status_changes(&self, order_id: &str, changes: Vec<StatusChange>) {
+        let finished = changes.last().is_some_and(|c| c.status.is_terminal());
+        let feed = StatusFeed {
+            changes: Arc::new(changes),
+            finished,
+        };
+        self.record(order_id, feed);
+    }
+
+    /// Read an order's status changes from its memo, for a client that has just started
+    /// following it. Anything pushed while this is read is kept, as pushes and the memo
+    /// both carry every change so far.
+    async fn catch_up(&self, order_id: &str) {
+        let feed = match get_status_changes(&self.client, order_id).await {
+            Ok(status) => {
+                let finished = !status.running || status.changes.last().is_some_and(|c| c.status.is_terminal());
+                StatusFeed {
+                    changes: Arc::new(status.changes),
+                    finished,
+                }
+            }
+            Err(e) if e.is::<OrderNotFound>() => StatusFeed {
+                finished: true,
+                ..Default::default()
+            },
+            Err(e) => {
+                warn!("Unable to get status changes of order {}, waiting for the next: {:#}", order_id, e);
+                return;
+            }
+        };
+
+        self.record(order_id, feed);
+    }
+}
+
+/// The API's own clients are streamed to directly, for workers that share its process
+#[async_trait]
+impl StatusPublisher for ApiState {
+    async fn publish(&self, order_id: &str, changes: &[StatusChange]) -> Result<(), PublishError> {
+        self.push_status_changes(order_id, changes.to_vec());
+        Ok(())
+    }
+}
+
+/// The web UI's `api/order` routes, plus basket and checkout routes, and the route
+/// workers push status changes to
+pub fn router(state: ApiState) -> Router {
+    Router::new()
+        .route("/api/order", get(list_orders).post(create_order))
+        .route("/api/order/{order_id}/status", get(order_status).post(change_status))
+        .route("/api/order/{order_id}/events", get(status_events))
+        .route("/api/order/{order_id}/status-changes", post(receive_status_changes))
+        .route("/api/order/{order_id}/items", post(add_to_basket))
+        .route("/api/order/{order_id}/items/{product_id}", delete(remove_from_basket))
+        .route("/api/order/{order_id}/discount", post(add_discount).delete(delete_discount))
+        .route("/api/order/{order_id}/checkout", post(checkout_order))
+        .route("/api/order/{order_id}/cancel", post(cancel))
+        .with_state(state)
+}
+
+/// Start an order with the customer's basket
+async fn create_order(
+    State(api): State<ApiState>,
+    Json(order): Json<NewOrder>,
+) -> Result<(StatusCode, Json<OrderCreated>), ApiError> {
+    let order_id = format!("order-{}", Uuid::new_v4());
+    let checks_out = order.checks_out();
+    let order = OrderState::try_from(order).map_err(|e| ApiError::BadRequest(e.to_string()))?;
+    start_order(&api.client, &order_id, &order).await?;
+    info!("Started order {}", order_id);
+
+    if checks_out {
+        signal_checkout(&api.client, &order_id).await?;
+        info!("Checked out order {} for the web UI", order_id);
+    }
+
+    Ok((StatusCode::CREATED, Json(OrderCreated { order_id })))
+}
+
+#[derive(Debug, Deserialize)]
+struct ListFilter {
+    status: Option<String>,
+    restaurant: Option<String>,
+    email: Option<String>,
+}
+
+impl TryFrom<ListFilter> for OrderFilter {
+    type Error = ApiError;
+
+    fn try_from(list: ListFilter) -> Result<Self, Self::Error> {
+        Ok(OrderFilter {
+            status: list
+                .status
+                .map(|s| s.parse::<OrderStatus>())
+                .transpose()
+                .map_err(ApiError::BadRequest)?,
+            restaurant_id: list.restaurant,
+            email: list.email,
+        })
+    }
+}
+
+/// Every running order, for the admin page - optionally only those with a `status`,
+/// from a `restaurant` or for a customer's `email`
+async fn list_orders(
+    State(api): State<ApiState>,
+    headers: HeaderMap,
+    Query(list): Query<ListFilter>,
+) -> Result<Json<OrderList>, ApiError> {
+    api.check_admin(&headers)?;
+    let filter = OrderFilter::try_from(list)?;
+
+    let orders = list_running_orders(&api.client, &filter)
+        .await?
+        .into_iter()
+        .map(OrderSummary::from)
+        .collect();
+
+    Ok(Json(OrderList { orders }))
+}
+
+async fn order_status(
+    State(api): State<ApiState>,
+    Path(order_id): Path<String>,
+) -> Result<Json<OrderView>, ApiError> {
+    let order = if api.is_running(&order_id).await? {
+        get_order_status(&api.client, &order_id).await?
+    } else {
+        // Finished orders keep their final order in their outcome
+        get_order_outcome(&api.client, &order_id).await?.order
+    };
+
+    Ok(Json(OrderView::from(&order)))
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Resume {
+    last_event_id: Option<String>,
+}
+
+/// Push every status change as it happens. Clients that reconnect with the
+/// `Last-Event-ID` header (or `?lastEventId=`) carry on from where they were, and the
+/// stream ends once the order finishes.
+async fn status_events(
+    State(api): State<ApiState>,
+    Path(order_id): Path<String>,
+    headers: HeaderMap,
+    Query(resume): Query<Resume>,
+) -> Result<Sse<impl Stream<Item = Result<Event, axum::Error>>>, ApiError> {
+    let last_event_id = headers
+        .get("last-event-id")
+        .and_then(|v| v.to_str().ok())
+        .or(resume.last_event_id.as_deref());
+    let seen = first_unseen(last_event_id)?;
+
+    // Unknown orders are a 404 rather than an empty stream
+    api.is_running(&order_id).await?;
+
+    // Follow before reading the memo, so nothing pushed in between is missed
+    let (feed, updates) = api.follow(&order_id);
+    api.catch_up(&order_id).await;
+    let watch = StatusWatch {
+        feed,
+        updates,
+        seen,
+        done: false,
+    };
+    let events = stream::unfold(watch, |mut watch| async move {
+        let events = watch.next_events().await?;
+        Some((stream::iter(events), watch))
+    })
+    .flatten();
+
+    Ok(Sse::new(events).keep_alive(KeepAlive::default()))
+}
+
+/// Take the status changes a worker's `HttpStatusPublisher` pushed. Workers are trusted
+/// like the restaurant, so this is an admin endpoint.
+async fn receive_status_changes(
+    State(api): State<ApiState>,
+    Path(order_id): Path<String>,
+    headers: HeaderMap,
+    Json(changes): Json<Vec<StatusChange>>,
+) -> Result<StatusCode, ApiError> {
+    api.check_admin(&headers)?;
+    api.push_status_changes(&order_id, changes);
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Follows one client's view of an order's status changes
+struct StatusWatch {
+    feed: StatusFeed,
+    updates: broadcast::Receiver<StatusFeed>,
+    seen: usize,
+    done: bool,
+}
+
+impl StatusWatch {
+    /// Wait for the next status changes, or `None` once there will be no more
+    async fn next_events(&mut self) -> Option<Vec<Result<Event, axum::Error>>> {
+        loop {
+            if self.done {
+                return None;
+            }
+            if self.feed.finished || self.feed.changes.len() > self.seen {
+                return Some(self.events());
+            }
+
+            match self.updates.recv().await {
+                Ok(feed) => self.feed = feed,
+                // Every feed has all the changes so far, so the next one catches up
+                Err(RecvError::Lagged(_)) => continue,
+                Err(RecvError::Closed) => return None,
+            }
+        }
+    }
+
+    /// The changes this client has not seen yet
+    fn events(&mut self) -> Vec<Result<Event, axum::Error>> {
+        let unseen = self.feed.changes.get(self.seen..).unwrap_or_default();
+        let events = unseen
+            .iter()
+            .zip(self.seen..)
+            .map(|(change, id)| Event::default().id(id.to_string()).json_data(StatusEvent::from(change)))
+            .collect();
+
+        self.seen = self.seen.max(self.feed.changes.len());
+        self.done = self.feed.finished;
+        events
+    }
+}
+
+/// Move an order on, as the restaurant would
+async fn change_status(
+    State(api): State<ApiState>,
+    Path(order_id): Path<String>,
+    headers: HeaderMap,
+    Json(update): Json<StatusUpdate>,
+) -> Result<Json<StatusUpdate>, ApiError> {
+    api.check_admin(&headers)?;
+    let status = update.status.parse::<OrderStatus>().map_err(ApiError::BadRequest)?;
+    api.check_running(&order_id).await?;
+
+    update_order_status(&api.client, &order_id, status.clone())
+        .await
+        .map_err(ApiError::from_update)?;
+    info!("Order {} moved to {}", order_id, status);
+
+    Ok(Json(StatusUpdate {
+        status: status.to_string(),
+    }))
+}
+
+async fn add_to_basket(
+    State(api): State<ApiState>,
+    Path(order_id): Path<String>,
+    Json(item): Json<BasketItem>,
+) -> Result<Json<Vec<BasketItem>>, ApiError> {
+    api.check_running(&order_id).await?;
+
+    let basket = add_item(&api.client, &order_id, item.into())
+        .await
+        .map_err(ApiError::from_update)?;
+
+    Ok(Json(basket.iter().map(BasketItem::from).collect()))
+}
+
+#[derive(Debug, Deserialize)]
+struct Quantity {
+    quantity: Option<u32>,
+}
+
+/// Take some of a product out of the basket - one unless `?quantity=` says otherwise
+async fn remove_from_basket(
+    State(api): State<ApiState>,
+    Path((order_id, product_id)): Path<(String, u32)>,
+    Query(query): Query<Quantity>,
+) -> Result<Json<Vec<BasketItem>>, ApiError> {
+    api.check_running(&order_id).await?;
+
+    let item = OrderProduct {
+        product_id,
+        quantity: query.quantity.unwrap_or(1),
+    };
+    let basket = remove_item(&api.client, &order_id, item)
+        .await
+        .map_err(ApiError::from_update)?;
+
+    Ok(Json(basket.iter().map(BasketItem::from).collect()))
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DiscountCode {
+    pub code: String,
+}
+
+/// Enter a discount code, returning the order as it is now priced
+async fn add_discount(
+    State(api): State<ApiState>,
+    Path(order_id): Path<String>,
+    Json(body): Json<DiscountCode>,
+) -> Result<Json<OrderView>, ApiError> {
+    api.check_running(&order_id).await?;
+
+    apply_discount(&api.client, &order_id, &body.code)
+        .await
+        .map_err(ApiError::from_update)?;
+
+    let state = get_order_status(&api.client, &order_id).await?;
+    Ok(Json(OrderView::from(&state)))
+}
+
+/// Take the discount code off, returning the order at full price
+async fn delete_discount(
+    State(api): State<ApiState>,
+    Path(order_id): Path<String>,
+) -> Result<Json<OrderView>, ApiError> {
+    api.check_running(&order_id).await?;
+
+    remove_discount(&api.client, &order_id)
+        .await
+        .map_err(ApiError::from_update)?;
+
+    let state = get_order_status(&api.client, &order_id).await?;
+    Ok(Json(OrderView::from(&state)))
+}
+
+/// Cancel the order for the customer. Orders the restaurant has accepted are refused
+/// with the reason.
+async fn cancel(State(api): State<ApiState>, Path(order_id): Path<String>) -> Result<Json<StatusUpdate>, ApiError> {
+    api.check_running(&order_id).await?;
+
+    cancel_order(&api.client, &order_id)
+        .await
+        .map_err(ApiError::from_update)?;
+    info!("Order {} cancelled", order_id);
+
+    Ok(Json(StatusUpdate {
+        status: OrderStatus::Cancelled.to_string(),
+    }))
+}
+
+/// Submit the basket for payment. A basket that cannot be checked out is a bad request;
+/// otherwise the order is paid for asynchronously - poll its status.
+async fn checkout_order(State(api): State<ApiState>, Path(order_id): Path<String>) -> Result<StatusCode, ApiError> {
+    api.check_running(&order_id).await?;
+
+    let amount = checkout(&api.client, &order_id).await.map_err(ApiError::from_update)?;
+    info!("Order {} checked out for {}", order_id, amount);
+
+    Ok(StatusCode::ACCEPTED)
+}
@@ -0,0 +1,54 @@
+/*
+ * Copyright 2025 Simon Emms <simon@simonemms.com>
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use food_ordering_rust::api::{router, ApiState};
+use std::{env, str::FromStr};
+use temporal_sdk::sdk_client_options;
+use temporal_sdk_core::Url;
+use tracing::{info, warn};
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    // Initialize tracing
+    tracing_subscriber::fmt::init();
+
+    // Get Temporal server address from environment
+    let temporal_address = env::var("TEMPORAL_ADDRESS").unwrap_or_else(|_| "http://localhost:7233".to_string());
+
+    // Create client
+    let server_options = sdk_client_options(Url::from_str(&temporal_address)?).build()?;
+    let client = server_options.connect("default", None).await?;
+
+    // The admin endpoints are disabled unless a token is given, or they are opened up
+    // on purpose for local development
+    let mut state = ApiState::new(client);
+    if let Ok(token) = env::var("API_ADMIN_TOKEN") {
+        state = state.with_admin_token(token);
+    } else if env::var("API_ADMIN_OPEN").is_ok_and(|v| v == "true") {
+        warn!("API_ADMIN_OPEN is set - admin endpoints are open to anyone");
+        state = state.with_open_admin();
+    } else {
+        info!("API_ADMIN_TOKEN not set - admin endpoints are disabled");
+    }
+
+    let address = env::var("API_ADDRESS").unwrap_or_else(|_| "0.0.0.0:3000".to_string());
+    let listener = tokio::net::TcpListener::bind(&address).await?;
+    info!("API listening on {}", address);
+
+    axum::serve(listener, router(state)).await?;
+
+    Ok(())
+}
@@ -0,0 +1,77 @@
+/*
+ * Copyright 2025 Simon Emms <simon@simonemms.com>
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! Lists in-flight orders, as the restaurant sees its queue:
+//!
+//! ```sh
+//! cargo run --bin orders -- [--status PENDING] [--restaurant <ID>] [--email <ADDRESS>]
+//! ```
+
+use food_ordering_rust::client::list_running_orders;
+use food_ordering_rust::pricing::Amount;
+use food_ordering_rust::search::OrderFilter;
+use std::{env, str::FromStr};
+use temporal_sdk::sdk_client_options;
+use temporal_sdk_core::Url;
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    // Initialize tracing
+    tracing_subscriber::fmt::init();
+
+    let filter = parse_filter(env::args().skip(1))?;
+
+    // Get Temporal server address from environment
+    let temporal_address = env::var("TEMPORAL_ADDRESS").unwrap_or_else(|_| "http://localhost:7233".to_string());
+
+    // Create client
+    let server_options = sdk_client_options(Url::from_str(&temporal_address)?).build()?;
+    let client = server_options.connect("default", None).await?;
+
+    let orders = list_running_orders(&client, &filter).await?;
+    for order in &orders {
+        let total = order.total_in_pence.map(|amount_in_pence| Amount {
+            amount_in_pence,
+            ..Default::default()
+        });
+        println!(
+            "{}\t{}\t{}\t{}\t{}",
+            order.workflow_id,
+            order.status.as_ref().map(ToString::to_string).unwrap_or_else(|| "-".to_string()),
+            total.map(|t| t.to_string()).unwrap_or_else(|| "-".to_string()),
+            order.restaurant_id.as_deref().unwrap_or("-"),
+            order.started.map(|s| s.to_rfc3339()).unwrap_or_else(|| "-".to_string()),
+        );
+    }
+    println!("{} in-flight orders", orders.len());
+
+    Ok(())
+}
+
+/// Build the filter from `--status`, `--restaurant` and `--email` arguments
+fn parse_filter(mut args: impl Iterator<Item = String>) -> Result<OrderFilter, Box<dyn std::error::Error>> {
+    let mut filter = OrderFilter::default();
+    while let Some(flag) = args.next() {
+        let value = args.next().ok_or_else(|| format!("{} needs a value", flag))?;
+        filter = match flag.as_str() {
+            "--status" => filter.with_status(value.parse()?),
+            "--restaurant" => filter.with_restaurant(value),
+            "--email" => filter.with_email(value),
+            _ => return Err(format!("unknown argument {}", flag).into()),
+        };
+    }
+    Ok(filter)
+}
@@ -14,14 +14,12 @@
  * limitations under the License.
  */
 
-use food_ordering_rust::constants::ORDER_FOOD_TASK_QUEUE;
+use food_ordering_rust::client::{add_item, checkout, get_order_outcome, get_order_status, start_order};
 use food_ordering_rust::types::{Address, OrderProduct, OrderState};
 use std::{env, str::FromStr};
 use temporal_sdk::{sdk_client_options};
 use temporal_sdk_core::{Url};
-use temporal_client::{WorkflowOptions, WorkflowClientTrait};
-use temporal_sdk_core_protos::temporal::api::enums::v1::WorkflowIdReusePolicy;
-use tracing::{error, info};
+use tracing::info;
 use uuid::Uuid;
 
 #[tokio::main]
@@ -39,14 +37,16 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     // Create sample order state
     let mut order_state = OrderState::new();
     order_state.email = "customer@example.com".to_string();
+    order_state.phone = Some("+447700900123".to_string());
     order_state.collection = false;
+    order_state.restaurant_id = env::var("RESTAURANT_ID").ok();
     order_state.delivery_address = Some(Address {
         line1: "123 Main St".to_string(),
         line2: None,
         line3: None,
         town: "Anytown".to_string(),
         county: None,
-        post_code: "12345".to_string(),
+        post_code: "AB1 2CD".to_string(),
     });
     order_state.products.push(OrderProduct {
         product_id: 1,
@@ -55,37 +55,47 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
 
     // Start workflow
     let workflow_id = format!("food-order-{}", Uuid::new_v4());
-    let workflow_options = WorkflowOptions {
-        id_reuse_policy: WorkflowIdReusePolicy::AllowDuplicate,
-        ..Default::default()
-    };
 
     info!("Starting order workflow with ID: {}", workflow_id);
 
-    let handle = client
-        .start_workflow(
-            vec![serde_json::to_vec(&order_state)?.into()], // input payloads
-            workflow_id.clone(),
-            "order_workflow".to_string(),
-            ORDER_FOOD_TASK_QUEUE.to_string(),
-            None, // workflow_id_reuse_policy
-            workflow_options,
-        )
-        .await?;
-
-    info!("Started workflow: {} with run ID: {}", workflow_id, handle.run_id);
-
-    // Wait for workflow completion
-    let result = client.get_workflow_execution_history(workflow_id, Some(handle.run_id.clone()), vec![]).await;
-    match result {
-        Ok(_) => {
-            info!("Workflow completed successfully");
-        }
-        Err(e) => {
-            error!("Workflow failed: {}", e);
-            return Err(e.into());
-        }
+    let run_id = start_order(&client, &workflow_id, &order_state).await?;
+
+    info!("Started workflow: {} with run ID: {}", workflow_id, run_id);
+
+    // Give the worker a moment to pick up the order, then show its live state
+    tokio::time::sleep(std::time::Duration::from_secs(5)).await;
+    let state = get_order_status(&client, &workflow_id).await?;
+    info!("State: {:?}", state);
+
+    // Add to basket
+    let basket = add_item(&client, &workflow_id, OrderProduct {
+        product_id: 1,
+        quantity: 2,
+    })
+    .await?;
+    info!("Basket: {:?}", basket);
+
+    tokio::time::sleep(std::time::Duration::from_secs(5)).await;
+
+    // Submit the basket for payment
+    let amount = checkout(&client, &workflow_id).await?;
+    info!("Order checked out for {}", amount);
+
+    // Wait for the restaurant to finish with the order
+    info!("Waiting for the order to complete - update its status from the Temporal UI");
+    let outcome = get_order_outcome(&client, &workflow_id).await?;
+
+    info!("Order finished with status: {}", outcome.status);
+    if let Some(charged) = outcome.charged {
+        info!("Charged: {}", charged);
+    }
+    if let Some(refunded) = outcome.refunded {
+        info!("Refunded: {}", refunded);
+    }
+    for transition in &outcome.transitions {
+        info!("{} at {}", transition.status, transition.at);
     }
+    info!("Notifications sent: {:?}", outcome.notification_ids);
 
     info!("Order processed");
     Ok(())
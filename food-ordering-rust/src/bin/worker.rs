@@ -14,11 +14,33 @@
  * limitations under the License.
  */
 
-use food_ordering_rust::activities::{refund_payment, send_text_message, take_payment};
-use food_ordering_rust::constants::ORDER_FOOD_TASK_QUEUE;
-use food_ordering_rust::workflows::order_workflow;
-use std::{env, str::FromStr};
-use temporal_sdk::{sdk_client_options, Worker};
+use food_ordering_rust::activities::{
+    assign_courier, escalate_order, find_promotion, load_sla_policy, price_basket, print_kitchen_ticket,
+    publish_status_changes, refund_payment, release_courier, send_receipt, send_text_message, take_payment,
+};
+use food_ordering_rust::capacity::KitchenCapacity;
+use food_ordering_rust::catalogue::{FileCatalogue, ProductCatalogue, SampleCatalogue};
+use food_ordering_rust::client::{set_kitchen_capacity, start_kitchen};
+use food_ordering_rust::constants::{restaurant_task_queue, ORDER_FOOD_TASK_QUEUE};
+use food_ordering_rust::couriers::{AssignCourier, CourierPool, DeliveryTimeouts, FakeCourierPool};
+use food_ordering_rust::eta::DeliveryTimes;
+use food_ordering_rust::kitchen::{KitchenTicket, StdoutPrinter, TicketPrinter};
+use food_ordering_rust::notifications::{
+    EmailNotifier, FileSink, NotificationSink, Notifier, SmsNotifier, StdoutSink, WebhookNotifier,
+};
+use food_ordering_rust::payments::{FakePaymentGateway, PaymentGateway, RefundRequest};
+use food_ordering_rust::pricing::Amount;
+use food_ordering_rust::promotions::{LocalPromotions, PromotionStore};
+use food_ordering_rust::receipts::{BlobStore, LocalBlobStore};
+use food_ordering_rust::sla::SlaPolicy;
+use food_ordering_rust::streaming::{HttpStatusPublisher, NoPublisher, StatusPublisher};
+use food_ordering_rust::types::{OrderProduct, OrderState, StatusChange};
+use food_ordering_rust::workflows::{
+    courier_workflow, delivery_job_from_args, kitchen_queue_from_args, kitchen_workflow, order_state_from_args,
+    order_workflow,
+};
+use std::{env, error::Error, str::FromStr, sync::Arc, time::Duration};
+use temporal_sdk::{sdk_client_options, ActContext, Worker};
 use temporal_sdk_core::{init_worker, Url, CoreRuntime};
 use temporal_sdk_core_api::{
     worker::{WorkerConfigBuilder, WorkerVersioningStrategy},
@@ -42,35 +64,79 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     let telemetry_options = TelemetryOptionsBuilder::default().build()?;
     let runtime = CoreRuntime::new_assume_tokio(telemetry_options)?;
 
+    // A central worker runs the workflows, payments and notifications, and the kitchen
+    // for orders without a restaurant. A restaurant worker only runs its own kitchen.
+    let restaurant_id = match env::var("WORKER_ROLE").as_deref() {
+        Ok("central") | Err(_) => None,
+        Ok("restaurant") => Some(
+            env::var("RESTAURANT_ID").map_err(|_| "RESTAURANT_ID is needed for a restaurant worker")?,
+        ),
+        Ok(role) => return Err(format!("unknown WORKER_ROLE {} (expected central or restaurant)", role).into()),
+    };
+    let task_queue = match &restaurant_id {
+        Some(restaurant_id) => restaurant_task_queue(restaurant_id),
+        None => ORDER_FOOD_TASK_QUEUE.to_string(),
+    };
+
     // Create worker config
     let worker_config = WorkerConfigBuilder::default()
         .namespace("default")
-        .task_queue(ORDER_FOOD_TASK_QUEUE)
+        .task_queue(task_queue.clone())
         .versioning_strategy(WorkerVersioningStrategy::None { 
             build_id: "rust-sdk".to_owned() 
         })
         .build()?;
 
+    // Load the product catalogue - the sample products unless a file is given
+    let catalogue: Arc<dyn ProductCatalogue> = match env::var("PRODUCT_CATALOGUE") {
+        Ok(path) => {
+            info!("Loading product catalogue from {}", path);
+            Arc::new(FileCatalogue::load(path)?)
+        }
+        Err(_) => Arc::new(SampleCatalogue),
+    };
+
+    // Each kitchen takes on as many orders at once as it has slots - the kitchen
+    // workflow runs centrally, so this is queued until a central worker is running
+    let mut capacity = KitchenCapacity::default();
+    let slots = env::var("KITCHEN_SLOTS").ok();
+    if let Some(slots) = &slots {
+        capacity.slots = slots.parse()?;
+    }
+    if let Ok(secs) = env::var("KITCHEN_PREP_TIME") {
+        capacity.prep_secs = secs.parse()?;
+    }
+    start_kitchen(&client, restaurant_id.as_deref(), capacity.clone()).await?;
+
+    // A running kitchen keeps the capacity it was given unless asked for another, so
+    // restarting a worker does not undo a change an operator made
+    if slots.is_some() {
+        info!("Kitchen has {} slots", capacity.slots);
+        set_kitchen_capacity(&client, restaurant_id.as_deref(), capacity).await?;
+    }
+
     // Initialize core worker
     let core_worker = init_worker(&runtime, worker_config, client)?;
 
     // Create Rust SDK worker
-    let mut worker = Worker::new_from_core(std::sync::Arc::new(core_worker), ORDER_FOOD_TASK_QUEUE);
+    let mut worker = Worker::new_from_core(std::sync::Arc::new(core_worker), task_queue.clone());
 
-    // Register workflows
-    worker.register_wf("order_workflow", |ctx: temporal_sdk::WfContext| async move {
-        // This is a placeholder - in a real implementation, the order state would be passed
-        // through the workflow input. For now, we'll create a dummy order state.
-        let dummy_order = food_ordering_rust::types::OrderState::new();
-        order_workflow(ctx, dummy_order).await
+    // Every worker runs a kitchen - a restaurant's own, or the central one
+    worker.register_activity("price_basket", move |ctx: ActContext, products: Vec<OrderProduct>| {
+        let catalogue = catalogue.clone();
+        async move { price_basket(ctx, catalogue, products).await }
+    });
+    let printer: Arc<dyn TicketPrinter> = Arc::new(StdoutPrinter);
+    worker.register_activity("print_kitchen_ticket", move |ctx: ActContext, ticket: KitchenTicket| {
+        let printer = printer.clone();
+        async move { print_kitchen_ticket(ctx, printer, ticket).await }
     });
 
-    // Register activities
-    worker.register_activity("refund_payment", refund_payment);
-    worker.register_activity("send_text_message", send_text_message);
-    worker.register_activity("take_payment", take_payment);
+    if restaurant_id.is_none() {
+        register_central(&mut worker)?;
+    }
 
-    info!("Starting worker for task queue: {}", ORDER_FOOD_TASK_QUEUE);
+    info!("Starting worker for task queue: {}", task_queue);
 
     // Run worker
     if let Err(e) = worker.run().await {
@@ -80,3 +146,162 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
 
     Ok(())
 }
+
+/// Register the workflows, and the activities that stay central, on a central worker
+fn register_central(worker: &mut Worker) -> Result<(), Box<dyn Error>> {
+    // Load the deadlines orders are held to - the defaults unless a file is given
+    let sla = Arc::new(match env::var("ORDER_SLA") {
+        Ok(path) => {
+            info!("Loading SLA policy from {}", path);
+            SlaPolicy::load(path)?
+        }
+        Err(_) => SlaPolicy::default(),
+    });
+
+    // Load how long deliveries take to each post code - the same everywhere unless a
+    // file is given
+    let delivery_times = Arc::new(match env::var("DELIVERY_TIMES") {
+        Ok(path) => {
+            info!("Loading delivery times from {}", path);
+            DeliveryTimes::load(path)?
+        }
+        Err(_) => DeliveryTimes::default(),
+    });
+
+    // Load the discount codes customers can use - none unless a file is given
+    let promotions: Arc<dyn PromotionStore> = match env::var("PROMOTIONS") {
+        Ok(path) => {
+            info!("Loading promotions from {}", path);
+            Arc::new(LocalPromotions::load(path)?)
+        }
+        Err(_) => Arc::new(LocalPromotions::default()),
+    };
+
+    // No real gateway is wired up - the fake approves everything, as slowly as a real one
+    let gateway: Arc<dyn PaymentGateway> =
+        Arc::new(FakePaymentGateway::new().with_latency(Duration::from_secs(2)));
+
+    // No courier service is wired up - deliveries go to a few in-memory couriers
+    let couriers: Arc<dyn CourierPool> = Arc::new(FakeCourierPool::new(["courier-1", "courier-2", "courier-3"]));
+    let mut timeouts = DeliveryTimeouts::default();
+    if let Ok(secs) = env::var("COURIER_PICKUP_TIMEOUT") {
+        timeouts.pickup_secs = secs.parse()?;
+    }
+    if let Ok(secs) = env::var("COURIER_DROP_OFF_TIMEOUT") {
+        timeouts.drop_off_secs = secs.parse()?;
+    }
+    if let Ok(secs) = env::var("COURIER_ASSIGN_TIMEOUT") {
+        timeouts.assign_secs = secs.parse()?;
+    }
+    if let Ok(max) = env::var("COURIER_MAX_REASSIGNMENTS") {
+        timeouts.max_reassignments = max.parse()?;
+    }
+    let timeouts = Arc::new(timeouts);
+
+    // Texts and emails go to stdout, or a file, until real providers are wired up
+    let sink: Arc<dyn NotificationSink> = match env::var("NOTIFICATION_LOG") {
+        Ok(path) => {
+            info!("Writing notifications to {}", path);
+            Arc::new(FileSink::new(path))
+        }
+        Err(_) => Arc::new(StdoutSink),
+    };
+    let mut notifiers: Vec<Arc<dyn Notifier>> =
+        vec![Arc::new(SmsNotifier::new(sink.clone())), Arc::new(EmailNotifier::new(sink))];
+    if let Ok(url) = env::var("NOTIFICATION_WEBHOOK_URL") {
+        info!("Sending notifications to webhook {}", url);
+        notifiers.push(Arc::new(WebhookNotifier::new(url)));
+    }
+    let notifiers = Arc::new(notifiers);
+
+    // Receipts are written to a local directory until an object store is wired up
+    let mut receipts = LocalBlobStore::new(env::var("RECEIPT_DIR").unwrap_or_else(|_| "receipts".to_string()));
+    if let Ok(url) = env::var("RECEIPT_BASE_URL") {
+        receipts = receipts.with_base_url(url);
+    }
+    let receipts: Arc<dyn BlobStore> = Arc::new(receipts);
+
+    // Status changes are pushed to the API for the customers streaming them, if it is
+    // given. The API's admin token is needed unless its admin endpoints are open.
+    let publisher: Arc<dyn StatusPublisher> = match env::var("STATUS_EVENTS_API_URL") {
+        Ok(url) => {
+            info!("Pushing status changes to the API at {}", url);
+            let mut publisher = HttpStatusPublisher::new(url);
+            if let Ok(token) = env::var("API_ADMIN_TOKEN") {
+                publisher = publisher.with_token(token);
+            }
+            Arc::new(publisher)
+        }
+        Err(_) => Arc::new(NoPublisher),
+    };
+
+    // Register workflow with a closure that deserializes input - malformed input fails
+    // the workflow rather than panicking the workflow task
+    worker.register_wf("order_workflow", move |ctx: temporal_sdk::WfContext| {
+        let delivery_times = delivery_times.clone();
+        async move {
+            let state = order_state_from_args(ctx.get_args())?;
+            order_workflow(ctx, state, delivery_times).await
+        }
+    });
+    worker.register_wf("courier_workflow", move |ctx: temporal_sdk::WfContext| {
+        let timeouts = timeouts.clone();
+        async move {
+            let job = delivery_job_from_args(ctx.get_args())?;
+            courier_workflow(ctx, job, timeouts).await
+        }
+    });
+    worker.register_wf("kitchen_workflow", |ctx: temporal_sdk::WfContext| async move {
+        let queue = kitchen_queue_from_args(ctx.get_args())?;
+        kitchen_workflow(ctx, queue).await
+    });
+
+    // Register activities
+    worker.register_activity("load_sla_policy", move |ctx: ActContext, _: ()| {
+        let sla = sla.clone();
+        async move { load_sla_policy(ctx, sla, ()).await }
+    });
+    let refund_gateway = gateway.clone();
+    worker.register_activity("refund_payment", move |ctx: ActContext, request: RefundRequest| {
+        let gateway = refund_gateway.clone();
+        async move { refund_payment(ctx, gateway, request).await }
+    });
+    let assign_couriers = couriers.clone();
+    worker.register_activity("assign_courier", move |ctx: ActContext, request: AssignCourier| {
+        let pool = assign_couriers.clone();
+        async move { assign_courier(ctx, pool, request).await }
+    });
+    worker.register_activity("release_courier", move |ctx: ActContext, courier_id: String| {
+        let pool = couriers.clone();
+        async move { release_courier(ctx, pool, courier_id).await }
+    });
+    let escalation_notifiers = notifiers.clone();
+    worker.register_activity("escalate_order", move |ctx: ActContext, state: OrderState| {
+        let notifiers = escalation_notifiers.clone();
+        async move { escalate_order(ctx, notifiers, state).await }
+    });
+    let receipt_notifiers = notifiers.clone();
+    worker.register_activity("send_receipt", move |ctx: ActContext, state: OrderState| {
+        let store = receipts.clone();
+        let notifiers = receipt_notifiers.clone();
+        async move { send_receipt(ctx, store, notifiers, state).await }
+    });
+    worker.register_activity("send_text_message", move |ctx: ActContext, state: OrderState| {
+        let notifiers = notifiers.clone();
+        async move { send_text_message(ctx, notifiers, state).await }
+    });
+    worker.register_activity("publish_status_changes", move |ctx: ActContext, changes: Vec<StatusChange>| {
+        let publisher = publisher.clone();
+        async move { publish_status_changes(ctx, publisher, changes).await }
+    });
+    worker.register_activity("find_promotion", move |ctx: ActContext, code: String| {
+        let promotions = promotions.clone();
+        async move { find_promotion(ctx, promotions, code).await }
+    });
+    worker.register_activity("take_payment", move |ctx: ActContext, amount: Amount| {
+        let gateway = gateway.clone();
+        async move { take_payment(ctx, gateway, amount).await }
+    });
+
+    Ok(())
+}
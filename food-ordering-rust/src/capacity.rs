@@ -0,0 +1,227 @@
+/*
+ * Copyright 2025 Simon Emms <simon@simonemms.com>
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, VecDeque};
+use temporal_sdk_core_protos::coresdk::PayloadDeserializeErr;
+use thiserror::Error;
+
+/// ID of the workflow that hands out a kitchen's preparation slots. Orders without a
+/// restaurant are cooked in the central kitchen.
+pub fn kitchen_workflow_id(restaurant_id: Option<&str>) -> String {
+    match restaurant_id {
+        Some(restaurant_id) => format!("kitchen-{}", restaurant_id),
+        None => "kitchen".to_string(),
+    }
+}
+
+/// How many orders a kitchen can prepare at once, and roughly how long each takes
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct KitchenCapacity {
+    pub slots: usize,
+    pub prep_secs: u64,
+}
+
+impl Default for KitchenCapacity {
+    fn default() -> Self {
+        Self {
+            slots: 4,
+            prep_secs: 15 * 60,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Error)]
+pub enum CapacityError {
+    #[error("a kitchen needs at least one preparation slot")]
+    NoSlots,
+}
+
+impl KitchenCapacity {
+    pub fn validate(&self) -> Result<(), CapacityError> {
+        if self.slots == 0 {
+            return Err(CapacityError::NoSlots);
+        }
+        Ok(())
+    }
+}
+
+/// What an order asks of its kitchen's workflow
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum KitchenRequest {
+    /// Queue for a preparation slot
+    Request { order_id: String },
+    /// Give up a slot, or a place in the queue
+    Release { order_id: String },
+    /// Change how much the kitchen can take on, from its worker
+    SetCapacity(KitchenCapacity),
+}
+
+/// Where an order is in the kitchen's queue, as the kitchen last told it
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "state", rename_all = "snake_case")]
+pub enum KitchenSlot {
+    /// A slot has been asked for, and the kitchen has not answered yet
+    Requested,
+    /// Waiting for a slot behind `ahead` other orders
+    Queued { ahead: usize, estimated_wait_secs: u64 },
+    /// The kitchen has room - the order can be prepared
+    Granted,
+}
+
+/// Reasons the kitchen cannot start preparing an order yet
+#[derive(Debug, Clone, PartialEq, Error)]
+pub enum SlotError {
+    #[error("waiting to hear from the kitchen")]
+    Requested,
+    #[error("kitchen is full - about {} minutes to wait, with {ahead} ahead", estimated_wait_secs.div_ceil(60))]
+    Queued { ahead: usize, estimated_wait_secs: u64 },
+}
+
+impl KitchenSlot {
+    /// Whether the order can move to `PREPARING`
+    pub fn check_can_prepare(&self) -> Result<(), SlotError> {
+        match self {
+            KitchenSlot::Requested => Err(SlotError::Requested),
+            KitchenSlot::Queued {
+                ahead,
+                estimated_wait_secs,
+            } => Err(SlotError::Queued {
+                ahead: *ahead,
+                estimated_wait_secs: *estimated_wait_secs,
+            }),
+            KitchenSlot::Granted => Ok(()),
+        }
+    }
+}
+
+/// An order being prepared, and when it was given its slot
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Preparing {
+    pub order_id: String,
+    pub since: DateTime<Utc>,
+}
+
+/// A kitchen's preparation slots and the orders waiting for them, first come first
+/// served. This is the kitchen workflow's state, and carried over when it continues as
+/// new.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct KitchenQueue {
+    pub capacity: KitchenCapacity,
+    #[serde(default)]
+    pub preparing: Vec<Preparing>,
+    #[serde(default)]
+    pub waiting: VecDeque<String>,
+}
+
+/// Reasons the kitchen_workflow input cannot be turned into a `KitchenQueue`
+#[derive(Debug, Error)]
+pub enum KitchenInputError {
+    #[error("kitchen_workflow started without an input payload")]
+    MissingInput,
+    #[error("kitchen_workflow input is not a valid KitchenQueue: {0}")]
+    Malformed(#[from] PayloadDeserializeErr),
+}
+
+impl KitchenQueue {
+    pub fn new(capacity: KitchenCapacity) -> Self {
+        Self {
+            capacity,
+            ..Default::default()
+        }
+    }
+
+    /// Queue an order for a slot, returning whether it has one. Asking again keeps the
+    /// order's place.
+    pub fn request(&mut self, order_id: &str, now: DateTime<Utc>) -> bool {
+        if self.is_preparing(order_id) {
+            return true;
+        }
+        if !self.waiting.iter().any(|o| o == order_id) {
+            self.waiting.push_back(order_id.to_string());
+        }
+
+        self.fill(now).iter().any(|o| o == order_id)
+    }
+
+    /// Free an order's slot, or take it out of the queue, returning the orders given
+    /// a slot in its place
+    pub fn release(&mut self, order_id: &str, now: DateTime<Utc>) -> Vec<String> {
+        self.preparing.retain(|p| p.order_id != order_id);
+        self.waiting.retain(|o| o != order_id);
+        self.fill(now)
+    }
+
+    /// Change the number of slots, returning any orders given one. Orders already
+    /// being prepared keep their slots if there are fewer.
+    pub fn set_capacity(&mut self, capacity: KitchenCapacity, now: DateTime<Utc>) -> Vec<String> {
+        self.capacity = capacity;
+        self.fill(now)
+    }
+
+    pub fn is_preparing(&self, order_id: &str) -> bool {
+        self.preparing.iter().any(|p| p.order_id == order_id)
+    }
+
+    /// Where each waiting order is in the queue, and roughly how long until it gets a
+    /// slot. Every order is assumed to take the kitchen's usual preparation time.
+    pub fn waits(&self, now: DateTime<Utc>) -> Vec<(String, KitchenSlot)> {
+        let prep = chrono::Duration::seconds(self.capacity.prep_secs as i64);
+
+        // When each slot is next free. If the kitchen has shrunk, the first orders to
+        // finish free up slots that are no longer there.
+        let mut finishing: Vec<DateTime<Utc>> = self.preparing.iter().map(|p| (p.since + prep).max(now)).collect();
+        finishing.sort();
+        let excess = finishing.len().saturating_sub(self.capacity.slots);
+        let free = self.capacity.slots.saturating_sub(finishing.len());
+        let mut slots: BinaryHeap<Reverse<DateTime<Utc>>> = finishing
+            .into_iter()
+            .skip(excess)
+            .chain(std::iter::repeat_n(now, free))
+            .map(Reverse)
+            .collect();
+
+        self.waiting
+            .iter()
+            .enumerate()
+            .map(|(ahead, order_id)| {
+                let starts = slots.pop().map(|Reverse(at)| at).unwrap_or(now);
+                slots.push(Reverse(starts + prep));
+                let estimated_wait_secs = (starts - now).num_seconds().max(0) as u64;
+                (order_id.clone(), KitchenSlot::Queued { ahead, estimated_wait_secs })
+            })
+            .collect()
+    }
+
+    /// Give free slots to the orders at the front of the queue, returning them
+    fn fill(&mut self, now: DateTime<Utc>) -> Vec<String> {
+        let mut granted = Vec::new();
+        while self.preparing.len() < self.capacity.slots {
+            let Some(order_id) = self.waiting.pop_front() else {
+                break;
+            };
+            self.preparing.push(Preparing {
+                order_id: order_id.clone(),
+                since: now,
+            });
+            granted.push(order_id);
+        }
+        granted
+    }
+}
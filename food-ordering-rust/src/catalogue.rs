@@ -0,0 +1,100 @@
+/*
+ * Copyright 2025 Simon Emms <simon@simonemms.com>
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use crate::types::{get_sample_products, Product};
+use serde::Deserialize;
+use std::path::Path;
+use thiserror::Error;
+
+/// Where products and their prices come from
+pub trait ProductCatalogue: Send + Sync {
+    fn products(&self) -> Vec<Product>;
+
+    fn find(&self, product_id: u32) -> Option<Product> {
+        self.products().into_iter().find(|p| p.product_id == product_id)
+    }
+}
+
+/// The built-in sample products - used when no catalogue file is configured
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SampleCatalogue;
+
+impl ProductCatalogue for SampleCatalogue {
+    fn products(&self) -> Vec<Product> {
+        get_sample_products()
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum CatalogueError {
+    #[error("unable to read catalogue: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("invalid JSON catalogue: {0}")]
+    Json(#[from] serde_json::Error),
+    #[error("invalid TOML catalogue: {0}")]
+    Toml(#[from] toml::de::Error),
+    #[error("unsupported catalogue format: {0} (expected .json or .toml)")]
+    UnsupportedFormat(String),
+}
+
+#[derive(Deserialize)]
+struct CatalogueFile {
+    products: Vec<Product>,
+}
+
+/// A catalogue loaded once from a JSON or TOML file, eg.
+///
+/// ```toml
+/// [[products]]
+/// product_id = 1
+/// name = "Margherita Pizza"
+/// price = 12.99
+/// available = true
+/// ```
+#[derive(Debug, Clone)]
+pub struct FileCatalogue {
+    products: Vec<Product>,
+}
+
+impl FileCatalogue {
+    /// Load a catalogue, picking the format from the file extension
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, CatalogueError> {
+        let path = path.as_ref();
+        let contents = std::fs::read_to_string(path)?;
+
+        match path.extension().and_then(|e| e.to_str()) {
+            Some("json") => Self::from_json(&contents),
+            Some("toml") => Self::from_toml(&contents),
+            _ => Err(CatalogueError::UnsupportedFormat(path.display().to_string())),
+        }
+    }
+
+    pub fn from_json(contents: &str) -> Result<Self, CatalogueError> {
+        let file: CatalogueFile = serde_json::from_str(contents)?;
+        Ok(Self { products: file.products })
+    }
+
+    pub fn from_toml(contents: &str) -> Result<Self, CatalogueError> {
+        let file: CatalogueFile = toml::from_str(contents)?;
+        Ok(Self { products: file.products })
+    }
+}
+
+impl ProductCatalogue for FileCatalogue {
+    fn products(&self) -> Vec<Product> {
+        self.products.clone()
+    }
+}
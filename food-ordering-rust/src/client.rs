@@ -0,0 +1,419 @@
+/*
+ * Copyright 2025 Simon Emms <simon@simonemms.com>
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use crate::capacity::{kitchen_workflow_id, KitchenCapacity, KitchenQueue, KitchenRequest};
+use crate::constants::{Memos, SearchAttributes, Signals, Updates, ORDER_FOOD_TASK_QUEUE};
+use crate::couriers::courier_workflow_id;
+use crate::pricing::{AppliedDiscount, Amount, PricedOrder};
+use crate::search::OrderFilter;
+use crate::types::{Fulfilment, OrderOutcome, OrderProduct, OrderState, OrderStatus, StatusChange};
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Utc};
+use temporal_client::{WfClientExt, WorkflowClientTrait, WorkflowExecutionResult, WorkflowOptions};
+use temporal_sdk_core_protos::coresdk::{AsJsonPayloadExt, FromJsonPayloadExt};
+use temporal_sdk_core_protos::temporal::api::common::v1::{Payload, Payloads};
+use temporal_sdk_core_protos::temporal::api::enums::v1::{
+    UpdateWorkflowExecutionLifecycleStage, WorkflowExecutionStatus, WorkflowIdConflictPolicy, WorkflowIdReusePolicy,
+};
+use temporal_sdk_core_protos::temporal::api::update::v1::{outcome, WaitPolicy};
+use temporal_sdk_core_protos::temporal::api::workflow::v1::WorkflowExecutionInfo;
+use thiserror::Error;
+use tonic::Code;
+
+/// The workflow refused an update - usually because its validator rejected the input
+#[derive(Debug, Error)]
+#[error("{name} failed: {message}")]
+pub struct UpdateRejected {
+    pub name: String,
+    pub message: String,
+}
+
+/// Temporal has no workflow with the order's ID
+#[derive(Debug, Error)]
+#[error("order {0} not found")]
+pub struct OrderNotFound(pub String);
+
+/// An order that has not finished yet, as its search attributes and memo describe it
+#[derive(Debug, Clone)]
+pub struct RunningOrder {
+    pub workflow_id: String,
+    pub started: Option<DateTime<Utc>>,
+    pub status: Option<OrderStatus>,
+    pub restaurant_id: Option<String>,
+    pub total_in_pence: Option<u64>,
+    /// The order as the workflow last published it - missing for orders started
+    /// before the workflow kept one
+    pub order: Option<OrderState>,
+}
+
+/// An order's status changes, as its `StatusChanges` memo keeps them
+#[derive(Debug, Clone, Default)]
+pub struct OrderStatusChanges {
+    /// Every status the order has been through, oldest first
+    pub changes: Vec<StatusChange>,
+    /// Whether the order's workflow is still running, so may change status again
+    pub running: bool,
+}
+
+/// Start an order workflow with the customer's basket, returning its run ID
+pub async fn start_order(client: &impl WorkflowClientTrait, workflow_id: &str, order: &OrderState) -> Result<String> {
+    let handle = client
+        .start_workflow(
+            vec![order.as_json_payload()?],
+            ORDER_FOOD_TASK_QUEUE.to_string(),
+            workflow_id.to_string(),
+            "order_workflow".to_string(),
+            None,
+            WorkflowOptions {
+                id_reuse_policy: WorkflowIdReusePolicy::AllowDuplicate,
+                ..Default::default()
+            },
+        )
+        .await?;
+
+    Ok(handle.run_id)
+}
+
+/// Wait for an order to finish, returning what happened to it
+pub async fn get_order_outcome(client: &impl WfClientExt, workflow_id: &str) -> Result<OrderOutcome> {
+    let handle = client.get_untyped_workflow_handle(workflow_id, String::new()); // latest run
+    match handle.get_workflow_result(Default::default()).await? {
+        WorkflowExecutionResult::Succeeded(payloads) => {
+            let payload = payloads
+                .first()
+                .ok_or_else(|| anyhow!("order_workflow returned no outcome"))?;
+            Ok(OrderOutcome::from_json_payload(payload)?)
+        }
+        result => Err(anyhow!("order workflow did not complete: {:?}", result)),
+    }
+}
+
+/// Whether an order's workflow is still running. Fails with `OrderNotFound` if there is
+/// no such order.
+pub async fn order_is_running(client: &impl WorkflowClientTrait, workflow_id: &str) -> Result<bool> {
+    let info = describe_order(client, workflow_id).await?;
+    Ok(info.map(|i| i.status) == Some(WorkflowExecutionStatus::Running as i32))
+}
+
+/// The latest run of an order, as Temporal describes it
+async fn describe_order(client: &impl WorkflowClientTrait, workflow_id: &str) -> Result<Option<WorkflowExecutionInfo>> {
+    match client.describe_workflow_execution(workflow_id.to_string(), None).await {
+        Ok(response) => Ok(response.workflow_execution_info),
+        Err(status) if status.code() == Code::NotFound => Err(OrderNotFound(workflow_id.to_string()).into()),
+        Err(status) => Err(status.into()),
+    }
+}
+
+/// Every order that has not finished yet and matches `filter`. Visibility is
+/// eventually consistent, so an order may take a moment to appear or move.
+pub async fn list_running_orders(client: &impl WorkflowClientTrait, filter: &OrderFilter) -> Result<Vec<RunningOrder>> {
+    let query = filter.query();
+
+    let mut orders = Vec::new();
+    let mut next_page_token = Vec::new();
+    loop {
+        let response = client
+            .list_workflow_executions(100, next_page_token, query.clone())
+            .await?;
+
+        orders.extend(response.executions.into_iter().filter_map(|info| {
+            let attributes = info.search_attributes.map(|a| a.indexed_fields).unwrap_or_default();
+            let attribute = |name: &str| attributes.get(name);
+            let memo = info.memo.map(|m| m.fields).unwrap_or_default();
+
+            Some(RunningOrder {
+                workflow_id: info.execution?.workflow_id,
+                started: info
+                    .start_time
+                    .and_then(|t| DateTime::from_timestamp(t.seconds, t.nanos as u32)),
+                status: attribute(SearchAttributes::ORDER_STATUS)
+                    .and_then(|p| String::from_json_payload(p).ok())
+                    .and_then(|s| s.parse().ok()),
+                restaurant_id: attribute(SearchAttributes::RESTAURANT_ID)
+                    .and_then(|p| String::from_json_payload(p).ok()),
+                total_in_pence: attribute(SearchAttributes::ORDER_TOTAL_PENCE)
+                    .and_then(|p| u64::from_json_payload(p).ok()),
+                order: memo
+                    .get(Memos::ORDER)
+                    .and_then(|p| OrderState::from_json_payload(p).ok()),
+            })
+        }));
+
+        if response.next_page_token.is_empty() {
+            break;
+        }
+        next_page_token = response.next_page_token;
+    }
+
+    Ok(orders)
+}
+
+/// Get the state of an order by its workflow ID, as the workflow last published it, with
+/// its ETA brought up to date so an order running late shows it. The Rust SDK cannot
+/// answer queries, so this reads the order's memo rather than asking the workflow. The
+/// order comes back whole, with how to reach the customer and where it is going.
+pub async fn get_order_status(client: &impl WorkflowClientTrait, workflow_id: &str) -> Result<OrderState> {
+    let memo = describe_order(client, workflow_id)
+        .await?
+        .and_then(|info| info.memo)
+        .map(|m| m.fields)
+        .unwrap_or_default();
+
+    let payload = memo
+        .get(Memos::ORDER)
+        .ok_or_else(|| anyhow!("order {} has not published its state", workflow_id))?;
+    let mut order = OrderState::from_json_payload(payload)?;
+    let changes = match memo.get(Memos::STATUS_CHANGES) {
+        Some(payload) => Vec::<StatusChange>::from_json_payload(payload)?,
+        None => Vec::new(),
+    };
+
+    let now = Utc::now();
+    order.revise_eta(changes.last().map_or(now, |c| c.at), now);
+    Ok(order)
+}
+
+/// Get every status change an order has made so far, and whether it is still running.
+/// Like `get_order_status` this reads the order's memo, so following an order does not
+/// write to its history.
+pub async fn get_status_changes(client: &impl WorkflowClientTrait, workflow_id: &str) -> Result<OrderStatusChanges> {
+    let info = describe_order(client, workflow_id).await?.unwrap_or_default();
+    let running = info.status == WorkflowExecutionStatus::Running as i32;
+    let memo = info.memo.map(|m| m.fields).unwrap_or_default();
+
+    let changes = match memo.get(Memos::STATUS_CHANGES) {
+        Some(payload) => Vec::<StatusChange>::from_json_payload(payload)?,
+        None => Vec::new(),
+    };
+    Ok(OrderStatusChanges { changes, running })
+}
+
+/// Add an item to an order's basket, returning the new basket
+pub async fn add_item(
+    client: &impl WorkflowClientTrait,
+    workflow_id: &str,
+    item: OrderProduct,
+) -> Result<Vec<OrderProduct>> {
+    execute_update(client, workflow_id, Updates::ADD_ITEM, item.as_json_payload()?).await
+}
+
+/// Remove an item from an order's basket, returning the new basket
+pub async fn remove_item(
+    client: &impl WorkflowClientTrait,
+    workflow_id: &str,
+    item: OrderProduct,
+) -> Result<Vec<OrderProduct>> {
+    execute_update(client, workflow_id, Updates::REMOVE_ITEM, item.as_json_payload()?).await
+}
+
+/// Choose between delivery and collection before checkout, returning what was set
+pub async fn set_fulfilment(
+    client: &impl WorkflowClientTrait,
+    workflow_id: &str,
+    fulfilment: Fulfilment,
+) -> Result<Fulfilment> {
+    execute_update(client, workflow_id, Updates::SET_FULFILMENT, fulfilment.as_json_payload()?).await
+}
+
+/// Enter a discount code before checkout, returning what it takes off the basket now
+pub async fn apply_discount(
+    client: &impl WorkflowClientTrait,
+    workflow_id: &str,
+    code: &str,
+) -> Result<AppliedDiscount> {
+    execute_update(client, workflow_id, Updates::APPLY_DISCOUNT, code.as_json_payload()?).await
+}
+
+/// Take the discount code off an order before checkout, returning the basket's price
+pub async fn remove_discount(client: &impl WorkflowClientTrait, workflow_id: &str) -> Result<Option<PricedOrder>> {
+    execute_update(client, workflow_id, Updates::REMOVE_DISCOUNT, ().as_json_payload()?).await
+}
+
+/// Cancel an order for the customer, refunding anything paid. Orders the restaurant has
+/// accepted can no longer be cancelled.
+pub async fn cancel_order(client: &impl WorkflowClientTrait, workflow_id: &str) -> Result<()> {
+    execute_update(client, workflow_id, Updates::CANCEL, ().as_json_payload()?).await
+}
+
+/// Submit an order's basket for payment, returning what the customer will be charged.
+/// Payment is taken afterwards - poll the order's status to see it go through.
+pub async fn checkout(client: &impl WorkflowClientTrait, workflow_id: &str) -> Result<Amount> {
+    execute_update(client, workflow_id, Updates::CHECKOUT, ().as_json_payload()?).await
+}
+
+/// Check out an order's basket with the CHECKOUT signal, for callers written against
+/// it. Nothing comes back - a basket that cannot be checked out is left open, so poll
+/// the order's status to see payment go through.
+pub async fn signal_checkout(client: &impl WorkflowClientTrait, workflow_id: &str) -> Result<()> {
+    client
+        .signal_workflow_execution(
+            workflow_id.to_string(),
+            String::new(), // latest run
+            Signals::CHECKOUT.to_string(),
+            None,
+            None,
+        )
+        .await?;
+
+    Ok(())
+}
+
+/// Tell the courier workflow delivering an order that the courier has the food
+pub async fn courier_picked_up(client: &impl WorkflowClientTrait, order_id: &str, courier_id: &str) -> Result<()> {
+    signal_courier(client, order_id, Signals::PICKED_UP, courier_id).await
+}
+
+/// Tell the courier workflow delivering an order that the customer has the food
+pub async fn courier_dropped_off(client: &impl WorkflowClientTrait, order_id: &str, courier_id: &str) -> Result<()> {
+    signal_courier(client, order_id, Signals::DROPPED_OFF, courier_id).await
+}
+
+/// Signal the courier workflow delivering an order on behalf of a courier
+async fn signal_courier(
+    client: &impl WorkflowClientTrait,
+    order_id: &str,
+    name: &str,
+    courier_id: &str,
+) -> Result<()> {
+    client
+        .signal_workflow_execution(
+            courier_workflow_id(order_id),
+            String::new(), // latest run
+            name.to_string(),
+            Some(Payloads {
+                payloads: vec![courier_id.as_json_payload()?],
+            }),
+            None,
+        )
+        .await?;
+
+    Ok(())
+}
+
+/// Start the workflow that hands out a kitchen's preparation slots with `capacity`,
+/// unless it is already running. A running kitchen keeps the capacity it has - use
+/// `set_kitchen_capacity` to change it. Orders without a restaurant are cooked in the
+/// central kitchen.
+pub async fn start_kitchen(
+    client: &impl WorkflowClientTrait,
+    restaurant_id: Option<&str>,
+    capacity: KitchenCapacity,
+) -> Result<()> {
+    capacity.validate()?;
+    client
+        .start_workflow(
+            vec![KitchenQueue::new(capacity).as_json_payload()?],
+            ORDER_FOOD_TASK_QUEUE.to_string(),
+            kitchen_workflow_id(restaurant_id),
+            "kitchen_workflow".to_string(),
+            None,
+            WorkflowOptions {
+                id_conflict_policy: WorkflowIdConflictPolicy::UseExisting,
+                ..Default::default()
+            },
+        )
+        .await?;
+
+    Ok(())
+}
+
+/// Change how many orders a running kitchen can take on at once
+pub async fn set_kitchen_capacity(
+    client: &impl WorkflowClientTrait,
+    restaurant_id: Option<&str>,
+    capacity: KitchenCapacity,
+) -> Result<()> {
+    capacity.validate()?;
+    client
+        .signal_workflow_execution(
+            kitchen_workflow_id(restaurant_id),
+            String::new(), // latest run
+            Signals::KITCHEN.to_string(),
+            Some(Payloads {
+                payloads: vec![KitchenRequest::SetCapacity(capacity).as_json_payload()?],
+            }),
+            None,
+        )
+        .await?;
+
+    Ok(())
+}
+
+/// Get a kitchen's preparation slots and the orders waiting for them, as the kitchen
+/// last published them in its memo
+pub async fn get_kitchen_status(
+    client: &impl WorkflowClientTrait,
+    restaurant_id: Option<&str>,
+) -> Result<KitchenQueue> {
+    let workflow_id = kitchen_workflow_id(restaurant_id);
+    let memo = client
+        .describe_workflow_execution(workflow_id.clone(), None)
+        .await?
+        .workflow_execution_info
+        .and_then(|info| info.memo)
+        .map(|m| m.fields)
+        .unwrap_or_default();
+
+    let payload = memo
+        .get(Memos::KITCHEN_QUEUE)
+        .ok_or_else(|| anyhow!("kitchen {} has not published its queue", workflow_id))?;
+    Ok(KitchenQueue::from_json_payload(payload)?)
+}
+
+/// Move an order to a new status, as the restaurant would
+pub async fn update_order_status(
+    client: &impl WorkflowClientTrait,
+    workflow_id: &str,
+    status: OrderStatus,
+) -> Result<()> {
+    execute_update(client, workflow_id, Updates::UPDATE_STATUS, status.to_string().as_json_payload()?).await
+}
+
+/// Run an update against the latest run of a workflow and wait for its typed result
+pub(crate) async fn execute_update<T: FromJsonPayloadExt>(
+    client: &impl WorkflowClientTrait,
+    workflow_id: &str,
+    name: &str,
+    input: Payload,
+) -> Result<T> {
+    let response = client
+        .update_workflow_execution(
+            workflow_id.to_string(),
+            String::new(), // latest run
+            name.to_string(),
+            WaitPolicy {
+                lifecycle_stage: UpdateWorkflowExecutionLifecycleStage::Completed as i32,
+            },
+            Some(Payloads { payloads: vec![input] }),
+        )
+        .await?;
+
+    match response.outcome.and_then(|o| o.value) {
+        Some(outcome::Value::Success(payloads)) => {
+            let payload = payloads
+                .payloads
+                .first()
+                .ok_or_else(|| anyhow!("{} returned no payload", name))?;
+            Ok(T::from_json_payload(payload)?)
+        }
+        Some(outcome::Value::Failure(failure)) => Err(UpdateRejected {
+            name: name.to_string(),
+            message: failure.message,
+        }
+        .into()),
+        None => Err(anyhow!("{} did not complete", name)),
+    }
+}
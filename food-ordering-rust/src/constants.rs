@@ -16,19 +16,67 @@
 
 pub const ORDER_FOOD_TASK_QUEUE: &str = "order-food";
 
-pub struct Queries;
-impl Queries {
-    pub const GET_STATUS: &str = "GET_STATUS";
+/// Task queue for the activities a restaurant runs itself - pricing its menu and
+/// printing kitchen tickets
+pub fn restaurant_task_queue(restaurant_id: &str) -> String {
+    format!("{}-{}", ORDER_FOOD_TASK_QUEUE, restaurant_id)
 }
 
 pub struct Signals;
 impl Signals {
+    /// Checks the basket out as the CHECKOUT update does, for callers that only signal.
+    /// A basket that cannot be checked out is left open, and the signal ignored.
     pub const CHECKOUT: &str = "CHECKOUT";
+    pub const DELIVERY_PROGRESS: &str = "DELIVERY_PROGRESS";
+    pub const PICKED_UP: &str = "PICKED_UP";
+    pub const DROPPED_OFF: &str = "DROPPED_OFF";
+    /// Sent to a kitchen workflow with a `KitchenRequest`
+    pub const KITCHEN: &str = "KITCHEN";
+    /// Sent by a kitchen workflow to an order with its `KitchenSlot`
+    pub const KITCHEN_SLOT: &str = "KITCHEN_SLOT";
 }
 
 pub struct Updates;
 impl Updates {
     pub const ADD_ITEM: &str = "ADD_ITEM";
     pub const REMOVE_ITEM: &str = "REMOVE_ITEM";
+    pub const SET_FULFILMENT: &str = "SET_FULFILMENT";
     pub const UPDATE_STATUS: &str = "UPDATE_STATUS";
+    pub const APPLY_DISCOUNT: &str = "APPLY_DISCOUNT";
+    pub const REMOVE_DISCOUNT: &str = "REMOVE_DISCOUNT";
+    pub const CANCEL: &str = "CANCEL";
+    pub const CHECKOUT: &str = "CHECKOUT";
+}
+
+/// Search attributes order_workflow keeps up to date. They must be registered with the
+/// namespace before the worker runs.
+pub struct SearchAttributes;
+impl SearchAttributes {
+    pub const ORDER_STATUS: &str = "OrderStatus";
+    pub const RESTAURANT_ID: &str = "RestaurantId";
+    pub const ORDER_TOTAL_PENCE: &str = "OrderTotalPence";
+    pub const CUSTOMER_EMAIL_HASH: &str = "CustomerEmailHash";
+
+    /// Every search attribute with the type it is registered as
+    pub const ALL: [(&str, &str); 4] = [
+        (Self::ORDER_STATUS, "Keyword"),
+        (Self::RESTAURANT_ID, "Keyword"),
+        (Self::ORDER_TOTAL_PENCE, "Int"),
+        (Self::CUSTOMER_EMAIL_HASH, "Keyword"),
+    ];
+}
+
+/// Memo fields the workflows keep up to date. Unlike search attributes they are not
+/// indexed, but come back with every listed order, and are read by describing the
+/// workflow rather than asking it.
+pub struct Memos;
+impl Memos {
+    /// The order as it stands. Memos are not indexed, so unlike search attributes this
+    /// can hold the customer's contact details for the restaurant - the API leaves them
+    /// out of what it shows
+    pub const ORDER: &str = "Order";
+    /// Every status the order has been through, oldest first
+    pub const STATUS_CHANGES: &str = "StatusChanges";
+    /// A kitchen_workflow's preparation slots and the orders waiting for them
+    pub const KITCHEN_QUEUE: &str = "KitchenQueue";
 }
@@ -0,0 +1,232 @@
+/*
+ * Copyright 2025 Simon Emms <simon@simonemms.com>
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use crate::types::Address;
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::sync::Mutex;
+use std::time::Duration;
+use temporal_sdk::ActivityError;
+use temporal_sdk_core_protos::coresdk::PayloadDeserializeErr;
+use thiserror::Error;
+
+/// ID of the courier workflow delivering an order - couriers signal it directly
+pub fn courier_workflow_id(order_id: &str) -> String {
+    format!("{}-courier", order_id)
+}
+
+/// A ready order to take to the customer
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DeliveryJob {
+    /// Workflow ID of the order, which is kept up to date as the delivery goes on
+    pub order_id: String,
+    pub address: Address,
+}
+
+/// Ask the pool for a courier, preferring any not in `avoid`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AssignCourier {
+    pub job: DeliveryJob,
+    pub avoid: Vec<String>,
+}
+
+/// How long a courier has at each stage before the delivery is offered to another, and
+/// how long, and how many times, a delivery is offered before it is given up on
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DeliveryTimeouts {
+    pub pickup_secs: u64,
+    pub drop_off_secs: u64,
+    /// How long the pool has to find each courier
+    pub assign_secs: u64,
+    /// How many late couriers are replaced before the delivery fails
+    pub max_reassignments: usize,
+}
+
+impl DeliveryTimeouts {
+    pub fn pickup(&self) -> Duration {
+        Duration::from_secs(self.pickup_secs)
+    }
+
+    pub fn drop_off(&self) -> Duration {
+        Duration::from_secs(self.drop_off_secs)
+    }
+
+    pub fn assign(&self) -> Duration {
+        Duration::from_secs(self.assign_secs)
+    }
+
+    /// Check the delivery can be offered to another courier after those in `timed_out`
+    pub fn check_can_reassign(&self, timed_out: &[String]) -> Result<(), DeliveryError> {
+        if timed_out.len() > self.max_reassignments {
+            return Err(DeliveryError::TooManyReassignments {
+                timed_out: timed_out.to_vec(),
+                max: self.max_reassignments,
+            });
+        }
+
+        Ok(())
+    }
+}
+
+impl Default for DeliveryTimeouts {
+    fn default() -> Self {
+        Self {
+            pickup_secs: 15 * 60,
+            drop_off_secs: 45 * 60,
+            assign_secs: 30 * 60,
+            max_reassignments: 3,
+        }
+    }
+}
+
+/// How far a courier has got, sent by the courier workflow to the order
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DeliveryStage {
+    Assigned,
+    PickedUp,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DeliveryProgress {
+    pub courier_id: String,
+    pub stage: DeliveryStage,
+}
+
+/// What the courier workflow reports back once the food is dropped off
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DeliveryReport {
+    /// The courier who delivered the order
+    pub courier_id: String,
+    /// Couriers who ran out of time, in the order they were replaced
+    pub timed_out: Vec<String>,
+}
+
+/// Reasons the courier pool could not help
+#[derive(Debug, Clone, PartialEq, Error)]
+pub enum CourierError {
+    #[error("no couriers available")]
+    NoneAvailable,
+    #[error("courier pool unavailable: {0}")]
+    Unavailable(String),
+}
+
+impl From<CourierError> for ActivityError {
+    fn from(e: CourierError) -> Self {
+        // Couriers come back on shift, so it is always worth asking again
+        ActivityError::Retryable {
+            source: e.into(),
+            explicit_delay: None,
+        }
+    }
+}
+
+/// Reasons the courier workflow gives up on a delivery, failing so its order is refunded
+#[derive(Debug, Error)]
+pub enum DeliveryError {
+    #[error("no courier could be assigned: {0}")]
+    NotAssigned(String),
+    #[error("gave up after {} couriers ran out of time ({max} reassignments allowed)", timed_out.len())]
+    TooManyReassignments { timed_out: Vec<String>, max: usize },
+}
+
+/// Reasons the courier_workflow input cannot be turned into a `DeliveryJob`
+#[derive(Debug, Error)]
+pub enum DeliveryInputError {
+    #[error("courier_workflow started without an input payload")]
+    MissingInput,
+    #[error("courier_workflow input is not a valid DeliveryJob: {0}")]
+    Malformed(#[from] PayloadDeserializeErr),
+}
+
+/// Hands out couriers for deliveries. Assigning carries an idempotency key - repeating
+/// a call with the same key must return the original courier.
+#[async_trait]
+pub trait CourierPool: Send + Sync {
+    async fn assign(&self, idempotency_key: &str, request: &AssignCourier) -> Result<String, CourierError>;
+
+    /// Free a courier for other deliveries. Releasing a free courier does nothing.
+    async fn release(&self, courier_id: &str) -> Result<(), CourierError>;
+}
+
+#[derive(Debug, Default)]
+struct Roster {
+    busy: HashSet<String>,
+    by_key: HashMap<String, String>,
+    assignments: Vec<String>,
+}
+
+/// A fixed set of in-memory couriers, assigned in the order given
+#[derive(Debug, Default)]
+pub struct FakeCourierPool {
+    couriers: Vec<String>,
+    roster: Mutex<Roster>,
+}
+
+impl FakeCourierPool {
+    pub fn new(couriers: impl IntoIterator<Item = impl Into<String>>) -> Self {
+        Self {
+            couriers: couriers.into_iter().map(Into::into).collect(),
+            roster: Mutex::default(),
+        }
+    }
+
+    /// Every courier assigned, in order
+    pub fn assignments(&self) -> Vec<String> {
+        self.roster.lock().unwrap().assignments.clone()
+    }
+
+    /// Couriers currently out on a delivery
+    pub fn busy(&self) -> Vec<String> {
+        let roster = self.roster.lock().unwrap();
+        self.couriers
+            .iter()
+            .filter(|c| roster.busy.contains(*c))
+            .cloned()
+            .collect()
+    }
+}
+
+#[async_trait]
+impl CourierPool for FakeCourierPool {
+    async fn assign(&self, idempotency_key: &str, request: &AssignCourier) -> Result<String, CourierError> {
+        let mut roster = self.roster.lock().unwrap();
+        if let Some(courier_id) = roster.by_key.get(idempotency_key) {
+            return Ok(courier_id.clone());
+        }
+
+        // Couriers to avoid are only used if nobody else is free
+        let free: Vec<&String> = self.couriers.iter().filter(|c| !roster.busy.contains(*c)).collect();
+        let courier_id = free
+            .iter()
+            .copied()
+            .find(|c| !request.avoid.contains(c))
+            .or_else(|| free.first().copied())
+            .cloned()
+            .ok_or(CourierError::NoneAvailable)?;
+
+        roster.busy.insert(courier_id.clone());
+        roster.by_key.insert(idempotency_key.to_string(), courier_id.clone());
+        roster.assignments.push(courier_id.clone());
+        Ok(courier_id)
+    }
+
+    async fn release(&self, courier_id: &str) -> Result<(), CourierError> {
+        self.roster.lock().unwrap().busy.remove(courier_id);
+        Ok(())
+    }
+}
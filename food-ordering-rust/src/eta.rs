@@ -0,0 +1,166 @@
+/*
+ * Copyright 2025 Simon Emms <simon@simonemms.com>
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use crate::capacity::KitchenSlot;
+use crate::pricing::PricedOrder;
+use crate::types::{is_valid_post_code, OrderStatus};
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::Path;
+use thiserror::Error;
+
+/// How long a product takes to prepare if the catalogue does not say
+pub const DEFAULT_PREP_MINUTES: u32 = 15;
+
+/// How long the kitchen needs for an order. Everything is cooked alongside everything
+/// else, so this is the slowest product's preparation time.
+pub fn prep_minutes(order: &PricedOrder) -> u32 {
+    order
+        .line_items
+        .iter()
+        .map(|l| l.prep_minutes.unwrap_or(DEFAULT_PREP_MINUTES))
+        .max()
+        .unwrap_or(0)
+}
+
+/// How long a courier takes to get from the kitchen to a post code, eg.
+///
+/// ```json
+/// { "default_minutes": 30, "post_codes": { "AB1": 10, "AB": 20 } }
+/// ```
+///
+/// A post code's district ("AB1") is looked up first, then its area ("AB").
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DeliveryTimes {
+    /// For post codes not in the table
+    pub default_minutes: u32,
+    #[serde(default)]
+    pub post_codes: BTreeMap<String, u32>,
+}
+
+impl Default for DeliveryTimes {
+    fn default() -> Self {
+        Self {
+            default_minutes: 30,
+            post_codes: BTreeMap::new(),
+        }
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum DeliveryTimesError {
+    #[error("unable to read delivery times: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("invalid JSON delivery times: {0}")]
+    Json(#[from] serde_json::Error),
+}
+
+impl DeliveryTimes {
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, DeliveryTimesError> {
+        Self::from_json(&std::fs::read_to_string(path)?)
+    }
+
+    pub fn from_json(contents: &str) -> Result<Self, DeliveryTimesError> {
+        Ok(serde_json::from_str(contents)?)
+    }
+
+    /// Minutes from the kitchen to `post_code`. Case and spacing are ignored, and
+    /// anything that is not a valid post code takes the default time.
+    pub fn minutes_to(&self, post_code: &str) -> u32 {
+        if !is_valid_post_code(post_code) {
+            return self.default_minutes;
+        }
+
+        // Valid post codes are ASCII, so the inward code is the last three bytes
+        let code: String = post_code
+            .chars()
+            .filter(|c| !c.is_whitespace())
+            .map(|c| c.to_ascii_uppercase())
+            .collect();
+        let district = &code[..code.len() - 3];
+        let area: String = district.chars().take_while(|c| c.is_ascii_alphabetic()).collect();
+
+        [district, area.as_str()]
+            .iter()
+            .filter(|prefix| !prefix.is_empty())
+            .find_map(|prefix| {
+                self.post_codes
+                    .iter()
+                    .find(|(key, _)| key.eq_ignore_ascii_case(prefix))
+                    .map(|(_, minutes)| *minutes)
+            })
+            .unwrap_or(self.default_minutes)
+    }
+}
+
+/// When a paid-for order should reach the customer, or be ready to collect
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Eta {
+    pub at: DateTime<Utc>,
+    /// Minutes from the kitchen to the customer, fixed at checkout - none for collection
+    pub delivery_minutes: u32,
+}
+
+impl Eta {
+    pub fn new(delivery_minutes: u32) -> Self {
+        Self {
+            at: DateTime::default(),
+            delivery_minutes,
+        }
+    }
+
+    /// Work out `at` again for an order that has been `status` since `since`. Orders
+    /// waiting for the kitchen add the kitchen's estimate of their wait, and anything
+    /// running late is assumed to be about to finish. Finished orders keep their last ETA.
+    pub fn revise(
+        &mut self,
+        prep_minutes: u32,
+        status: &OrderStatus,
+        kitchen_slot: Option<&KitchenSlot>,
+        since: DateTime<Utc>,
+        now: DateTime<Utc>,
+    ) {
+        let prep = Duration::minutes(i64::from(prep_minutes));
+        let delivery = Duration::minutes(i64::from(self.delivery_minutes));
+
+        let ready = match status {
+            OrderStatus::Default | OrderStatus::Pending | OrderStatus::Accepted => {
+                let wait = match kitchen_slot {
+                    Some(KitchenSlot::Queued {
+                        estimated_wait_secs, ..
+                    }) => Duration::seconds(*estimated_wait_secs as i64),
+                    _ => Duration::zero(),
+                };
+                now + wait + prep
+            }
+            OrderStatus::Preparing => (since + prep).max(now),
+            // Waiting for a courier to pick it up
+            OrderStatus::Ready => now,
+            OrderStatus::OutForDelivery => {
+                self.at = (since + delivery).max(now);
+                return;
+            }
+            OrderStatus::Completed
+            | OrderStatus::Rejected
+            | OrderStatus::PaymentDeclined
+            | OrderStatus::Cancelled
+            | OrderStatus::DeliveryFailed => return,
+        };
+
+        self.at = ready + delivery;
+    }
+}
@@ -0,0 +1,158 @@
+/*
+ * Copyright 2025 Simon Emms <simon@simonemms.com>
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use crate::types::OrderState;
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::sync::Mutex;
+use temporal_sdk::ActivityError;
+use thiserror::Error;
+
+/// What the kitchen cooks an accepted order from
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct KitchenTicket {
+    pub order_id: String,
+    pub restaurant_id: Option<String>,
+    pub collection: bool,
+    pub lines: Vec<TicketLine>,
+    /// The customer cancelled the order - this tells the kitchen not to cook it
+    #[serde(default)]
+    pub cancelled: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TicketLine {
+    pub product_id: u32,
+    pub name: String,
+    pub quantity: u32,
+}
+
+impl KitchenTicket {
+    /// The ticket for an order, naming products from its pricing
+    pub fn for_order(order_id: &str, order: &OrderState) -> Self {
+        let lines = order
+            .products
+            .iter()
+            .map(|product| {
+                let name = order
+                    .pricing
+                    .as_ref()
+                    .and_then(|p| p.line_items.iter().find(|l| l.product_id == product.product_id))
+                    .map(|l| l.name.clone())
+                    .unwrap_or_else(|| format!("Product {}", product.product_id));
+                TicketLine {
+                    product_id: product.product_id,
+                    name,
+                    quantity: product.quantity,
+                }
+            })
+            .collect();
+
+        Self {
+            order_id: order_id.to_string(),
+            restaurant_id: order.restaurant_id.clone(),
+            collection: order.collection,
+            lines,
+            cancelled: false,
+        }
+    }
+
+    /// The notice printed when the customer cancels an order the restaurant has been
+    /// sent - any order that was paid for
+    pub fn cancellation(order_id: &str, order: &OrderState) -> Self {
+        Self {
+            cancelled: true,
+            ..Self::for_order(order_id, order)
+        }
+    }
+
+    /// The ticket as printed
+    pub fn render(&self) -> String {
+        let mut ticket = format!(
+            "Order {} ({}){}\n",
+            self.order_id,
+            if self.collection { "collection" } else { "delivery" },
+            if self.cancelled { " CANCELLED - do not cook" } else { "" }
+        );
+        for line in &self.lines {
+            ticket.push_str(&format!("{} x {}\n", line.quantity, line.name));
+        }
+        ticket
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum PrinterError {
+    #[error("kitchen printer unavailable: {0}")]
+    Unavailable(String),
+}
+
+impl From<PrinterError> for ActivityError {
+    fn from(e: PrinterError) -> Self {
+        // Printers come back once the paper is changed
+        ActivityError::Retryable {
+            source: e.into(),
+            explicit_delay: None,
+        }
+    }
+}
+
+/// Prints tickets in a restaurant's kitchen. Printing carries an idempotency key -
+/// repeating a call with the same key must not print the ticket again.
+#[async_trait]
+pub trait TicketPrinter: Send + Sync {
+    async fn print(&self, idempotency_key: &str, ticket: &KitchenTicket) -> Result<(), PrinterError>;
+}
+
+/// Print tickets to stdout - for development
+#[derive(Debug, Default)]
+pub struct StdoutPrinter;
+
+#[async_trait]
+impl TicketPrinter for StdoutPrinter {
+    async fn print(&self, _idempotency_key: &str, ticket: &KitchenTicket) -> Result<(), PrinterError> {
+        println!("{}", ticket.render());
+        Ok(())
+    }
+}
+
+/// Keep printed tickets in memory so tests can assert on them
+#[derive(Debug, Default)]
+pub struct MemoryPrinter {
+    printed: Mutex<(HashSet<String>, Vec<KitchenTicket>)>,
+}
+
+impl MemoryPrinter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn printed(&self) -> Vec<KitchenTicket> {
+        self.printed.lock().unwrap().1.clone()
+    }
+}
+
+#[async_trait]
+impl TicketPrinter for MemoryPrinter {
+    async fn print(&self, idempotency_key: &str, ticket: &KitchenTicket) -> Result<(), PrinterError> {
+        let mut printed = self.printed.lock().unwrap();
+        if printed.0.insert(idempotency_key.to_string()) {
+            printed.1.push(ticket.clone());
+        }
+        Ok(())
+    }
+}
@@ -15,12 +15,42 @@
  */
 
 pub mod activities;
+pub mod api;
+pub mod capacity;
+pub mod catalogue;
+pub mod client;
 pub mod constants;
+pub mod couriers;
+pub mod eta;
+pub mod kitchen;
+pub mod notifications;
+pub mod payments;
+pub mod pricing;
+pub mod promotions;
+pub mod receipts;
+pub mod search;
+pub mod sla;
+pub mod streaming;
 pub mod types;
 pub mod workflows;
 
 
 pub use activities::*;
+pub use api::*;
+pub use capacity::*;
+pub use catalogue::*;
+pub use client::*;
 pub use constants::*;
+pub use couriers::*;
+pub use eta::*;
+pub use kitchen::*;
+pub use notifications::*;
+pub use payments::*;
+pub use pricing::*;
+pub use promotions::*;
+pub use receipts::*;
+pub use search::*;
+pub use sla::*;
+pub use streaming::*;
 pub use types::*;
 pub use workflows::*;
@@ -0,0 +1,470 @@
+/*
+ * Copyright 2025 Simon Emms <simon@simonemms.com>
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use crate::types::{OrderState, OrderStatus};
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use std::io::Write;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use temporal_sdk::ActivityError;
+use thiserror::Error;
+
+/// Longest body sent as a single text message
+pub const SMS_MAX_LENGTH: usize = 160;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Channel {
+    Sms,
+    Email,
+    Webhook,
+}
+
+impl std::fmt::Display for Channel {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Channel::Sms => write!(f, "sms"),
+            Channel::Email => write!(f, "email"),
+            Channel::Webhook => write!(f, "webhook"),
+        }
+    }
+}
+
+/// Why a notification was sent
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum NotificationKind {
+    /// The order moved to a new status
+    #[default]
+    StatusUpdate,
+    /// The order has been in its status for longer than the SLA allows
+    Escalation,
+    /// A link to the completed order's receipt
+    Receipt,
+}
+
+/// A message sent about an order. The ID is stable across activity retries, so
+/// receivers can drop duplicates.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Notification {
+    pub notification_id: String,
+    pub channel: Channel,
+    #[serde(default)]
+    pub kind: NotificationKind,
+    pub recipient: String,
+    pub status: OrderStatus,
+    pub subject: String,
+    pub body: String,
+}
+
+#[derive(Debug, Error)]
+pub enum NotificationError {
+    #[error("unable to write notification: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("unable to encode notification: {0}")]
+    Json(#[from] serde_json::Error),
+    #[error("webhook request failed: {0}")]
+    Webhook(#[from] reqwest::Error),
+}
+
+impl NotificationError {
+    /// Whether sending again may work. A webhook that refuses the request, other than
+    /// to time it out or slow us down, will refuse it every time.
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            NotificationError::Webhook(e) => match e.status() {
+                Some(status) if status.is_client_error() => {
+                    status == reqwest::StatusCode::REQUEST_TIMEOUT || status == reqwest::StatusCode::TOO_MANY_REQUESTS
+                }
+                _ => true,
+            },
+            _ => true,
+        }
+    }
+}
+
+impl From<NotificationError> for ActivityError {
+    fn from(e: NotificationError) -> Self {
+        if e.is_retryable() {
+            ActivityError::Retryable {
+                source: e.into(),
+                explicit_delay: None,
+            }
+        } else {
+            ActivityError::NonRetryable(e.into())
+        }
+    }
+}
+
+/// The message for an order status. `{total}`, `{reason}`, `{post_code}` and
+/// `{receipt_url}` are filled in from the order, and orders still on their way are told
+/// when to expect them.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MessageTemplate {
+    pub kind: NotificationKind,
+    pub subject: &'static str,
+    pub body: &'static str,
+}
+
+impl MessageTemplate {
+    pub fn for_status(status: &OrderStatus) -> Self {
+        let (subject, body) = match status {
+            OrderStatus::Default => ("Your basket", "Your basket comes to {total}."),
+            OrderStatus::Pending => (
+                "Order received",
+                "Thanks for your order of {total}. We've sent it to the restaurant.",
+            ),
+            OrderStatus::Accepted => ("Order accepted", "The restaurant has accepted your order."),
+            OrderStatus::Preparing => ("Order being prepared", "The restaurant is preparing your food."),
+            OrderStatus::Ready => ("Order ready", "Your food is ready."),
+            OrderStatus::OutForDelivery => ("Out for delivery", "Your food is on its way to {post_code}."),
+            OrderStatus::Completed => ("Order complete", "Your order is complete. Enjoy your meal!"),
+            OrderStatus::Rejected => (
+                "Order rejected",
+                "Sorry, the restaurant could not take your order. {total} has been refunded.",
+            ),
+            OrderStatus::PaymentDeclined => (
+                "Payment declined",
+                "We could not take payment for your order: {reason}",
+            ),
+            OrderStatus::Cancelled => (
+                "Order cancelled",
+                "Your order has been cancelled as you asked. Anything you paid has been refunded.",
+            ),
+            OrderStatus::DeliveryFailed => (
+                "Delivery failed",
+                "Sorry, we could not deliver your order: {reason}. Everything you paid has been refunded.",
+            ),
+        };
+
+        Self {
+            kind: NotificationKind::StatusUpdate,
+            subject,
+            body,
+        }
+    }
+
+    /// The message for an order that has been in its status for too long
+    pub fn escalation_for(status: &OrderStatus) -> Self {
+        let body = match status {
+            OrderStatus::Pending => "The restaurant hasn't accepted your order yet. We're chasing it up.",
+            OrderStatus::Accepted => "The restaurant hasn't started on your order yet. We're chasing it up.",
+            OrderStatus::Ready => "Your food is ready but hasn't reached you yet. We're chasing it up.",
+            OrderStatus::OutForDelivery => "Your delivery is running late. We're chasing it up.",
+            _ => "Your order is taking longer than expected. We're chasing it up.",
+        };
+
+        Self {
+            kind: NotificationKind::Escalation,
+            subject: "Order delayed",
+            body,
+        }
+    }
+
+    /// The message linking a completed order to its receipt
+    pub fn receipt() -> Self {
+        Self {
+            kind: NotificationKind::Receipt,
+            subject: "Your receipt",
+            body: "Thanks for your order of {total}. Your receipt is at {receipt_url}",
+        }
+    }
+
+    /// Fill in the template, with the ETA of orders still on their way, returning the
+    /// subject and body
+    pub fn render(&self, state: &OrderState) -> (String, String) {
+        let total = state.pricing.as_ref().map(|p| p.total()).unwrap_or_default();
+        let reason = state
+            .decline_reason
+            .as_deref()
+            .or(state.delivery_failure.as_deref())
+            .unwrap_or("unknown reason");
+        let post_code = state
+            .delivery_address
+            .as_ref()
+            .map(|a| a.post_code.as_str())
+            .unwrap_or("you");
+        let receipt_url = state.receipt.as_ref().map_or("", |r| r.html_url.as_str());
+
+        let fill = |template: &str| {
+            template
+                .replace("{total}", &total.to_string())
+                .replace("{reason}", reason)
+                .replace("{post_code}", post_code)
+                .replace("{receipt_url}", receipt_url)
+        };
+
+        let mut body = fill(self.body);
+        if let Some(expected) = expected_at(state) {
+            body.push(' ');
+            body.push_str(&expected);
+        }
+
+        (fill(self.subject), body)
+    }
+}
+
+/// When the customer can expect an unfinished order, if it has an ETA. Collection
+/// orders that are ready have nothing left to wait for.
+fn expected_at(state: &OrderState) -> Option<String> {
+    let eta = state.eta.as_ref()?;
+    let at = eta.at.format("%H:%M UTC");
+    match (&state.status, state.collection) {
+        (status, _) if status.is_terminal() => None,
+        (OrderStatus::Ready, true) => None,
+        (_, true) => Some(format!("It should be ready to collect at about {}.", at)),
+        (_, false) => Some(format!("It should be with you at about {}.", at)),
+    }
+}
+
+/// Somewhere to send a rendered notification. Stands in for an SMS or email provider.
+#[async_trait]
+pub trait NotificationSink: Send + Sync {
+    async fn deliver(&self, notification: &Notification) -> Result<(), NotificationError>;
+}
+
+/// Print notifications as JSON lines - for development
+#[derive(Debug, Default)]
+pub struct StdoutSink;
+
+#[async_trait]
+impl NotificationSink for StdoutSink {
+    async fn deliver(&self, notification: &Notification) -> Result<(), NotificationError> {
+        println!("{}", serde_json::to_string(notification)?);
+        Ok(())
+    }
+}
+
+/// Append notifications to a file as JSON lines - for development
+#[derive(Debug)]
+pub struct FileSink {
+    path: PathBuf,
+    lock: Mutex<()>,
+}
+
+impl FileSink {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self {
+            path: path.into(),
+            lock: Mutex::new(()),
+        }
+    }
+}
+
+#[async_trait]
+impl NotificationSink for FileSink {
+    async fn deliver(&self, notification: &Notification) -> Result<(), NotificationError> {
+        let line = serde_json::to_string(notification)?;
+
+        // Keep concurrent activities from interleaving lines
+        let _guard = self.lock.lock().unwrap();
+        let mut file = std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?;
+        writeln!(file, "{}", line)?;
+
+        Ok(())
+    }
+}
+
+/// Keep notifications in memory so tests can assert on them
+#[derive(Debug, Default)]
+pub struct MemorySink {
+    sent: Mutex<Vec<Notification>>,
+}
+
+impl MemorySink {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn sent(&self) -> Vec<Notification> {
+        self.sent.lock().unwrap().clone()
+    }
+}
+
+#[async_trait]
+impl NotificationSink for MemorySink {
+    async fn deliver(&self, notification: &Notification) -> Result<(), NotificationError> {
+        self.sent.lock().unwrap().push(notification.clone());
+        Ok(())
+    }
+}
+
+/// A way of telling the customer their order has changed
+#[async_trait]
+pub trait Notifier: Send + Sync {
+    fn channel(&self) -> Channel;
+
+    /// Who this channel sends to for the order - `None` skips the channel
+    fn recipient(&self, state: &OrderState) -> Option<String>;
+
+    /// Build the message for the order from a template
+    fn render(&self, notification_id: String, state: &OrderState, template: &MessageTemplate) -> Option<Notification> {
+        let recipient = self.recipient(state)?;
+        let (subject, body) = template.render(state);
+
+        Some(Notification {
+            notification_id,
+            channel: self.channel(),
+            kind: template.kind,
+            recipient,
+            status: state.status.clone(),
+            subject,
+            body,
+        })
+    }
+
+    async fn send(&self, notification: &Notification) -> Result<(), NotificationError>;
+}
+
+/// Text the customer's phone, if they gave one
+pub struct SmsNotifier {
+    sink: Arc<dyn NotificationSink>,
+}
+
+impl SmsNotifier {
+    pub fn new(sink: Arc<dyn NotificationSink>) -> Self {
+        Self { sink }
+    }
+}
+
+#[async_trait]
+impl Notifier for SmsNotifier {
+    fn channel(&self) -> Channel {
+        Channel::Sms
+    }
+
+    fn recipient(&self, state: &OrderState) -> Option<String> {
+        state.phone.clone().filter(|p| !p.is_empty())
+    }
+
+    /// Texts have no subject, so it leads the body, which is cut to fit one message
+    fn render(&self, notification_id: String, state: &OrderState, template: &MessageTemplate) -> Option<Notification> {
+        let recipient = self.recipient(state)?;
+        let (subject, body) = template.render(state);
+
+        Some(Notification {
+            notification_id,
+            channel: Channel::Sms,
+            kind: template.kind,
+            recipient,
+            status: state.status.clone(),
+            subject: String::new(),
+            body: format!("{}: {}", subject, body).chars().take(SMS_MAX_LENGTH).collect(),
+        })
+    }
+
+    async fn send(&self, notification: &Notification) -> Result<(), NotificationError> {
+        self.sink.deliver(notification).await
+    }
+}
+
+/// Email the address on the order
+pub struct EmailNotifier {
+    sink: Arc<dyn NotificationSink>,
+}
+
+impl EmailNotifier {
+    pub fn new(sink: Arc<dyn NotificationSink>) -> Self {
+        Self { sink }
+    }
+}
+
+#[async_trait]
+impl Notifier for EmailNotifier {
+    fn channel(&self) -> Channel {
+        Channel::Email
+    }
+
+    fn recipient(&self, state: &OrderState) -> Option<String> {
+        Some(state.email.clone()).filter(|e| !e.is_empty())
+    }
+
+    async fn send(&self, notification: &Notification) -> Result<(), NotificationError> {
+        self.sink.deliver(notification).await
+    }
+}
+
+/// POST every notification as JSON to a URL, such as a restaurant's order system
+pub struct WebhookNotifier {
+    url: String,
+    client: reqwest::Client,
+}
+
+impl WebhookNotifier {
+    pub fn new(url: impl Into<String>) -> Self {
+        Self {
+            url: url.into(),
+            client: reqwest::Client::new(),
+        }
+    }
+}
+
+#[async_trait]
+impl Notifier for WebhookNotifier {
+    fn channel(&self) -> Channel {
+        Channel::Webhook
+    }
+
+    fn recipient(&self, _: &OrderState) -> Option<String> {
+        Some(self.url.clone())
+    }
+
+    async fn send(&self, notification: &Notification) -> Result<(), NotificationError> {
+        self.client
+            .post(&self.url)
+            .header("Idempotency-Key", &notification.notification_id)
+            .json(notification)
+            .send()
+            .await?
+            .error_for_status()?;
+
+        Ok(())
+    }
+}
+
+/// Send the order's current status down every channel that has a recipient. IDs are
+/// `{id_prefix}/{channel}`.
+pub async fn notify_all(
+    notifiers: &[Arc<dyn Notifier>],
+    id_prefix: &str,
+    state: &OrderState,
+) -> Result<Vec<Notification>, NotificationError> {
+    send_all(notifiers, id_prefix, state, &MessageTemplate::for_status(&state.status)).await
+}
+
+/// Send a message from `template` down every channel that has a recipient
+pub async fn send_all(
+    notifiers: &[Arc<dyn Notifier>],
+    id_prefix: &str,
+    state: &OrderState,
+    template: &MessageTemplate,
+) -> Result<Vec<Notification>, NotificationError> {
+    let mut sent = Vec::new();
+
+    for notifier in notifiers {
+        let id = format!("{}/{}", id_prefix, notifier.channel());
+        if let Some(notification) = notifier.render(id, state, template) {
+            notifier.send(&notification).await?;
+            sent.push(notification);
+        }
+    }
+
+    Ok(sent)
+}
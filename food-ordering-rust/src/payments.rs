@@ -0,0 +1,370 @@
+/*
+ * Copyright 2025 Simon Emms <simon@simonemms.com>
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use crate::pricing::{Amount, PricingError};
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
+use std::sync::Mutex;
+use std::time::Duration;
+use temporal_sdk::ActivityError;
+use thiserror::Error;
+
+/// Reasons the payment gateway refused a request
+#[derive(Debug, Clone, PartialEq, Error)]
+pub enum PaymentError {
+    /// The card issuer said no - retrying will not help
+    #[error("payment declined: {0}")]
+    Declined(String),
+    #[error("payment gateway timed out")]
+    Timeout,
+    #[error("payment gateway unavailable: {0}")]
+    Unavailable(String),
+}
+
+impl PaymentError {
+    pub fn is_retryable(&self) -> bool {
+        !matches!(self, PaymentError::Declined(_))
+    }
+}
+
+impl From<PaymentError> for ActivityError {
+    fn from(e: PaymentError) -> Self {
+        if e.is_retryable() {
+            ActivityError::Retryable {
+                source: e.into(),
+                explicit_delay: None,
+            }
+        } else {
+            ActivityError::NonRetryable(e.into())
+        }
+    }
+}
+
+/// Funds reserved on the customer's card
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Authorization {
+    pub authorization_id: String,
+    pub amount: Amount,
+}
+
+/// Funds taken from the customer - the transaction ID is needed to refund them
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Capture {
+    pub transaction_id: String,
+    pub amount: Amount,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Refund {
+    pub refund_id: String,
+    pub transaction_id: String,
+    pub amount: Amount,
+}
+
+/// Input to the refund_payment activity
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RefundRequest {
+    pub transaction_id: String,
+    pub amount: Amount,
+}
+
+/// Money moved for an order, kept on the order as its transaction history
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Transaction {
+    Charge(Capture),
+    Refund(Refund),
+}
+
+/// What the customer has paid, less what they have been given back
+pub fn net_paid(history: &[Transaction]) -> Result<Amount, PricingError> {
+    let charged = total_charged(history)?.unwrap_or_default();
+    let refunded = total_refunded(history)?.map_or(0, |r| r.amount_in_pence);
+    Ok(Amount {
+        amount_in_pence: charged.amount_in_pence.saturating_sub(refunded),
+        ..charged
+    })
+}
+
+/// Everything charged, if anything has been
+pub fn total_charged(history: &[Transaction]) -> Result<Option<Amount>, PricingError> {
+    sum(history.iter().filter_map(|t| match t {
+        Transaction::Charge(capture) => Some(capture.amount),
+        Transaction::Refund(_) => None,
+    }))
+}
+
+/// Everything given back, if anything has been
+pub fn total_refunded(history: &[Transaction]) -> Result<Option<Amount>, PricingError> {
+    sum(history.iter().filter_map(|t| match t {
+        Transaction::Refund(refund) => Some(refund.amount),
+        Transaction::Charge(_) => None,
+    }))
+}
+
+fn sum(amounts: impl Iterator<Item = Amount>) -> Result<Option<Amount>, PricingError> {
+    amounts.try_fold(None, |total: Option<Amount>, amount| {
+        let Some(total) = total else {
+            return Ok(Some(amount));
+        };
+        let amount_in_pence = total
+            .amount_in_pence
+            .checked_add(amount.amount_in_pence)
+            .ok_or(PricingError::Overflow)?;
+        Ok(Some(Amount {
+            amount_in_pence,
+            currency: total.currency,
+        }))
+    })
+}
+
+/// The refunds that give back `amount`. Refunds are made against the latest charges
+/// first, and no charge is refunded for more than is left of it.
+pub fn plan_refunds(history: &[Transaction], amount: Amount) -> Vec<RefundRequest> {
+    let mut remaining = amount.amount_in_pence;
+    let mut requests = Vec::new();
+
+    for transaction in history.iter().rev() {
+        if remaining == 0 {
+            break;
+        }
+        let Transaction::Charge(capture) = transaction else {
+            continue;
+        };
+
+        let refunded = history
+            .iter()
+            .filter_map(|t| match t {
+                Transaction::Refund(r) if r.transaction_id == capture.transaction_id => Some(r.amount.amount_in_pence),
+                _ => None,
+            })
+            .fold(0, u64::saturating_add);
+        let refundable = capture.amount.amount_in_pence.saturating_sub(refunded).min(remaining);
+        if refundable > 0 {
+            requests.push(RefundRequest {
+                transaction_id: capture.transaction_id.clone(),
+                amount: Amount {
+                    amount_in_pence: refundable,
+                    currency: amount.currency,
+                },
+            });
+            remaining -= refundable;
+        }
+    }
+
+    requests
+}
+
+/// Build an idempotency key that is stable across retries of the same activity, so
+/// a retried charge or refund is never applied twice. Activity IDs restart with each
+/// run, and order IDs can be reused, so the run keeps a new order's keys its own.
+pub fn idempotency_key(workflow_id: &str, run_id: &str, activity_id: &str, operation: &str) -> String {
+    format!("{}/{}/{}/{}", workflow_id, run_id, activity_id, operation)
+}
+
+/// Takes and gives back the customer's money. Every call carries an idempotency key -
+/// repeating a call with the same key must return the original result.
+#[async_trait]
+pub trait PaymentGateway: Send + Sync {
+    async fn authorize(
+        &self,
+        idempotency_key: &str,
+        amount: Amount,
+    ) -> Result<Authorization, PaymentError>;
+
+    async fn capture(
+        &self,
+        idempotency_key: &str,
+        authorization: &Authorization,
+    ) -> Result<Capture, PaymentError>;
+
+    async fn refund(
+        &self,
+        idempotency_key: &str,
+        request: &RefundRequest,
+    ) -> Result<Refund, PaymentError>;
+}
+
+/// What the fake gateway does with its next new request
+#[derive(Debug, Clone, PartialEq)]
+pub enum ScriptedOutcome {
+    Approve,
+    Decline(String),
+    Timeout,
+    Unavailable(String),
+}
+
+#[derive(Debug, Clone)]
+enum Recorded {
+    Authorization(Authorization),
+    Capture(Capture),
+    Refund(Refund),
+}
+
+#[derive(Debug, Default)]
+struct Ledger {
+    script: VecDeque<ScriptedOutcome>,
+    by_key: HashMap<String, Recorded>,
+    captures: Vec<Capture>,
+    refunds: Vec<Refund>,
+    next_id: u32,
+}
+
+/// A deterministic in-memory gateway. It approves everything unless scripted
+/// otherwise - each new (not replayed) request takes the next scripted outcome.
+#[derive(Debug, Default)]
+pub struct FakePaymentGateway {
+    ledger: Mutex<Ledger>,
+    latency: Duration,
+}
+
+impl FakePaymentGateway {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Outcomes for the next new requests, in order
+    pub fn with_script(self, script: impl IntoIterator<Item = ScriptedOutcome>) -> Self {
+        self.ledger.lock().unwrap().script.extend(script);
+        self
+    }
+
+    /// Simulate a slow gateway
+    pub fn with_latency(mut self, latency: Duration) -> Self {
+        self.latency = latency;
+        self
+    }
+
+    /// Money successfully taken
+    pub fn captures(&self) -> Vec<Capture> {
+        self.ledger.lock().unwrap().captures.clone()
+    }
+
+    /// Money successfully given back
+    pub fn refunds(&self) -> Vec<Refund> {
+        self.ledger.lock().unwrap().refunds.clone()
+    }
+
+    /// Replay the result for a known key, or run `record` against the next scripted outcome
+    async fn call<T>(
+        &self,
+        idempotency_key: &str,
+        replay: impl Fn(&Recorded) -> Option<T>,
+        record: impl FnOnce(&mut Ledger, String) -> (T, Recorded),
+    ) -> Result<T, PaymentError> {
+        if !self.latency.is_zero() {
+            tokio::time::sleep(self.latency).await;
+        }
+
+        let mut ledger = self.ledger.lock().unwrap();
+        if let Some(result) = ledger.by_key.get(idempotency_key).and_then(replay) {
+            return Ok(result);
+        }
+
+        match ledger
+            .script
+            .pop_front()
+            .unwrap_or(ScriptedOutcome::Approve)
+        {
+            ScriptedOutcome::Approve => {
+                ledger.next_id += 1;
+                let id = ledger.next_id.to_string();
+                let (result, recorded) = record(&mut ledger, id);
+                ledger.by_key.insert(idempotency_key.to_string(), recorded);
+                Ok(result)
+            }
+            ScriptedOutcome::Decline(reason) => Err(PaymentError::Declined(reason)),
+            ScriptedOutcome::Timeout => Err(PaymentError::Timeout),
+            ScriptedOutcome::Unavailable(reason) => Err(PaymentError::Unavailable(reason)),
+        }
+    }
+}
+
+#[async_trait]
+impl PaymentGateway for FakePaymentGateway {
+    async fn authorize(
+        &self,
+        idempotency_key: &str,
+        amount: Amount,
+    ) -> Result<Authorization, PaymentError> {
+        self.call(
+            idempotency_key,
+            |r| match r {
+                Recorded::Authorization(a) => Some(a.clone()),
+                _ => None,
+            },
+            |_, id| {
+                let authorization = Authorization {
+                    authorization_id: format!("auth-{}", id),
+                    amount,
+                };
+                (
+                    authorization.clone(),
+                    Recorded::Authorization(authorization),
+                )
+            },
+        )
+        .await
+    }
+
+    async fn capture(
+        &self,
+        idempotency_key: &str,
+        authorization: &Authorization,
+    ) -> Result<Capture, PaymentError> {
+        self.call(
+            idempotency_key,
+            |r| match r {
+                Recorded::Capture(c) => Some(c.clone()),
+                _ => None,
+            },
+            |ledger, id| {
+                let capture = Capture {
+                    transaction_id: format!("txn-{}", id),
+                    amount: authorization.amount,
+                };
+                ledger.captures.push(capture.clone());
+                (capture.clone(), Recorded::Capture(capture))
+            },
+        )
+        .await
+    }
+
+    async fn refund(
+        &self,
+        idempotency_key: &str,
+        request: &RefundRequest,
+    ) -> Result<Refund, PaymentError> {
+        self.call(
+            idempotency_key,
+            |r| match r {
+                Recorded::Refund(r) => Some(r.clone()),
+                _ => None,
+            },
+            |ledger, id| {
+                let refund = Refund {
+                    refund_id: format!("refund-{}", id),
+                    transaction_id: request.transaction_id.clone(),
+                    amount: request.amount,
+                };
+                ledger.refunds.push(refund.clone());
+                (refund.clone(), Recorded::Refund(refund))
+            },
+        )
+        .await
+    }
+}
@@ -0,0 +1,179 @@
+/*
+ * Copyright 2025 Simon Emms <simon@simonemms.com>
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use crate::catalogue::ProductCatalogue;
+use crate::types::OrderProduct;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Currency {
+    #[default]
+    #[serde(rename = "GBP")]
+    Gbp,
+}
+
+impl Currency {
+    pub fn symbol(&self) -> &'static str {
+        match self {
+            Currency::Gbp => "£",
+        }
+    }
+}
+
+impl std::fmt::Display for Currency {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Currency::Gbp => write!(f, "GBP"),
+        }
+    }
+}
+
+/// An amount of money in minor units (pence)
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Amount {
+    pub amount_in_pence: u64,
+    pub currency: Currency,
+}
+
+impl std::fmt::Display for Amount {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}{}.{:02}",
+            self.currency.symbol(),
+            self.amount_in_pence / 100,
+            self.amount_in_pence % 100
+        )
+    }
+}
+
+/// Reasons an order cannot be priced
+#[derive(Debug, Clone, PartialEq, Error)]
+pub enum PricingError {
+    #[error("unknown product: {0}")]
+    UnknownProduct(u32),
+    #[error("product {0} has an invalid price")]
+    InvalidPrice(u32),
+    #[error("order total is too large")]
+    Overflow,
+}
+
+/// One product in a priced order
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LineItem {
+    pub product_id: u32,
+    pub name: String,
+    pub quantity: u32,
+    pub unit_price_in_pence: u64,
+    pub total_in_pence: u64,
+    /// Minutes the product takes to prepare, if the catalogue says
+    #[serde(default)]
+    pub prep_minutes: Option<u32>,
+    /// Whether the product is in stock. Baskets are priced whether or not they are, as
+    /// out of stock products can still be taken out of them.
+    #[serde(default = "in_stock")]
+    pub available: bool,
+}
+
+fn in_stock() -> bool {
+    true
+}
+
+/// A discount code taken off an order
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AppliedDiscount {
+    pub code: String,
+    pub amount_in_pence: u64,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct PricedOrder {
+    pub line_items: Vec<LineItem>,
+    /// What the customer pays - the line items less any discount
+    pub total_in_pence: u64,
+    pub currency: Currency,
+    #[serde(default)]
+    pub discount: Option<AppliedDiscount>,
+}
+
+impl PricedOrder {
+    pub fn total(&self) -> Amount {
+        Amount {
+            amount_in_pence: self.total_in_pence,
+            currency: self.currency,
+        }
+    }
+
+    /// What the line items come to, before any discount
+    pub fn subtotal_in_pence(&self) -> u64 {
+        self.line_items.iter().map(|l| l.total_in_pence).sum()
+    }
+
+    /// Take a discount off the line items, replacing any taken off before. A discount
+    /// larger than the order makes it free.
+    pub fn apply_discount(&mut self, discount: AppliedDiscount) {
+        self.total_in_pence = self.subtotal_in_pence().saturating_sub(discount.amount_in_pence);
+        self.discount = Some(discount);
+    }
+
+    /// Put the order back to the price of its line items
+    pub fn remove_discount(&mut self) {
+        self.total_in_pence = self.subtotal_in_pence();
+        self.discount = None;
+    }
+}
+
+/// Convert a catalogue price in pounds to pence, rounding to the nearest penny.
+/// Catalogue prices are the only floats - everything downstream is integer.
+pub fn to_pence(product_id: u32, price: f64) -> Result<u64, PricingError> {
+    if !price.is_finite() || price < 0.0 {
+        return Err(PricingError::InvalidPrice(product_id));
+    }
+
+    Ok((price * 100.0).round() as u64)
+}
+
+/// Price each product in the basket from the catalogue
+pub fn price_order(catalogue: &dyn ProductCatalogue, products: &[OrderProduct]) -> Result<PricedOrder, PricingError> {
+    let mut priced = PricedOrder::default();
+
+    for item in products {
+        let product = catalogue
+            .find(item.product_id)
+            .ok_or(PricingError::UnknownProduct(item.product_id))?;
+        let unit_price_in_pence = to_pence(product.product_id, product.price)?;
+        let total_in_pence = unit_price_in_pence
+            .checked_mul(u64::from(item.quantity))
+            .ok_or(PricingError::Overflow)?;
+
+        priced.total_in_pence = priced
+            .total_in_pence
+            .checked_add(total_in_pence)
+            .ok_or(PricingError::Overflow)?;
+        priced.line_items.push(LineItem {
+            product_id: product.product_id,
+            name: product.name,
+            quantity: item.quantity,
+            unit_price_in_pence,
+            total_in_pence,
+            prep_minutes: product.prep_minutes,
+            available: product.available,
+        });
+    }
+
+    Ok(priced)
+}
@@ -0,0 +1,154 @@
+/*
+ * Copyright 2025 Simon Emms <simon@simonemms.com>
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use crate::pricing::{AppliedDiscount, Amount, PricedOrder};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use temporal_sdk::ActivityError;
+use thiserror::Error;
+
+/// What a promotion takes off an order
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Discount {
+    /// A percentage off the line items, rounded to the nearest penny
+    Percentage { percent: u32 },
+    /// A fixed amount off, up to the whole order
+    FixedAmount { amount_in_pence: u64 },
+    /// One of a product free, if it is in the basket
+    FreeItem { product_id: u32 },
+}
+
+/// A discount code and what it gives the customer
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Promotion {
+    pub code: String,
+    pub discount: Discount,
+    /// The least the line items must come to for the code to apply
+    #[serde(default)]
+    pub min_spend_in_pence: u64,
+}
+
+/// Reasons a discount code cannot be used
+#[derive(Debug, Clone, PartialEq, Error)]
+pub enum PromotionError {
+    #[error("unknown discount code {0}")]
+    UnknownCode(String),
+    #[error("discount code {code} needs a spend of at least {minimum}")]
+    MinimumSpend { code: String, minimum: Amount },
+    #[error("discount code {code} needs product {product_id} in the basket")]
+    ItemNotInBasket { code: String, product_id: u32 },
+}
+
+impl From<PromotionError> for ActivityError {
+    fn from(e: PromotionError) -> Self {
+        // Asking again will not change the answer - the customer has to
+        ActivityError::NonRetryable(e.into())
+    }
+}
+
+impl Promotion {
+    /// Work out the discount for a priced order, from its line items
+    pub fn apply(&self, order: &PricedOrder) -> Result<AppliedDiscount, PromotionError> {
+        let subtotal = order.subtotal_in_pence();
+        if subtotal < self.min_spend_in_pence {
+            return Err(PromotionError::MinimumSpend {
+                code: self.code.clone(),
+                minimum: Amount {
+                    amount_in_pence: self.min_spend_in_pence,
+                    currency: order.currency,
+                },
+            });
+        }
+
+        let amount_in_pence = match &self.discount {
+            Discount::Percentage { percent } => {
+                let pence = (u128::from(subtotal) * u128::from(*percent.min(&100)) + 50) / 100;
+                pence as u64
+            }
+            Discount::FixedAmount { amount_in_pence } => (*amount_in_pence).min(subtotal),
+            Discount::FreeItem { product_id } => order
+                .line_items
+                .iter()
+                .find(|l| l.product_id == *product_id)
+                .map(|l| l.unit_price_in_pence)
+                .ok_or_else(|| PromotionError::ItemNotInBasket {
+                    code: self.code.clone(),
+                    product_id: *product_id,
+                })?,
+        };
+
+        Ok(AppliedDiscount {
+            code: self.code.clone(),
+            amount_in_pence,
+        })
+    }
+}
+
+/// Where discount codes are looked up. Codes are not case sensitive.
+pub trait PromotionStore: Send + Sync {
+    fn find(&self, code: &str) -> Option<Promotion>;
+}
+
+#[derive(Debug, Error)]
+pub enum PromotionStoreError {
+    #[error("unable to read promotions: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("invalid JSON promotions: {0}")]
+    Json(#[from] serde_json::Error),
+    #[error("promotion {0} is more than 100% off")]
+    InvalidPercentage(String),
+}
+
+#[derive(Deserialize)]
+struct PromotionsFile {
+    promotions: Vec<Promotion>,
+}
+
+/// Promotions held by the worker, loaded from a JSON file such as `promotions.json`.
+/// The default store has no promotions.
+#[derive(Debug, Clone, Default)]
+pub struct LocalPromotions {
+    promotions: Vec<Promotion>,
+}
+
+impl LocalPromotions {
+    pub fn new(promotions: Vec<Promotion>) -> Result<Self, PromotionStoreError> {
+        for promotion in &promotions {
+            if matches!(promotion.discount, Discount::Percentage { percent } if percent > 100) {
+                return Err(PromotionStoreError::InvalidPercentage(promotion.code.clone()));
+            }
+        }
+
+        Ok(Self { promotions })
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, PromotionStoreError> {
+        Self::from_json(&std::fs::read_to_string(path)?)
+    }
+
+    pub fn from_json(contents: &str) -> Result<Self, PromotionStoreError> {
+        let file: PromotionsFile = serde_json::from_str(contents)?;
+        Self::new(file.promotions)
+    }
+}
+
+impl PromotionStore for LocalPromotions {
+    fn find(&self, code: &str) -> Option<Promotion> {
+        let code = code.trim();
+        self.promotions.iter().find(|p| p.code.eq_ignore_ascii_case(code)).cloned()
+    }
+}
@@ -0,0 +1,365 @@
+/*
+ * Copyright 2025 Simon Emms <simon@simonemms.com>
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use crate::notifications::Notification;
+use crate::payments::{net_paid, total_charged, total_refunded};
+use crate::pricing::{AppliedDiscount, Amount, PricingError};
+use crate::types::OrderState;
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use temporal_sdk::ActivityError;
+use thiserror::Error;
+
+/// UK standard rate VAT, which catalogue prices include
+pub const VAT_RATE_PERCENT: u64 = 20;
+
+/// One product on a receipt
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ReceiptLine {
+    pub name: String,
+    pub quantity: u32,
+    pub unit_price: Amount,
+    pub total: Amount,
+}
+
+/// The VAT included in what the customer paid
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct VatBreakdown {
+    pub rate_percent: u64,
+    pub net: Amount,
+    pub vat: Amount,
+    pub gross: Amount,
+}
+
+impl VatBreakdown {
+    /// Split `gross`, which includes VAT at `rate_percent`, rounding the VAT to the
+    /// nearest penny. The sum is worked in `u128`, so any `u64` gross can be split and
+    /// only a rate too large to be real overflows.
+    pub fn included_in(gross: Amount, rate_percent: u64) -> Result<Self, PricingError> {
+        let rate = u128::from(rate_percent);
+        let divisor = 100 + rate;
+        let vat = u128::from(gross.amount_in_pence)
+            .checked_mul(rate * 2)
+            .and_then(|v| v.checked_add(divisor))
+            .ok_or(PricingError::Overflow)?
+            / (divisor * 2);
+        // VAT is part of the gross, so it is never more than the gross
+        let vat = u64::try_from(vat).map_err(|_| PricingError::Overflow)?;
+        let amount = |amount_in_pence| Amount { amount_in_pence, ..gross };
+
+        Ok(Self {
+            rate_percent,
+            net: amount(gross.amount_in_pence - vat),
+            vat: amount(vat),
+            gross,
+        })
+    }
+}
+
+/// An itemised receipt for a completed order, priced as the customer was charged
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Receipt {
+    pub order_id: String,
+    pub issued_at: DateTime<Utc>,
+    /// The post code delivered to, or `None` for collection
+    pub post_code: Option<String>,
+    pub lines: Vec<ReceiptLine>,
+    pub subtotal: Amount,
+    pub discount: Option<AppliedDiscount>,
+    pub total: Amount,
+    /// Everything the customer was charged, including for amendments
+    pub charged: Amount,
+    /// Given back for items taken out after payment
+    pub refunded: Amount,
+    /// What the customer paid, less what they were given back
+    pub paid: Amount,
+    pub vat: VatBreakdown,
+}
+
+#[derive(Debug, Clone, PartialEq, Error)]
+pub enum ReceiptError {
+    #[error("order has not been priced")]
+    NotPriced,
+    #[error(transparent)]
+    Pricing(#[from] PricingError),
+}
+
+impl Receipt {
+    /// The receipt for a priced order
+    pub fn for_order(order_id: &str, order: &OrderState, issued_at: DateTime<Utc>) -> Result<Self, ReceiptError> {
+        let priced = order.pricing.as_ref().ok_or(ReceiptError::NotPriced)?;
+        let amount = |amount_in_pence| Amount {
+            amount_in_pence,
+            currency: priced.currency,
+        };
+        let paid = net_paid(&order.transactions)?;
+
+        Ok(Self {
+            order_id: order_id.to_string(),
+            issued_at,
+            post_code: order
+                .delivery_address
+                .as_ref()
+                .filter(|_| !order.collection)
+                .map(|a| a.post_code.clone()),
+            lines: priced
+                .line_items
+                .iter()
+                .map(|l| ReceiptLine {
+                    name: l.name.clone(),
+                    quantity: l.quantity,
+                    unit_price: amount(l.unit_price_in_pence),
+                    total: amount(l.total_in_pence),
+                })
+                .collect(),
+            subtotal: amount(priced.subtotal_in_pence()),
+            discount: priced.discount.clone(),
+            total: priced.total(),
+            charged: total_charged(&order.transactions)?.unwrap_or(amount(0)),
+            refunded: total_refunded(&order.transactions)?.unwrap_or(amount(0)),
+            paid,
+            vat: VatBreakdown::included_in(paid, VAT_RATE_PERCENT)?,
+        })
+    }
+
+    /// The totals under the line items, as label and amount. Refunds are shown against
+    /// what was charged, so the rows add up to what was paid.
+    fn summary(&self) -> Vec<(String, String)> {
+        let mut rows = vec![("Subtotal".to_string(), self.subtotal.to_string())];
+        if let Some(discount) = &self.discount {
+            let amount = Amount {
+                amount_in_pence: discount.amount_in_pence,
+                ..self.total
+            };
+            rows.push((format!("Discount ({})", discount.code), format!("-{}", amount)));
+        }
+        rows.push(("Total".to_string(), self.total.to_string()));
+        if self.refunded.amount_in_pence > 0 {
+            rows.push(("Charged".to_string(), self.charged.to_string()));
+            rows.push(("Refunded".to_string(), format!("-{}", self.refunded)));
+        }
+        rows.push(("Paid".to_string(), self.paid.to_string()));
+        rows.push((format!("VAT at {}% included", self.vat.rate_percent), self.vat.vat.to_string()));
+        rows.push(("Net of VAT".to_string(), self.vat.net.to_string()));
+        rows
+    }
+
+    fn fulfilment(&self) -> String {
+        match &self.post_code {
+            Some(post_code) => format!("Delivered to {}", post_code),
+            None => "Collected".to_string(),
+        }
+    }
+
+    /// The receipt as plain text
+    pub fn to_text(&self) -> String {
+        let mut text = format!(
+            "Receipt for order {}\nIssued {}\n{}\n\n",
+            self.order_id,
+            self.issued_at.format("%Y-%m-%d %H:%M UTC"),
+            self.fulfilment()
+        );
+        for line in &self.lines {
+            let item = format!("{} x {} @ {}", line.quantity, line.name, line.unit_price);
+            let _ = writeln!(text, "{:<40} {:>10}", item, line.total.to_string());
+        }
+        text.push('\n');
+        for (label, value) in self.summary() {
+            let _ = writeln!(text, "{:<40} {:>10}", label, value);
+        }
+        text
+    }
+
+    /// The receipt as a standalone HTML page
+    pub fn to_html(&self) -> String {
+        let mut html = format!(
+            "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>Receipt for order {id}</title>\n\
+             </head>\n<body>\n<h1>Receipt for order {id}</h1>\n<p>Issued {issued}<br>{fulfilment}</p>\n<table>\n\
+             <tr><th>Item</th><th>Quantity</th><th>Price</th><th>Total</th></tr>\n",
+            id = escape_html(&self.order_id),
+            issued = self.issued_at.format("%Y-%m-%d %H:%M UTC"),
+            fulfilment = escape_html(&self.fulfilment()),
+        );
+        for line in &self.lines {
+            let _ = writeln!(
+                html,
+                "<tr><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>",
+                escape_html(&line.name),
+                line.quantity,
+                line.unit_price,
+                line.total
+            );
+        }
+        for (label, value) in self.summary() {
+            let _ = writeln!(
+                html,
+                "<tr><th colspan=\"3\">{}</th><td>{}</td></tr>",
+                escape_html(&label),
+                value
+            );
+        }
+        html.push_str("</table>\n</body>\n</html>\n");
+        html
+    }
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&#39;")
+}
+
+/// Where a receipt was stored, in each format
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct StoredReceipt {
+    pub html_url: String,
+    pub text_url: String,
+}
+
+/// What the `send_receipt` activity did, so it is recorded in history
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SentReceipt {
+    pub receipt: StoredReceipt,
+    pub notifications: Vec<Notification>,
+}
+
+#[derive(Debug, Error)]
+pub enum BlobStoreError {
+    #[error("unable to store {0}: not a valid key")]
+    InvalidKey(String),
+    #[error("unable to write blob: {0}")]
+    Io(#[from] std::io::Error),
+}
+
+impl From<BlobStoreError> for ActivityError {
+    fn from(e: BlobStoreError) -> Self {
+        match e {
+            BlobStoreError::InvalidKey(_) => ActivityError::NonRetryable(e.into()),
+            BlobStoreError::Io(_) => ActivityError::Retryable {
+                source: e.into(),
+                explicit_delay: None,
+            },
+        }
+    }
+}
+
+/// Somewhere to keep documents for customers to download. Stands in for an object store.
+#[async_trait]
+pub trait BlobStore: Send + Sync {
+    /// Store `contents` under `key`, replacing anything already there, and return a
+    /// link to it
+    async fn put(&self, key: &str, content_type: &str, contents: Vec<u8>) -> Result<String, BlobStoreError>;
+}
+
+/// Keys are file names, so they cannot reach outside the store
+fn check_key(key: &str) -> Result<(), BlobStoreError> {
+    if key.is_empty() || key.starts_with('.') || key.contains(['/', '\\']) {
+        return Err(BlobStoreError::InvalidKey(key.to_string()));
+    }
+    Ok(())
+}
+
+/// Key for one run's receipt, so an order ID reused for another customer's order does
+/// not replace theirs. Anything but ASCII letters, digits and `-` is encoded as `_` and
+/// its hex bytes, which keeps keys valid, and different IDs apart, whatever the IDs hold.
+pub fn receipt_key(workflow_id: &str, run_id: &str, extension: &str) -> String {
+    format!("{}.{}.{}", encode_key_part(workflow_id), encode_key_part(run_id), extension)
+}
+
+fn encode_key_part(part: &str) -> String {
+    let mut encoded = String::with_capacity(part.len());
+    for byte in part.bytes() {
+        if byte.is_ascii_alphanumeric() || byte == b'-' {
+            encoded.push(byte as char);
+        } else {
+            let _ = write!(encoded, "_{:02X}", byte);
+        }
+    }
+    encoded
+}
+
+/// Write blobs to files in a local directory - for development. Links are `file://`
+/// URLs unless the directory is served from a base URL.
+#[derive(Debug)]
+pub struct LocalBlobStore {
+    dir: PathBuf,
+    base_url: Option<String>,
+}
+
+impl LocalBlobStore {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self {
+            dir: dir.into(),
+            base_url: None,
+        }
+    }
+
+    pub fn with_base_url(mut self, base_url: impl Into<String>) -> Self {
+        self.base_url = Some(base_url.into());
+        self
+    }
+}
+
+#[async_trait]
+impl BlobStore for LocalBlobStore {
+    async fn put(&self, key: &str, _content_type: &str, contents: Vec<u8>) -> Result<String, BlobStoreError> {
+        check_key(key)?;
+        std::fs::create_dir_all(&self.dir)?;
+        let path = self.dir.join(key);
+        std::fs::write(&path, contents)?;
+
+        Ok(match &self.base_url {
+            Some(base_url) => format!("{}/{}", base_url.trim_end_matches('/'), key),
+            None => format!("file://{}", std::fs::canonicalize(&path)?.display()),
+        })
+    }
+}
+
+/// Keep blobs in memory so tests can assert on them
+#[derive(Debug, Default)]
+pub struct MemoryBlobStore {
+    blobs: Mutex<BTreeMap<String, (String, Vec<u8>)>>,
+}
+
+impl MemoryBlobStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The content type and contents stored under `key`
+    pub fn get(&self, key: &str) -> Option<(String, Vec<u8>)> {
+        self.blobs.lock().unwrap().get(key).cloned()
+    }
+}
+
+#[async_trait]
+impl BlobStore for MemoryBlobStore {
+    async fn put(&self, key: &str, content_type: &str, contents: Vec<u8>) -> Result<String, BlobStoreError> {
+        check_key(key)?;
+        self.blobs
+            .lock()
+            .unwrap()
+            .insert(key.to_string(), (content_type.to_string(), contents));
+        Ok(format!("memory://{}", key))
+    }
+}
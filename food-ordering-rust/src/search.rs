@@ -0,0 +1,103 @@
+/*
+ * Copyright 2025 Simon Emms <simon@simonemms.com>
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use crate::constants::SearchAttributes;
+use crate::types::{OrderState, OrderStatus};
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use std::fmt::Write;
+
+/// Customers are found by a hash of their email address, so it is never stored in
+/// the visibility store. Addresses are trimmed and lower-cased first.
+pub fn email_hash(email: &str) -> String {
+    let digest = Sha256::digest(email.trim().to_lowercase().as_bytes());
+    digest.iter().fold(String::new(), |mut hex, byte| {
+        let _ = write!(hex, "{:02x}", byte);
+        hex
+    })
+}
+
+/// The search attributes for an order as it is now. The total is only known once
+/// the basket has been priced, and the restaurant once it is set.
+pub fn order_search_attributes(order: &OrderState) -> Vec<(String, Value)> {
+    let mut attributes = vec![
+        (SearchAttributes::ORDER_STATUS.to_string(), Value::from(order.status.to_string())),
+        (SearchAttributes::CUSTOMER_EMAIL_HASH.to_string(), Value::from(email_hash(&order.email))),
+    ];
+    if let Some(restaurant_id) = &order.restaurant_id {
+        attributes.push((SearchAttributes::RESTAURANT_ID.to_string(), Value::from(restaurant_id.clone())));
+    }
+    if let Some(pricing) = &order.pricing {
+        attributes.push((SearchAttributes::ORDER_TOTAL_PENCE.to_string(), Value::from(pricing.total_in_pence)));
+    }
+    attributes
+}
+
+/// `temporal server start-dev` arguments registering every search attribute
+pub fn dev_server_args() -> Vec<String> {
+    SearchAttributes::ALL
+        .iter()
+        .flat_map(|(name, kind)| ["--search-attribute".to_string(), format!("{}={}", name, kind)])
+        .collect()
+}
+
+/// Narrows a listing of in-flight orders
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct OrderFilter {
+    pub status: Option<OrderStatus>,
+    pub restaurant_id: Option<String>,
+    pub email: Option<String>,
+}
+
+impl OrderFilter {
+    pub fn with_status(mut self, status: OrderStatus) -> Self {
+        self.status = Some(status);
+        self
+    }
+
+    pub fn with_restaurant(mut self, restaurant_id: impl Into<String>) -> Self {
+        self.restaurant_id = Some(restaurant_id.into());
+        self
+    }
+
+    pub fn with_email(mut self, email: impl Into<String>) -> Self {
+        self.email = Some(email.into());
+        self
+    }
+
+    /// The visibility query for running orders matching the filter
+    pub fn query(&self) -> String {
+        let mut clauses = vec![
+            r#"WorkflowType="order_workflow""#.to_string(),
+            r#"ExecutionStatus="Running""#.to_string(),
+        ];
+        if let Some(status) = &self.status {
+            clauses.push(format!(r#"{}="{}""#, SearchAttributes::ORDER_STATUS, status));
+        }
+        if let Some(restaurant_id) = &self.restaurant_id {
+            clauses.push(format!(r#"{}="{}""#, SearchAttributes::RESTAURANT_ID, quote(restaurant_id)));
+        }
+        if let Some(email) = &self.email {
+            clauses.push(format!(r#"{}="{}""#, SearchAttributes::CUSTOMER_EMAIL_HASH, email_hash(email)));
+        }
+        clauses.join(" AND ")
+    }
+}
+
+/// Escape a value for a double-quoted string in a visibility query
+fn quote(value: &str) -> String {
+    value.replace('\\', r"\\").replace('"', r#"\""#)
+}
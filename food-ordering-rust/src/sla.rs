@@ -0,0 +1,139 @@
+/*
+ * Copyright 2025 Simon Emms <simon@simonemms.com>
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use crate::types::OrderStatus;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use std::time::Duration;
+use thiserror::Error;
+
+/// What happens when an order sits in a status for too long
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Breach {
+    /// Reject the order and refund the customer
+    Reject,
+    /// Tell the customer and the restaurant the order is late, then keep waiting
+    Escalate,
+}
+
+/// How long an order may stay in a status
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Deadline {
+    pub status: OrderStatus,
+    pub within_secs: u64,
+    pub on_breach: Breach,
+}
+
+impl Deadline {
+    pub fn within(&self) -> Duration {
+        Duration::from_secs(self.within_secs)
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum SlaError {
+    #[error("{0} is before payment, so cannot have a deadline")]
+    Unpaid(OrderStatus),
+    #[error("{0} is a final status and cannot have a deadline")]
+    Terminal(OrderStatus),
+    #[error("an order cannot be rejected from {0}")]
+    CannotReject(OrderStatus),
+    #[error("{0} has more than one deadline")]
+    Duplicate(OrderStatus),
+    #[error("unable to read SLA policy: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("invalid SLA policy: {0}")]
+    Json(#[from] serde_json::Error),
+}
+
+/// The deadlines an order is held to once it has been paid for. Each order loads the
+/// worker's policy once, through the `load_sla_policy` activity, and keeps it: changing
+/// it only affects orders paid for after the workers are restarted with it.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SlaPolicy {
+    deadlines: Vec<Deadline>,
+}
+
+impl SlaPolicy {
+    pub fn new(deadlines: Vec<Deadline>) -> Result<Self, SlaError> {
+        for (i, deadline) in deadlines.iter().enumerate() {
+            if deadline.status == OrderStatus::Default {
+                return Err(SlaError::Unpaid(deadline.status.clone()));
+            }
+            if deadline.status.is_terminal() {
+                return Err(SlaError::Terminal(deadline.status.clone()));
+            }
+            if deadline.on_breach == Breach::Reject && !deadline.status.can_transition_to(&OrderStatus::Rejected) {
+                return Err(SlaError::CannotReject(deadline.status.clone()));
+            }
+            if deadlines[..i].iter().any(|d| d.status == deadline.status) {
+                return Err(SlaError::Duplicate(deadline.status.clone()));
+            }
+        }
+
+        Ok(Self { deadlines })
+    }
+
+    /// No deadlines - orders wait for the restaurant for as long as it takes
+    pub fn none() -> Self {
+        Self { deadlines: Vec::new() }
+    }
+
+    /// Load a policy from a JSON file of the form `{ "deadlines": [...] }`
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, SlaError> {
+        let contents = std::fs::read_to_string(path)?;
+        Self::from_json(&contents)
+    }
+
+    pub fn from_json(contents: &str) -> Result<Self, SlaError> {
+        let policy: SlaPolicy = serde_json::from_str(contents)?;
+        Self::new(policy.deadlines)
+    }
+
+    pub fn deadline_for(&self, status: &OrderStatus) -> Option<&Deadline> {
+        self.deadlines.iter().find(|d| d.status == *status)
+    }
+}
+
+impl Default for SlaPolicy {
+    /// The restaurant must accept within 5 minutes or the order is rejected. After
+    /// that, a late order is escalated rather than thrown away.
+    fn default() -> Self {
+        Self::new(vec![
+            Deadline {
+                status: OrderStatus::Pending,
+                within_secs: 5 * 60,
+                on_breach: Breach::Reject,
+            },
+            Deadline {
+                status: OrderStatus::Accepted,
+                within_secs: 10 * 60,
+                on_breach: Breach::Escalate,
+            },
+            Deadline {
+                status: OrderStatus::Preparing,
+                within_secs: 45 * 60,
+                on_breach: Breach::Escalate,
+            },
+            Deadline {
+                status: OrderStatus::Ready,
+                within_secs: 30 * 60,
+                on_breach: Breach::Escalate,
+            },
+        ])
+        .expect("default SLA policy is valid")
+    }
+}
@@ -0,0 +1,110 @@
+/*
+ * Copyright 2025 Simon Emms <simon@simonemms.com>
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use crate::types::StatusChange;
+use async_trait::async_trait;
+use temporal_sdk::ActivityError;
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum PublishError {
+    #[error("status changes request failed: {0}")]
+    Http(#[from] reqwest::Error),
+}
+
+impl PublishError {
+    /// Whether publishing again may work. An API that refuses the request, other than to
+    /// time it out or slow us down, will refuse it every time.
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            PublishError::Http(e) => match e.status() {
+                Some(status) if status.is_client_error() => {
+                    status == reqwest::StatusCode::REQUEST_TIMEOUT || status == reqwest::StatusCode::TOO_MANY_REQUESTS
+                }
+                _ => true,
+            },
+        }
+    }
+}
+
+impl From<PublishError> for ActivityError {
+    fn from(e: PublishError) -> Self {
+        if e.is_retryable() {
+            ActivityError::Retryable {
+                source: e.into(),
+                explicit_delay: None,
+            }
+        } else {
+            ActivityError::NonRetryable(e.into())
+        }
+    }
+}
+
+/// Hands an order's status changes to the clients streaming them. Every call carries
+/// all the changes so far, oldest first, so one that goes missing is made up by the next.
+#[async_trait]
+pub trait StatusPublisher: Send + Sync {
+    async fn publish(&self, order_id: &str, changes: &[StatusChange]) -> Result<(), PublishError>;
+}
+
+/// Publish nowhere - for workers with no API to stream to
+#[derive(Debug, Default)]
+pub struct NoPublisher;
+
+#[async_trait]
+impl StatusPublisher for NoPublisher {
+    async fn publish(&self, _order_id: &str, _changes: &[StatusChange]) -> Result<(), PublishError> {
+        Ok(())
+    }
+}
+
+/// Publish to the API's `POST /api/order/{order_id}/status-changes`, an admin endpoint
+pub struct HttpStatusPublisher {
+    api_url: String,
+    token: Option<String>,
+    client: reqwest::Client,
+}
+
+impl HttpStatusPublisher {
+    /// Publish to the API at `api_url`, such as `http://localhost:3000`
+    pub fn new(api_url: impl Into<String>) -> Self {
+        Self {
+            api_url: api_url.into().trim_end_matches('/').to_string(),
+            token: None,
+            client: reqwest::Client::new(),
+        }
+    }
+
+    /// Send the API's admin token as `Authorization: Bearer <token>`
+    pub fn with_token(mut self, token: impl Into<String>) -> Self {
+        self.token = Some(token.into());
+        self
+    }
+}
+
+#[async_trait]
+impl StatusPublisher for HttpStatusPublisher {
+    async fn publish(&self, order_id: &str, changes: &[StatusChange]) -> Result<(), PublishError> {
+        let url = format!("{}/api/order/{}/status-changes", self.api_url, order_id);
+        let mut request = self.client.post(url).json(changes);
+        if let Some(token) = &self.token {
+            request = request.bearer_auth(token);
+        }
+
+        request.send().await?.error_for_status()?;
+        Ok(())
+    }
+}
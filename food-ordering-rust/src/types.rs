@@ -14,7 +14,17 @@
  * limitations under the License.
  */
 
+use crate::capacity::KitchenSlot;
+use crate::couriers::DeliveryReport;
+use crate::eta::{prep_minutes, Eta};
+use crate::payments::{Capture, Transaction};
+use crate::pricing::{Amount, PricedOrder};
+use crate::promotions::Promotion;
+use crate::receipts::StoredReceipt;
+use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
+use temporal_sdk_core_protos::coresdk::PayloadDeserializeErr;
+use thiserror::Error;
 
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum OrderStatus {
@@ -22,9 +32,72 @@ pub enum OrderStatus {
     Pending,   // Order paid and waiting for restaurant to accept
     Accepted,  // Restaurant accepted order, but not started work yet
     Preparing, // Restaurant is cooking your food
-    Ready,     // Food is ready for collection/out for delivery
+    Ready,     // Food is ready for collection/delivery
+    OutForDelivery, // A courier has the food
     Completed, // Food given to a hungry person
     Rejected,  // Kitchen has rejected the order
+    PaymentDeclined, // Payment could not be taken - the kitchen never sees the order
+    Cancelled, // Customer cancelled before the restaurant accepted the order
+    DeliveryFailed, // No courier could deliver the order - the customer is refunded
+}
+
+/// An order cannot move between these two statuses
+#[derive(Debug, Clone, PartialEq, Error)]
+#[error("cannot move order from {from} to {to}")]
+pub struct StatusTransitionError {
+    pub from: OrderStatus,
+    pub to: OrderStatus,
+}
+
+impl OrderStatus {
+    /// The statuses an order may legally move to next in its lifecycle. Not all of these
+    /// are the restaurant's to make - see `OrderState::restaurant_next_statuses`.
+    pub fn next_statuses(&self) -> Vec<OrderStatus> {
+        match self {
+            OrderStatus::Default => vec![OrderStatus::Pending, OrderStatus::PaymentDeclined],
+            OrderStatus::Pending => vec![OrderStatus::Accepted, OrderStatus::Rejected],
+            OrderStatus::Accepted => vec![OrderStatus::Preparing, OrderStatus::Rejected],
+            OrderStatus::Preparing => vec![OrderStatus::Ready, OrderStatus::Rejected],
+            OrderStatus::Ready => vec![
+                OrderStatus::OutForDelivery,
+                OrderStatus::Completed,
+                OrderStatus::DeliveryFailed,
+            ],
+            OrderStatus::OutForDelivery => vec![OrderStatus::Completed, OrderStatus::DeliveryFailed],
+            OrderStatus::Completed
+            | OrderStatus::Rejected
+            | OrderStatus::PaymentDeclined
+            | OrderStatus::Cancelled
+            | OrderStatus::DeliveryFailed => vec![],
+        }
+    }
+
+    pub fn can_transition_to(&self, next: &OrderStatus) -> bool {
+        self.next_statuses().contains(next)
+    }
+
+    /// Check the move to `next` is legal
+    pub fn check_transition(&self, next: &OrderStatus) -> Result<(), StatusTransitionError> {
+        if self.can_transition_to(next) {
+            Ok(())
+        } else {
+            Err(StatusTransitionError {
+                from: self.clone(),
+                to: next.clone(),
+            })
+        }
+    }
+
+    /// Whether the order has finished - nothing further happens to it
+    pub fn is_terminal(&self) -> bool {
+        self.next_statuses().is_empty()
+    }
+
+    /// Whether the customer may cancel the order. Only the customer cancels, so this
+    /// is not one of the statuses offered to the restaurant.
+    pub fn is_cancellable(&self) -> bool {
+        matches!(self, OrderStatus::Default | OrderStatus::Pending)
+    }
 }
 
 impl std::str::FromStr for OrderStatus {
@@ -37,8 +110,12 @@ impl std::str::FromStr for OrderStatus {
             "ACCEPTED" => Ok(OrderStatus::Accepted),
             "PREPARING" => Ok(OrderStatus::Preparing),
             "READY" => Ok(OrderStatus::Ready),
+            "OUT_FOR_DELIVERY" => Ok(OrderStatus::OutForDelivery),
             "REJECTED" => Ok(OrderStatus::Rejected),
             "COMPLETED" => Ok(OrderStatus::Completed),
+            "PAYMENT_DECLINED" => Ok(OrderStatus::PaymentDeclined),
+            "CANCELLED" => Ok(OrderStatus::Cancelled),
+            "DELIVERY_FAILED" => Ok(OrderStatus::DeliveryFailed),
             _ => Err(format!("Invalid status: {}", s)),
         }
     }
@@ -52,8 +129,12 @@ impl std::fmt::Display for OrderStatus {
             OrderStatus::Accepted => write!(f, "ACCEPTED"),
             OrderStatus::Preparing => write!(f, "PREPARING"),
             OrderStatus::Ready => write!(f, "READY"),
+            OrderStatus::OutForDelivery => write!(f, "OUT_FOR_DELIVERY"),
             OrderStatus::Rejected => write!(f, "REJECTED"),
             OrderStatus::Completed => write!(f, "COMPLETED"),
+            OrderStatus::PaymentDeclined => write!(f, "PAYMENT_DECLINED"),
+            OrderStatus::Cancelled => write!(f, "CANCELLED"),
+            OrderStatus::DeliveryFailed => write!(f, "DELIVERY_FAILED"),
         }
     }
 }
@@ -68,13 +149,127 @@ pub struct Address {
     pub post_code: String,
 }
 
+/// Why an order cannot be delivered or collected as it stands
+#[derive(Debug, Clone, PartialEq, Error)]
+pub enum FulfilmentError {
+    #[error("delivery orders need a delivery address")]
+    MissingAddress,
+    #[error("collection orders cannot have a delivery address")]
+    UnexpectedAddress,
+    #[error("delivery address needs a first line")]
+    MissingLine1,
+    #[error("delivery address needs a town")]
+    MissingTown,
+    #[error("invalid post code: {0}")]
+    InvalidPostCode(String),
+}
+
+impl Address {
+    /// Check a courier could find the address
+    pub fn validate(&self) -> Result<(), FulfilmentError> {
+        if self.line1.trim().is_empty() {
+            return Err(FulfilmentError::MissingLine1);
+        }
+        if self.town.trim().is_empty() {
+            return Err(FulfilmentError::MissingTown);
+        }
+        if !is_valid_post_code(&self.post_code) {
+            return Err(FulfilmentError::InvalidPostCode(self.post_code.clone()));
+        }
+
+        Ok(())
+    }
+}
+
+/// Whether this looks like a UK post code, eg. "AB1 2CD" or "SW1A 1AA". Case and
+/// spacing are not checked.
+pub fn is_valid_post_code(post_code: &str) -> bool {
+    let code: Vec<char> = post_code
+        .chars()
+        .filter(|c| !c.is_whitespace())
+        .map(|c| c.to_ascii_uppercase())
+        .collect();
+    if !(5..=7).contains(&code.len()) {
+        return false;
+    }
+
+    // The inward code is always a digit and two letters
+    let (outward, inward) = code.split_at(code.len() - 3);
+    if !(inward[0].is_ascii_digit() && inward[1].is_ascii_alphabetic() && inward[2].is_ascii_alphabetic()) {
+        return false;
+    }
+
+    // The outward code is A9, A99, AA9, AA99, A9A or AA9A
+    let shape: String = outward
+        .iter()
+        .map(|c| if c.is_ascii_digit() { '9' } else if c.is_ascii_alphabetic() { 'A' } else { '?' })
+        .collect();
+    matches!(shape.as_str(), "A9" | "A99" | "AA9" | "AA99" | "A9A" | "AA9A")
+}
+
+/// How the customer gets their food
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Fulfilment {
+    pub collection: bool,
+    pub delivery_address: Option<Address>,
+}
+
+impl Fulfilment {
+    /// Delivery orders need a complete address, collection orders none
+    pub fn validate(&self) -> Result<(), FulfilmentError> {
+        match (self.collection, &self.delivery_address) {
+            (true, Some(_)) => Err(FulfilmentError::UnexpectedAddress),
+            (true, None) => Ok(()),
+            (false, None) => Err(FulfilmentError::MissingAddress),
+            (false, Some(address)) => address.validate(),
+        }
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct OrderState {
     pub collection: bool,
     pub delivery_address: Option<Address>,
     pub email: String,
+    /// Mobile number for text messages - optional, the email address is always used
+    #[serde(default)]
+    pub phone: Option<String>,
     pub products: Vec<OrderProduct>,
     pub status: OrderStatus,
+    /// Basket priced from the catalogue - kept up to date by the workflow
+    #[serde(default)]
+    pub pricing: Option<PricedOrder>,
+    /// Money taken at checkout - needed to refund the customer
+    #[serde(default)]
+    pub payment: Option<Capture>,
+    /// Every charge and refund, starting with `payment`, including those for amendments
+    #[serde(default)]
+    pub transactions: Vec<Transaction>,
+    /// Why payment could not be taken, if it was declined
+    #[serde(default)]
+    pub decline_reason: Option<String>,
+    /// Why the order could not be delivered, if it was not
+    #[serde(default)]
+    pub delivery_failure: Option<String>,
+    /// Who is delivering the food, once a courier has been dispatched
+    #[serde(default)]
+    pub courier_id: Option<String>,
+    /// The restaurant the order is from
+    #[serde(default)]
+    pub restaurant_id: Option<String>,
+    /// The discount code the customer has entered, if any
+    #[serde(default)]
+    pub promotion: Option<Promotion>,
+    /// Where the order is in the kitchen's queue, from acceptance until it is prepared
+    #[serde(default)]
+    pub kitchen_slot: Option<KitchenSlot>,
+    /// When the order should arrive, or be ready to collect - kept up to date by the
+    /// workflow from checkout
+    #[serde(default)]
+    pub eta: Option<Eta>,
+    /// Where the completed order's receipt was stored
+    #[serde(default)]
+    pub receipt: Option<StoredReceipt>,
 }
 
 impl OrderState {
@@ -83,22 +278,133 @@ impl OrderState {
             collection: false,
             delivery_address: None,
             email: String::new(),
+            phone: None,
             products: Vec::new(),
             status: OrderStatus::Default,
+            pricing: None,
+            payment: None,
+            transactions: Vec::new(),
+            decline_reason: None,
+            delivery_failure: None,
+            courier_id: None,
+            restaurant_id: None,
+            promotion: None,
+            kitchen_slot: None,
+            eta: None,
+            receipt: None,
+        }
+    }
+
+    pub fn fulfilment(&self) -> Fulfilment {
+        Fulfilment {
+            collection: self.collection,
+            delivery_address: self.delivery_address.clone(),
+        }
+    }
+
+    pub fn set_fulfilment(&mut self, fulfilment: Fulfilment) {
+        self.collection = fulfilment.collection;
+        self.delivery_address = fulfilment.delivery_address;
+    }
+
+    /// The statuses this order may move to next in its lifecycle, whoever moves it.
+    /// Delivery orders go out for delivery before they are completed; collection orders
+    /// are completed when collected, and have no delivery to fail.
+    pub(crate) fn next_statuses(&self) -> Vec<OrderStatus> {
+        let mut next = self.status.next_statuses();
+        if self.status == OrderStatus::Ready {
+            if self.collection {
+                next.retain(|s| !matches!(s, OrderStatus::OutForDelivery | OrderStatus::DeliveryFailed));
+            } else {
+                next.retain(|s| *s != OrderStatus::Completed);
+            }
+        }
+        next
+    }
+
+    /// Check the move to `next` is legal for this order
+    pub(crate) fn check_transition(&self, next: &OrderStatus) -> Result<(), StatusTransitionError> {
+        if self.next_statuses().contains(next) {
+            Ok(())
+        } else {
+            Err(StatusTransitionError {
+                from: self.status.clone(),
+                to: next.clone(),
+            })
+        }
+    }
+
+    /// The statuses the restaurant may move this order to with UPDATE_STATUS. UIs can
+    /// use this to only offer the restaurant moves it will accept.
+    pub fn restaurant_next_statuses(&self) -> Vec<OrderStatus> {
+        self.next_statuses()
+            .into_iter()
+            .filter(|next| self.check_restaurant_transition(next).is_ok())
+            .collect()
+    }
+
+    /// Check the restaurant may move the order to `next`. The workflow takes payment and
+    /// the courier moves delivery orders on once they are ready, so the restaurant can
+    /// only make some of the moves in the order's lifecycle.
+    pub fn check_restaurant_transition(&self, next: &OrderStatus) -> Result<(), RestaurantStatusError> {
+        if self.status == OrderStatus::Default {
+            return Err(RestaurantStatusError::NotPaid);
+        }
+        match next {
+            OrderStatus::OutForDelivery => return Err(RestaurantStatusError::OutForDelivery),
+            OrderStatus::Completed if !self.collection => return Err(RestaurantStatusError::DeliveryCompleted),
+            OrderStatus::DeliveryFailed => return Err(RestaurantStatusError::DeliveryFailed),
+            _ => {}
+        }
+        Ok(self.check_transition(next)?)
+    }
+
+    /// Take the order's promotion off a priced basket. A basket that no longer qualifies
+    /// for it, say after items were taken out, is full price until it does again.
+    pub fn discounted(&self, mut priced: PricedOrder) -> PricedOrder {
+        priced.remove_discount();
+        if let Some(discount) = self.promotion.as_ref().and_then(|p| p.apply(&priced).ok()) {
+            priced.apply_discount(discount);
+        }
+        priced
+    }
+
+    /// Bring the ETA up to date for an order that has been in its status since `since`
+    pub fn revise_eta(&mut self, since: DateTime<Utc>, now: DateTime<Utc>) {
+        let prep = self.pricing.as_ref().map_or(0, prep_minutes);
+        if let Some(eta) = self.eta.as_mut() {
+            eta.revise(prep, &self.status, self.kitchen_slot.as_ref(), since, now);
         }
     }
 
-    pub fn add_item(&mut self, item: OrderProduct) {
+    pub fn add_item(&mut self, item: OrderProduct) -> Result<(), BasketError> {
         // Check if we're updating existing products
         for existing_item in &mut self.products {
             if existing_item.product_id == item.product_id {
-                existing_item.quantity += item.quantity;
-                return;
+                existing_item.quantity = existing_item
+                    .quantity
+                    .checked_add(item.quantity)
+                    .ok_or(BasketError::QuantityTooLarge(item.product_id))?;
+                return Ok(());
             }
         }
 
         // Otherwise, add new product
         self.products.push(item);
+        Ok(())
+    }
+
+    /// Check `item` can be added to the basket without its quantity overflowing
+    pub fn check_can_add(&self, item: &OrderProduct) -> Result<(), BasketError> {
+        let existing = self
+            .products
+            .iter()
+            .find(|p| p.product_id == item.product_id)
+            .map_or(0, |p| p.quantity);
+        match existing.checked_add(item.quantity) {
+            Some(_) => Ok(()),
+            None => Err(BasketError::QuantityTooLarge(item.product_id)),
+        }
     }
 
     pub fn remove_item(&mut self, item: OrderProduct) {
@@ -113,17 +419,129 @@ impl OrderState {
     }
 }
 
+/// When an order moved to a status, in workflow time
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct StatusChange {
+    pub status: OrderStatus,
+    pub at: DateTime<Utc>,
+}
+
+/// What happened to an order - the result of `order_workflow`
 #[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OrderOutcome {
+    pub status: OrderStatus,
+    pub charged: Option<Amount>,
+    pub refunded: Option<Amount>,
+    /// Every status the order went through, starting with `DEFAULT` when it was created
+    pub transitions: Vec<StatusChange>,
+    pub notification_ids: Vec<String>,
+    /// How a delivery order reached the customer
+    #[serde(default)]
+    pub delivery: Option<DeliveryReport>,
+    /// The order as it finished
+    pub order: OrderState,
+}
+
+/// Reasons the order_workflow input cannot be turned into an `OrderState`
+#[derive(Debug, Error)]
+pub enum OrderInputError {
+    #[error("order_workflow started without an input payload")]
+    MissingInput,
+    #[error("order_workflow input is not a valid OrderState: {0}")]
+    Malformed(#[from] PayloadDeserializeErr),
+}
+
+/// Reasons the restaurant cannot move an order to a status
+#[derive(Debug, Clone, PartialEq, Error)]
+pub enum RestaurantStatusError {
+    #[error("order has not been paid for yet")]
+    NotPaid,
+    #[error("orders go out for delivery when a courier picks them up")]
+    OutForDelivery,
+    #[error("delivery orders are completed when the courier drops them off")]
+    DeliveryCompleted,
+    #[error("only the courier can fail a delivery")]
+    DeliveryFailed,
+    #[error(transparent)]
+    Transition(#[from] StatusTransitionError),
+}
+
+/// Reasons a customer cannot cancel their order
+#[derive(Debug, Clone, PartialEq, Error)]
+pub enum CancelError {
+    #[error("order is being paid for - try again in a moment")]
+    CheckingOut,
+    #[error("order is being changed - try again in a moment")]
+    Amending,
+    #[error("order can no longer be cancelled - it is {0}")]
+    TooLate(OrderStatus),
+}
+
+/// Reasons a change to the basket is refused
+#[derive(Debug, Clone, PartialEq, Error)]
+pub enum BasketError {
+    #[error("unknown product: {0}")]
+    UnknownProduct(u32),
+    #[error("product {0} is out of stock")]
+    OutOfStock(u32),
+    #[error("quantity must be at least 1")]
+    InvalidQuantity,
+    #[error("too many of product {0} in the basket")]
+    QuantityTooLarge(u32),
+    #[error("order has already been checked out")]
+    CheckedOut,
+    #[error("order can only be changed until the restaurant accepts it")]
+    AmendmentClosed,
+    #[error("another change to the order is in progress")]
+    AmendmentInProgress,
+    #[error("an order needs at least one item")]
+    Empty,
+    #[error("order has been cancelled")]
+    Cancelled,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct OrderProduct {
     pub product_id: u32,
     pub quantity: u32,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+impl OrderProduct {
+    /// Check the item is for a sensible quantity. Workflows cannot read the catalogue,
+    /// so this is all their validators check.
+    pub fn check_quantity(&self) -> Result<(), BasketError> {
+        if self.quantity == 0 {
+            return Err(BasketError::InvalidQuantity);
+        }
+
+        Ok(())
+    }
+
+    /// Check a basket priced with this item in it has the item, in stock
+    pub fn check_in_stock(&self, priced: &PricedOrder) -> Result<(), BasketError> {
+        match priced.line_items.iter().find(|l| l.product_id == self.product_id) {
+            Some(line) if line.available => Ok(()),
+            Some(_) => Err(BasketError::OutOfStock(self.product_id)),
+            None => Err(BasketError::UnknownProduct(self.product_id)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Product {
     pub product_id: u32,
     pub name: String,
     pub price: f64,
+    /// Out of stock products cannot be added to a basket
+    #[serde(default = "default_available")]
+    pub available: bool,
+    /// Minutes the kitchen takes to prepare it, used to estimate when orders arrive
+    #[serde(default)]
+    pub prep_minutes: Option<u32>,
+}
+
+fn default_available() -> bool {
+    true
 }
 
 // Sample products data
@@ -133,26 +551,36 @@ pub fn get_sample_products() -> Vec<Product> {
             product_id: 1,
             name: "Margherita Pizza".to_string(),
             price: 12.99,
+            available: true,
+            prep_minutes: Some(15),
         },
         Product {
             product_id: 2,
             name: "Pepperoni Pizza".to_string(),
             price: 14.99,
+            available: true,
+            prep_minutes: Some(15),
         },
         Product {
             product_id: 3,
             name: "Caesar Salad".to_string(),
             price: 8.99,
+            available: true,
+            prep_minutes: Some(5),
         },
         Product {
             product_id: 4,
             name: "Chicken Wings".to_string(),
             price: 9.99,
+            available: true,
+            prep_minutes: Some(20),
         },
         Product {
             product_id: 5,
             name: "Coca Cola".to_string(),
             price: 2.99,
+            available: true,
+            prep_minutes: Some(0),
         },
     ]
 }
@@ -14,60 +14,1716 @@
  * limitations under the License.
  */
 
-use crate::types::{OrderState, OrderStatus};
-use anyhow::Result;
-use temporal_sdk::{WfContext, WfExitValue, ActivityOptions};
+use crate::capacity::{kitchen_workflow_id, KitchenInputError, KitchenQueue, KitchenRequest, KitchenSlot};
+use crate::constants::{restaurant_task_queue, Memos, Signals, Updates};
+use crate::couriers::{
+    courier_workflow_id, AssignCourier, DeliveryError, DeliveryInputError, DeliveryJob, DeliveryProgress,
+    DeliveryReport, DeliveryStage, DeliveryTimeouts,
+};
+use crate::eta::{DeliveryTimes, Eta};
+use crate::kitchen::KitchenTicket;
+use crate::notifications::Notification;
+use crate::payments::{
+    net_paid, plan_refunds, total_charged, total_refunded, Capture, Refund, RefundRequest, Transaction,
+};
+use crate::pricing::{Amount, AppliedDiscount, PricedOrder};
+use crate::promotions::Promotion;
+use crate::receipts::SentReceipt;
+use crate::search::order_search_attributes;
+use crate::sla::{Breach, Deadline, SlaPolicy};
+use crate::types::{
+    Address, BasketError, CancelError, Fulfilment, OrderInputError, OrderOutcome, OrderProduct, OrderState,
+    OrderStatus, StatusChange,
+};
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Utc};
+use futures::future::{select, Either};
+use futures::StreamExt;
+use std::future::Future;
+use std::pin::pin;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use temporal_sdk::{
+    ActivityOptions, CancellableFuture, ChildWorkflowOptions, DrainableSignalStream, SignalWorkflowOptions,
+    TimerOptions, UpdateContext, WfContext, WfExitValue,
+};
+use temporal_sdk_core_protos::coresdk::activity_result::activity_resolution;
+use temporal_sdk_core_protos::coresdk::child_workflow::child_workflow_result;
+use temporal_sdk_core_protos::coresdk::workflow_commands::ContinueAsNewWorkflowExecution;
+use temporal_sdk_core_protos::coresdk::{AsJsonPayloadExt, FromJsonPayloadExt};
+use temporal_sdk_core_protos::temporal::api::common::v1::Payload;
+use temporal_sdk_core_protos::temporal::api::failure::v1::{failure::FailureInfo, Failure};
 use tokio::time::Duration;
-use tracing::info;
+use tracing::{debug, info, warn};
 
-pub async fn order_workflow(ctx: WfContext, mut state: OrderState) -> Result<WfExitValue<()>, anyhow::Error> {
+/// How long a restaurant's worker has to pick up one of its activities before the
+/// restaurant is treated as closed
+const RESTAURANT_START_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// How many requests a kitchen workflow handles before continuing as new, to keep its
+/// history short
+const KITCHEN_REQUESTS_PER_RUN: usize = 500;
+
+/// Deserialise the order the workflow was started with from its input payloads
+pub fn order_state_from_args(args: &[Payload]) -> Result<OrderState, OrderInputError> {
+    let payload = args.first().ok_or(OrderInputError::MissingInput)?;
+
+    Ok(OrderState::from_json_payload(payload)?)
+}
+
+/// Deserialise the delivery the courier workflow was started with from its input payloads
+pub fn delivery_job_from_args(args: &[Payload]) -> Result<DeliveryJob, DeliveryInputError> {
+    let payload = args.first().ok_or(DeliveryInputError::MissingInput)?;
+
+    Ok(DeliveryJob::from_json_payload(payload)?)
+}
+
+/// Deserialise the queue the kitchen workflow was started, or continued, with
+pub fn kitchen_queue_from_args(args: &[Payload]) -> Result<KitchenQueue, KitchenInputError> {
+    let payload = args.first().ok_or(KitchenInputError::MissingInput)?;
+
+    Ok(KitchenQueue::from_json_payload(payload)?)
+}
+
+/// Runs an order from basket, through payment, to a terminal status, returning what
+/// happened to it. The Rust SDK cannot answer queries, so while it runs the workflow
+/// keeps the order and its status changes in its memo for `get_order_status` to read;
+/// the outcome is where a completed, rejected (and refunded) or declined order is read
+/// from.
+///
+/// Baskets are only priced by the `price_basket` activity, so the prices shown and
+/// charged are fixed in history and replay the same. Orders from a restaurant are
+/// priced, and their kitchen tickets printed, by that restaurant's worker; payment and
+/// everything else stays central.
+/// Until the restaurant accepts it, a paid-for order may be amended with ADD_ITEM and
+/// REMOVE_ITEM; the difference in price is charged or refunded. Once accepted, the
+/// order queues for a slot in its kitchen's `kitchen_workflow`, and can only be
+/// prepared once it has one.
+/// Once paid for, the order is held to the deadlines the `load_sla_policy` activity
+/// gives it, kept for the rest of the order. Delivery orders are handed to a
+/// `courier_workflow` child when they are ready, and completed when it reports the
+/// food dropped off; collection orders wait to be collected.
+/// From payment, the order carries an ETA worked out from its products' preparation
+/// times, its place in the kitchen's queue and how far `delivery_times` says it is going.
+pub async fn order_workflow(
+    ctx: WfContext,
+    mut state: OrderState,
+    delivery_times: Arc<DeliveryTimes>,
+) -> Result<WfExitValue<OrderOutcome>, anyhow::Error> {
     // Force to be default state - payment not taken yet
     state.status = OrderStatus::Default;
+    state.pricing = None;
 
-    // Take payment
-    ctx.activity(ActivityOptions {
-        activity_type: "take_payment".to_string(),
-        input: serde_json::to_vec(&())?.into(),
-        start_to_close_timeout: Some(Duration::from_secs(60)),
-        ..Default::default()
-    })
-    .await
-    .unwrap_ok_payload();
+    // Handlers outlive this function's borrows, so the state is shared with them
+    publish_order(&ctx, &state);
+    let state = Arc::new(Mutex::new(state));
+    let progress = Arc::new(Mutex::new(Progress::default()));
+    progress.lock().unwrap().record(&ctx, OrderStatus::Default);
+    // Status updates and cancellations can overlap, so the workflow counts those still
+    // running rather than flagging one
+    let updates_in_flight = Arc::new(AtomicUsize::new(0));
+    let checked_out = Arc::new(AtomicBool::new(false));
+    let amending = Arc::new(AtomicBool::new(false));
+
+    let to_charge = Arc::new(Mutex::new(None));
+    let mut checkout = ctx.make_signal_channel(Signals::CHECKOUT);
+    let mut delivery_progress = ctx.make_signal_channel(Signals::DELIVERY_PROGRESS);
+    let mut kitchen_slots = ctx.make_signal_channel(Signals::KITCHEN_SLOT);
+
+    // Add an item to the basket, returning the new basket. Once the order is paid for
+    // this amends it, until the restaurant accepts it.
+    let add_validator_checked_out = checked_out.clone();
+    let add_validator_amending = amending.clone();
+    let add_validator_state = state.clone();
+    let add_checked_out = checked_out.clone();
+    let add_amending = amending.clone();
+    let add_state = state.clone();
+    ctx.update_handler(
+        Updates::ADD_ITEM,
+        move |_: &_, item: OrderProduct| {
+            check_basket_changeable(&add_validator_checked_out, &add_validator_amending, &add_validator_state)?;
+            item.check_quantity()?;
+            add_validator_state.lock().unwrap().check_can_add(&item)?;
+            Ok(())
+        },
+        move |u: &mut UpdateContext, item: OrderProduct| {
+            let ctx = u.wf_ctx.clone();
+            let state = add_state.clone();
+            let checked_out = add_checked_out.clone();
+            let amendment = start_amendment(&add_checked_out, &add_amending);
+            async move {
+                info!("Adding {} of product {} to basket", item.quantity, item.product_id);
+                let change = BasketChange::Add(item);
+                change_basket(&ctx, &state, &checked_out, amendment, change).await
+            }
+        },
+    );
+
+    // Remove an item from the basket, returning the new basket. Once the order is paid
+    // for this amends it, until the restaurant accepts it.
+    let remove_validator_checked_out = checked_out.clone();
+    let remove_validator_amending = amending.clone();
+    let remove_validator_state = state.clone();
+    let remove_checked_out = checked_out.clone();
+    let remove_amending = amending.clone();
+    let remove_state = state.clone();
+    ctx.update_handler(
+        Updates::REMOVE_ITEM,
+        move |_: &_, item: OrderProduct| {
+            // Out of stock products can still be taken out of the basket
+            check_basket_changeable(
+                &remove_validator_checked_out,
+                &remove_validator_amending,
+                &remove_validator_state,
+            )?;
+            item.check_quantity()?;
+            Ok(())
+        },
+        move |u: &mut UpdateContext, item: OrderProduct| {
+            let ctx = u.wf_ctx.clone();
+            let state = remove_state.clone();
+            let checked_out = remove_checked_out.clone();
+            let amendment = start_amendment(&remove_checked_out, &remove_amending);
+            async move {
+                info!("Removing {} of product {} from basket", item.quantity, item.product_id);
+                let change = BasketChange::Remove(item);
+                change_basket(&ctx, &state, &checked_out, amendment, change).await
+            }
+        },
+    );
+
+    // Choose delivery or collection, returning what was set
+    let fulfilment_checked_out = checked_out.clone();
+    let fulfilment_state = state.clone();
+    ctx.update_handler(
+        Updates::SET_FULFILMENT,
+        move |_: &_, fulfilment: Fulfilment| {
+            check_basket_open(&fulfilment_checked_out)?;
+            if let Err(e) = fulfilment.validate() {
+                debug!("Invalid fulfilment: {}", e);
+                return Err(e.into());
+            }
+            Ok(())
+        },
+        move |u: &mut UpdateContext, fulfilment: Fulfilment| {
+            let fulfilment = {
+                let mut state = fulfilment_state.lock().unwrap();
+                info!("Setting order for {}", if fulfilment.collection { "collection" } else { "delivery" });
+                state.set_fulfilment(fulfilment);
+                publish_order(&u.wf_ctx, &state);
+                state.fulfilment()
+            };
+            async move { Ok(fulfilment) }
+        },
+    );
+
+    // Enter a discount code, returning the discount it gives the basket now. The code
+    // stays on the order, so the discount follows the basket up to checkout.
+    let discount_validator_checked_out = checked_out.clone();
+    let discount_checked_out = checked_out.clone();
+    let discount_state = state.clone();
+    ctx.update_handler(
+        Updates::APPLY_DISCOUNT,
+        move |_: &_, code: String| {
+            check_basket_open(&discount_validator_checked_out)?;
+            if code.trim().is_empty() {
+                debug!("Empty discount code");
+                return Err(anyhow!("discount code is empty"));
+            }
+            Ok(())
+        },
+        move |u: &mut UpdateContext, code: String| {
+            let ctx = u.wf_ctx.clone();
+            let state = discount_state.clone();
+            let checked_out = discount_checked_out.clone();
+            async move {
+                let promotion = find_promotion(&ctx, &code).await?;
+                discount_basket(&ctx, &state, &checked_out, promotion).await
+            }
+        },
+    );
+
+    // Take the discount code off the order, returning the full price
+    let remove_discount_checked_out = checked_out.clone();
+    let remove_discount_state = state.clone();
+    ctx.update_handler(
+        Updates::REMOVE_DISCOUNT,
+        move |_: &_, _: ()| check_basket_open(&remove_discount_checked_out),
+        move |u: &mut UpdateContext, _: ()| {
+            let pricing = {
+                let mut state = remove_discount_state.lock().unwrap();
+                info!("Removing discount code");
+                state.promotion = None;
+                if let Some(pricing) = &mut state.pricing {
+                    pricing.remove_discount();
+                }
+                publish_order(&u.wf_ctx, &state);
+                state.pricing.clone()
+            };
+            async move { Ok(pricing) }
+        },
+    );
+
+    // Check out a non-empty basket that can be fulfilled, returning what the customer
+    // will be charged. A basket that cannot be priced is not checked out, and can still
+    // be changed.
+    let checkout_validator_state = state.clone();
+    let checkout_validator_checked_out = checked_out.clone();
+    let checkout_state = state.clone();
+    let checkout_checked_out = checked_out.clone();
+    let checkout_to_charge = to_charge.clone();
+    ctx.update_handler(
+        Updates::CHECKOUT,
+        move |_: &_, _: ()| {
+            if let Err(e) = check_checkout(&checkout_validator_state, &checkout_validator_checked_out) {
+                debug!("Order cannot be checked out: {}", e);
+                return Err(e);
+            }
+            Ok(())
+        },
+        move |u: &mut UpdateContext, _: ()| {
+            let ctx = u.wf_ctx.clone();
+            let state = checkout_state.clone();
+            let checked_out = checkout_checked_out.clone();
+            let to_charge = checkout_to_charge.clone();
+            // Freeze the basket before the next update is validated
+            checked_out.store(true, Ordering::SeqCst);
+            async move { checkout_basket(&ctx, &state, &checked_out, &to_charge).await }
+        },
+    );
+
+    // The customer cancels the order, until the restaurant accepts it. Anything paid is
+    // refunded, and the restaurant and customer are told.
+    let cancel_validator_state = state.clone();
+    let cancel_validator_checked_out = checked_out.clone();
+    let cancel_validator_amending = amending.clone();
+    let cancel_state = state.clone();
+    let cancel_progress = progress.clone();
+    let cancel_checked_out = checked_out.clone();
+    let cancel_in_flight = updates_in_flight.clone();
+    ctx.update_handler(
+        Updates::CANCEL,
+        move |_: &_, _: ()| {
+            if let Err(e) = check_cancellable(
+                &cancel_validator_state,
+                &cancel_validator_checked_out,
+                &cancel_validator_amending,
+            ) {
+                debug!("Order cannot be cancelled: {}", e);
+                return Err(e.into());
+            }
+            Ok(())
+        },
+        move |u: &mut UpdateContext, _: ()| {
+            let ctx = u.wf_ctx.clone();
+            let state = cancel_state.clone();
+            let progress = cancel_progress.clone();
+            let in_flight = cancel_in_flight.clone();
+            // Nothing about the basket changes from here on
+            cancel_checked_out.store(true, Ordering::SeqCst);
+            in_flight.fetch_add(1, Ordering::SeqCst);
+            async move {
+                let result = cancel_order(&ctx, &state, &progress).await;
+                in_flight.fetch_sub(1, Ordering::SeqCst);
+
+                result
+            }
+        },
+    );
+
+    // Update the order status - this will come from the restaurant
+    let validator_state = state.clone();
+    let validator_amending = amending.clone();
+    let update_state = state.clone();
+    let update_progress = progress.clone();
+    let update_in_flight = updates_in_flight.clone();
+    ctx.update_handler(
+        Updates::UPDATE_STATUS,
+        move |_: &_, input: String| {
+            let status = match input.parse::<OrderStatus>() {
+                Ok(status) => status,
+                Err(e) => {
+                    debug!("Invalid status: {}", input);
+                    return Err(anyhow!(e));
+                }
+            };
+
+            // The restaurant sees the order once the customer's change has been paid for
+            if validator_amending.load(Ordering::SeqCst) {
+                debug!("Status update during an amendment");
+                return Err(BasketError::AmendmentInProgress.into());
+            }
+
+            // Only the workflow takes payment, and only the courier moves a delivery
+            // order on once it is ready
+            let current = validator_state.lock().unwrap().clone();
+            if let Err(e) = current.check_restaurant_transition(&status) {
+                debug!("Invalid status transition: {}", e);
+                return Err(e.into());
+            }
+
+            // A busy kitchen takes orders in turn
+            if status == OrderStatus::Preparing {
+                let slot = current.kitchen_slot.as_ref().map_or(Ok(()), KitchenSlot::check_can_prepare);
+                if let Err(e) = slot {
+                    debug!("No kitchen slot yet: {}", e);
+                    return Err(e.into());
+                }
+            }
+
+            Ok(())
+        },
+        move |u: &mut UpdateContext, input: String| {
+            let ctx = u.wf_ctx.clone();
+            let state = update_state.clone();
+            let progress = update_progress.clone();
+            let in_flight = update_in_flight.clone();
+            // Already checked by the validator
+            let status = input.parse::<OrderStatus>().map_err(|e| anyhow!(e));
+            in_flight.fetch_add(1, Ordering::SeqCst);
+            async move {
+                let result = match status {
+                    Ok(status) => apply_status(&ctx, &state, &progress, status).await,
+                    Err(e) => Err(e),
+                };
+                in_flight.fetch_sub(1, Ordering::SeqCst);
+
+                result
+            }
+        },
+    );
+
+    // Show the starter's basket with its price, unless the customer has changed it
+    // while it was priced
+    let has_products = !state.lock().unwrap().products.is_empty();
+    if has_products {
+        if let Err(e) = change_basket(&ctx, &state, &checked_out, None, BasketChange::Reprice).await {
+            warn!("Unable to price initial basket: {}", e);
+        }
+    }
+
+    // Wait for the customer to check out, with the CHECKOUT update or signal, unless they
+    // cancel first
+    let amount = loop {
+        let finished = pin!(ctx.wait_condition(|| {
+            to_charge.lock().unwrap().is_some() || state.lock().unwrap().status == OrderStatus::Cancelled
+        }));
+        match select(checkout.next(), finished).await {
+            Either::Left((Some(_), _)) => {
+                // The signal has no validator, so it is checked here instead
+                if let Err(e) = check_checkout(&state, &checked_out) {
+                    warn!("Ignoring CHECKOUT signal: {}", e);
+                    continue;
+                }
+                checked_out.store(true, Ordering::SeqCst);
+                let _ = checkout_basket(&ctx, &state, &checked_out, &to_charge).await;
+            }
+            Either::Left((None, _)) => return Err(anyhow!("checkout signal channel closed")),
+            Either::Right(_) => break *to_charge.lock().unwrap(),
+        }
+    };
+    let Some(amount) = amount else {
+        // Nothing was paid, so once the customer has been told there is nothing to do
+        ctx.wait_condition(|| updates_in_flight.load(Ordering::SeqCst) == 0).await;
+        let snapshot = state.lock().unwrap().clone();
+        info!("Order cancelled before checkout");
+        return Ok(WfExitValue::Normal(progress.lock().unwrap().outcome(snapshot)?));
+    };
+    info!("Order checked out for {}", amount);
+
+    // Take payment - a declined card ends the order, it is not a workflow failure
+    match take_payment(&ctx, amount).await? {
+        Payment::Taken(capture) => {
+            let mut state = state.lock().unwrap();
+            state.transactions.push(Transaction::Charge(capture.clone()));
+            state.payment = Some(capture);
+
+            let delivery_minutes = match (&state.delivery_address, state.collection) {
+                (Some(address), false) => delivery_times.minutes_to(&address.post_code),
+                _ => 0,
+            };
+            state.eta = Some(Eta::new(delivery_minutes));
+        }
+        Payment::Declined(reason) => {
+            warn!("Payment declined: {}", reason);
+            state.lock().unwrap().decline_reason = Some(reason);
+            let snapshot = set_status(&ctx, &state, &progress, OrderStatus::PaymentDeclined);
+
+            send_text_message(&ctx, &snapshot, &progress).await?;
+
+            return Ok(WfExitValue::Normal(progress.lock().unwrap().outcome(snapshot)?));
+        }
+    }
 
     // Set order status to pending
-    state.status = OrderStatus::Pending;
+    let snapshot = set_status(&ctx, &state, &progress, OrderStatus::Pending);
 
     // Send notification
+    send_text_message(&ctx, &snapshot, &progress).await?;
+
+    // Wait for the restaurant to complete or reject the order, holding it to the
+    // deadline for each status on the way. The policy is loaded once, so the order is
+    // held to the same deadlines throughout, and on replay.
+    let sla = load_sla_policy(&ctx).await?;
+    loop {
+        let (status, delivery_address) = {
+            let state = state.lock().unwrap();
+            (state.status.clone(), state.delivery_address.clone())
+        };
+        if status.is_terminal() {
+            break;
+        }
+
+        // Delivery orders are handed to a courier once the kitchen has them ready. The
+        // courier workflow keeps its own time, so there are no deadlines while it runs.
+        if let (OrderStatus::Ready, Some(address)) = (&status, delivery_address) {
+            deliver(&ctx, &state, &progress, &mut delivery_progress, address).await?;
+            continue;
+        }
+
+        // The kitchen keeps the order up to date with its place in the queue while the
+        // restaurant has it
+        let Some(deadline) = sla.deadline_for(&status) else {
+            let changed = ctx.wait_condition(|| state.lock().unwrap().status != status);
+            following_kitchen(&ctx, &state, &progress, &mut kitchen_slots, changed).await;
+            continue;
+        };
+
+        let changed = status_changed_within(&ctx, &state, &status, deadline);
+        if following_kitchen(&ctx, &state, &progress, &mut kitchen_slots, changed).await {
+            continue;
+        }
+
+        warn!("Order has been {} for longer than {:?}", status, deadline.within());
+        match deadline.on_breach {
+            Breach::Reject => {
+                // The restaurant may have moved the order on as the deadline passed, and
+                // a change the customer is paying for must finish before it is refunded
+                ctx.wait_condition(|| !amending.load(Ordering::SeqCst)).await;
+                if state.lock().unwrap().status == status {
+                    apply_status(&ctx, &state, &progress, OrderStatus::Rejected).await?;
+                }
+            }
+            Breach::Escalate => {
+                let snapshot = state.lock().unwrap().clone();
+                escalate_order(&ctx, &snapshot, &progress).await?;
+                let changed = ctx.wait_condition(|| state.lock().unwrap().status != status);
+                following_kitchen(&ctx, &state, &progress, &mut kitchen_slots, changed).await;
+            }
+        }
+    }
+
+    // Let every update that moved the order on complete its refunds and notifications
+    ctx.wait_condition(|| updates_in_flight.load(Ordering::SeqCst) == 0).await;
+
+    // Completed orders are sent their receipt once the customer knows they are complete
+    if state.lock().unwrap().status == OrderStatus::Completed {
+        send_receipt(&ctx, &state, &progress).await;
+    }
+
+    let state = state.lock().unwrap().clone();
+    info!("Order workflow finished with status: {}", state.status);
+    Ok(WfExitValue::Normal(progress.lock().unwrap().outcome(state)?))
+}
+
+/// Everything the outcome needs that is not kept on the order itself
+#[derive(Default)]
+struct Progress {
+    transitions: Vec<StatusChange>,
+    notification_ids: Vec<String>,
+    delivery: Option<DeliveryReport>,
+}
+
+impl Progress {
+    /// Note the order moved to `status` now, in workflow time so it is the same on
+    /// replay, and keep the changes so far in the order's memo
+    fn record(&mut self, ctx: &WfContext, status: OrderStatus) {
+        self.transitions.push(StatusChange {
+            status,
+            at: workflow_now(ctx),
+        });
+
+        match self.transitions.as_json_payload() {
+            Ok(payload) => ctx.upsert_memo([(Memos::STATUS_CHANGES.to_string(), payload)]),
+            Err(e) => warn!("Status changes could not be kept in the memo: {}", e),
+        }
+    }
+
+    /// When the order moved to its current status
+    fn since(&self) -> DateTime<Utc> {
+        self.transitions.last().map(|t| t.at).unwrap_or_default()
+    }
+
+    fn outcome(&self, order: OrderState) -> Result<OrderOutcome> {
+        Ok(OrderOutcome {
+            status: order.status.clone(),
+            charged: total_charged(&order.transactions)?,
+            refunded: total_refunded(&order.transactions)?,
+            transitions: self.transitions.clone(),
+            notification_ids: self.notification_ids.clone(),
+            delivery: self.delivery.clone(),
+            order,
+        })
+    }
+}
+
+/// Move the order to a status from the restaurant, refunding the customer if the
+/// kitchen rejected it, and let the customer know. Accepted orders queue for a kitchen
+/// slot, which they give up once the food is ready or the order is rejected.
+async fn apply_status(
+    ctx: &WfContext,
+    state: &Mutex<OrderState>,
+    progress: &Mutex<Progress>,
+    status: OrderStatus,
+) -> Result<()> {
+    info!("Updating order status: {}", status);
+
+    // Another update may have moved the order on since this one was validated
+    let current = state.lock().unwrap().clone();
+    current.check_transition(&status)?;
+
+    let snapshot = set_status(ctx, state, progress, status);
+
+    match snapshot.status {
+        OrderStatus::Accepted => request_kitchen_slot(ctx, state, &snapshot).await,
+        OrderStatus::Preparing => {}
+        _ if snapshot.kitchen_slot.is_some() => release_kitchen_slot(ctx, state, &snapshot).await,
+        _ => {}
+    }
+
+    if snapshot.status == OrderStatus::Rejected {
+        info!("Order rejected, refunding payment");
+        if snapshot.payment.is_none() {
+            return Err(anyhow!("no payment to refund"));
+        }
+
+        refund_in_full(ctx, state, &snapshot).await?;
+    }
+
+    // A ticket that will not print should not hold the order up - the kitchen can still
+    // see it on the admin page
+    if snapshot.status == OrderStatus::Accepted {
+        let ticket = KitchenTicket::for_order(&ctx.workflow_initial_info().workflow_id, &snapshot);
+        if let Err(e) = print_kitchen_ticket(ctx, &ticket).await {
+            warn!("Unable to print kitchen ticket: {}", e);
+        }
+    }
+
+    send_text_message(ctx, &snapshot, progress)
+        .await
+        .map_err(|e| anyhow!("error notifying of status change: {}", e))
+}
+
+/// Cancel the order for the customer. A paid-for order has been sent to the restaurant,
+/// so it is refunded and the restaurant is told not to cook it; the customer is told
+/// either way.
+async fn cancel_order(ctx: &WfContext, state: &Mutex<OrderState>, progress: &Mutex<Progress>) -> Result<()> {
+    // The restaurant may have accepted the order since this update was validated
+    let status = state.lock().unwrap().status.clone();
+    if !status.is_cancellable() {
+        return Err(CancelError::TooLate(status).into());
+    }
+
+    info!("Order cancelled by the customer");
+    let snapshot = set_status(ctx, state, progress, OrderStatus::Cancelled);
+
+    if snapshot.payment.is_some() {
+        refund_in_full(ctx, state, &snapshot).await?;
+
+        // As with tickets, a notice that will not print should not hold up the refund
+        let notice = KitchenTicket::cancellation(&ctx.workflow_initial_info().workflow_id, &snapshot);
+        if let Err(e) = print_kitchen_ticket(ctx, &notice).await {
+            warn!("Unable to print cancellation notice: {}", e);
+        }
+    }
+
+    send_text_message(ctx, &snapshot, progress)
+        .await
+        .map_err(|e| anyhow!("error notifying of cancellation: {}", e))
+}
+
+/// Refund everything the customer has paid, including for amendments, less anything
+/// already refunded
+async fn refund_in_full(ctx: &WfContext, state: &Mutex<OrderState>, snapshot: &OrderState) -> Result<()> {
+    let paid = net_paid(&snapshot.transactions)?;
+    for request in plan_refunds(&snapshot.transactions, paid) {
+        let refund = refund_payment(ctx, &request).await?;
+        let mut state = state.lock().unwrap();
+        state.transactions.push(Transaction::Refund(refund));
+        publish_order(ctx, &state);
+    }
+
+    Ok(())
+}
+
+/// Queue the order for a slot in its kitchen. A kitchen that is not taking requests
+/// is not throttled, so the order can be prepared straight away.
+async fn request_kitchen_slot(ctx: &WfContext, state: &Mutex<OrderState>, order: &OrderState) {
+    // Set before anything is awaited, so the restaurant cannot start preparing first
+    set_kitchen_slot(ctx, state, Some(KitchenSlot::Requested));
+
+    let request = KitchenRequest::Request {
+        order_id: ctx.workflow_initial_info().workflow_id.clone(),
+    };
+    if let Err(e) = signal_kitchen(ctx, order.restaurant_id.as_deref(), &request).await {
+        warn!("Kitchen is not taking requests, so the order is not throttled: {}", e);
+        set_kitchen_slot(ctx, state, None);
+    }
+}
+
+/// Give up the order's kitchen slot, or its place in the queue
+async fn release_kitchen_slot(ctx: &WfContext, state: &Mutex<OrderState>, order: &OrderState) {
+    set_kitchen_slot(ctx, state, None);
+
+    let request = KitchenRequest::Release {
+        order_id: ctx.workflow_initial_info().workflow_id.clone(),
+    };
+    if let Err(e) = signal_kitchen(ctx, order.restaurant_id.as_deref(), &request).await {
+        warn!("Unable to release kitchen slot: {}", e);
+    }
+}
+
+/// Set or clear the order's kitchen slot, publishing the change
+fn set_kitchen_slot(ctx: &WfContext, state: &Mutex<OrderState>, slot: Option<KitchenSlot>) {
+    let mut state = state.lock().unwrap();
+    state.kitchen_slot = slot;
+    publish_order(ctx, &state);
+}
+
+/// Send a request to the kitchen workflow for the order's restaurant
+async fn signal_kitchen(ctx: &WfContext, restaurant_id: Option<&str>, request: &KitchenRequest) -> Result<()> {
+    let kitchen_id = kitchen_workflow_id(restaurant_id);
+    ctx.signal_workflow(SignalWorkflowOptions::new(
+        kitchen_id.as_str(),
+        "",
+        Signals::KITCHEN,
+        [request.as_json_payload()?],
+    ))
+    .await
+    .map_err(|f| anyhow!("error signalling kitchen {}: {}", kitchen_id, f.message))?;
+
+    Ok(())
+}
+
+/// Run `until`, keeping the order, and its ETA, up to date with what the kitchen says
+/// about its slot
+async fn following_kitchen<T>(
+    ctx: &WfContext,
+    state: &Mutex<OrderState>,
+    progress: &Mutex<Progress>,
+    updates: &mut DrainableSignalStream,
+    until: impl Future<Output = T>,
+) -> T {
+    let mut until = pin!(until);
+    loop {
+        match select(until.as_mut(), updates.next()).await {
+            Either::Left((result, _)) => return result,
+            Either::Right((Some(signal), _)) => {
+                let Some(slot) = signal.input.first().and_then(|p| KitchenSlot::from_json_payload(p).ok()) else {
+                    warn!("Ignoring malformed kitchen slot update");
+                    continue;
+                };
+
+                // The kitchen may have sent this before the order gave its slot up
+                let mut state = state.lock().unwrap();
+                if state.kitchen_slot.is_some() {
+                    debug!("Kitchen slot is now {:?}", slot);
+                    state.kitchen_slot = Some(slot);
+                    state.revise_eta(progress.lock().unwrap().since(), workflow_now(ctx));
+                    publish_order(ctx, &state);
+                }
+            }
+            Either::Right((None, _)) => return until.await,
+        }
+    }
+}
+
+/// Hand a ready delivery order to a courier workflow, following its courier until the
+/// food is dropped off, then complete the order. An order no courier delivers is
+/// refunded rather than failing the workflow.
+async fn deliver(
+    ctx: &WfContext,
+    state: &Mutex<OrderState>,
+    progress: &Mutex<Progress>,
+    updates: &mut DrainableSignalStream,
+    address: Address,
+) -> Result<()> {
+    let order_id = ctx.workflow_initial_info().workflow_id.clone();
+    let job = DeliveryJob {
+        order_id: order_id.clone(),
+        address,
+    };
+    let started = ctx
+        .child_workflow(ChildWorkflowOptions {
+            workflow_id: courier_workflow_id(&order_id),
+            workflow_type: "courier_workflow".to_string(),
+            input: vec![job.as_json_payload()?],
+            ..Default::default()
+        })
+        .start(ctx)
+        .await
+        .into_started();
+    let Some(courier) = started else {
+        warn!("Courier workflow did not start");
+        return fail_delivery(ctx, state, progress, "no courier was available").await;
+    };
+
+    let mut delivered = pin!(courier.result());
+    let result = loop {
+        match select(delivered.as_mut(), updates.next()).await {
+            Either::Left((result, _)) => break result,
+            Either::Right((Some(signal), _)) => {
+                // Anyone can signal the order, so a bad signal must not fail it once paid for
+                let Some(update) = signal.input.first().and_then(|p| DeliveryProgress::from_json_payload(p).ok())
+                else {
+                    warn!("Ignoring malformed delivery progress update");
+                    continue;
+                };
+                track_delivery(ctx, state, progress, update).await?;
+            }
+            Either::Right((None, _)) => return Err(anyhow!("delivery progress signal channel closed")),
+        }
+    };
+
+    let report = match result.status {
+        Some(child_workflow_result::Status::Completed(success)) => {
+            let payload = success
+                .result
+                .ok_or_else(|| anyhow!("courier workflow returned no payload"))?;
+            DeliveryReport::from_json_payload(&payload)?
+        }
+        status => {
+            warn!("Courier workflow did not deliver the order: {:?}", status);
+            return fail_delivery(ctx, state, progress, "no courier could deliver it").await;
+        }
+    };
+    info!("Order delivered by courier {}", report.courier_id);
+
+    // The pickup may arrive in the same workflow task as the drop-off
+    let picked_up = DeliveryProgress {
+        courier_id: report.courier_id.clone(),
+        stage: DeliveryStage::PickedUp,
+    };
+    track_delivery(ctx, state, progress, picked_up).await?;
+    progress.lock().unwrap().delivery = Some(report);
+
+    apply_status(ctx, state, progress, OrderStatus::Completed).await
+}
+
+/// End an order no courier delivered, refunding the customer and telling them why
+async fn fail_delivery(
+    ctx: &WfContext,
+    state: &Mutex<OrderState>,
+    progress: &Mutex<Progress>,
+    reason: &str,
+) -> Result<()> {
+    state.lock().unwrap().delivery_failure = Some(reason.to_string());
+    let snapshot = set_status(ctx, state, progress, OrderStatus::DeliveryFailed);
+
+    info!("Delivery failed, refunding payment");
+    refund_in_full(ctx, state, &snapshot).await?;
+
+    send_text_message(ctx, &snapshot, progress)
+        .await
+        .map_err(|e| anyhow!("error notifying of failed delivery: {}", e))
+}
+
+/// Keep the order up to date with its courier, sending it out for delivery when the
+/// food is picked up
+async fn track_delivery(
+    ctx: &WfContext,
+    state: &Mutex<OrderState>,
+    progress: &Mutex<Progress>,
+    update: DeliveryProgress,
+) -> Result<()> {
+    match update.stage {
+        DeliveryStage::Assigned => info!("Courier {} assigned", update.courier_id),
+        DeliveryStage::PickedUp => info!("Courier {} has the order", update.courier_id),
+    }
+
+    let ready = {
+        let mut state = state.lock().unwrap();
+        state.courier_id = Some(update.courier_id);
+        publish_order(ctx, &state);
+        state.status == OrderStatus::Ready
+    };
+
+    if update.stage == DeliveryStage::PickedUp && ready {
+        let snapshot = set_status(ctx, state, progress, OrderStatus::OutForDelivery);
+        send_text_message(ctx, &snapshot, progress).await?;
+    }
+
+    Ok(())
+}
+
+/// Race the deadline for `status` against the restaurant moving the order on,
+/// returning whether the order moved in time
+async fn status_changed_within(
+    ctx: &WfContext,
+    state: &Mutex<OrderState>,
+    status: &OrderStatus,
+    deadline: &Deadline,
+) -> bool {
+    let timer = pin!(ctx.timer(TimerOptions {
+        duration: deadline.within(),
+        summary: Some(format!("{} deadline", status)),
+    }));
+    let changed = pin!(ctx.wait_condition(|| state.lock().unwrap().status != *status));
+
+    match select(timer, changed).await {
+        Either::Left(_) => false,
+        Either::Right((_, timer)) => {
+            timer.cancel(ctx);
+            true
+        }
+    }
+}
+
+/// How an attempt to take payment ended
+enum Payment {
+    Taken(Capture),
+    Declined(String),
+}
+
+/// Charge the customer. The gateway's declines are non-retryable, so they come back
+/// here as a business outcome; anything else that reaches the workflow is an error.
+async fn take_payment(ctx: &WfContext, amount: Amount) -> Result<Payment> {
+    let resolution = ctx
+        .activity(ActivityOptions {
+            activity_type: "take_payment".to_string(),
+            input: amount.as_json_payload()?,
+            start_to_close_timeout: Some(Duration::from_secs(60)),
+            ..Default::default()
+        })
+        .await;
+
+    match resolution.status {
+        Some(activity_resolution::Status::Completed(success)) => {
+            let payload = success
+                .result
+                .ok_or_else(|| anyhow!("take_payment returned no payload"))?;
+            Ok(Payment::Taken(Capture::from_json_payload(&payload)?))
+        }
+        Some(activity_resolution::Status::Failed(failed)) => {
+            let failure = failed.failure.unwrap_or_default();
+            match non_retryable_cause(&failure) {
+                Some(cause) => Ok(Payment::Declined(cause.message.clone())),
+                None => Err(anyhow!("error taking payment: {}", failure.message)),
+            }
+        }
+        status => Err(anyhow!("error taking payment: {:?}", status)),
+    }
+}
+
+/// Give the customer back money taken by one charge
+async fn refund_payment(ctx: &WfContext, request: &RefundRequest) -> Result<Refund> {
+    let payload = ctx
+        .activity(ActivityOptions {
+            activity_type: "refund_payment".to_string(),
+            input: request.as_json_payload()?,
+            start_to_close_timeout: Some(Duration::from_secs(60)),
+            ..Default::default()
+        })
+        .await
+        .success_payload_or_error()
+        .map_err(|e| anyhow!("error refunding payment: {}", e))?
+        .ok_or_else(|| anyhow!("refund_payment returned no payload"))?;
+
+    Ok(Refund::from_json_payload(&payload)?)
+}
+
+/// Look up a discount code. Codes the store does not know are the customer's mistake,
+/// so they come back with the store's reason rather than as an activity failure.
+async fn find_promotion(ctx: &WfContext, code: &str) -> Result<Promotion> {
+    let resolution = ctx
+        .activity(ActivityOptions {
+            activity_type: "find_promotion".to_string(),
+            input: code.as_json_payload()?,
+            start_to_close_timeout: Some(Duration::from_secs(30)),
+            ..Default::default()
+        })
+        .await;
+
+    match resolution.status {
+        Some(activity_resolution::Status::Completed(success)) => {
+            let payload = success
+                .result
+                .ok_or_else(|| anyhow!("find_promotion returned no payload"))?;
+            Ok(Promotion::from_json_payload(&payload)?)
+        }
+        Some(activity_resolution::Status::Failed(failed)) => {
+            let failure = failed.failure.unwrap_or_default();
+            match non_retryable_cause(&failure) {
+                Some(cause) => Err(anyhow!("{}", cause.message)),
+                None => Err(anyhow!("error finding promotion: {}", failure.message)),
+            }
+        }
+        status => Err(anyhow!("error finding promotion: {:?}", status)),
+    }
+}
+
+/// Load the deadlines the order is held to from the worker, recording them in history
+async fn load_sla_policy(ctx: &WfContext) -> Result<SlaPolicy> {
+    let resolution = ctx
+        .activity(ActivityOptions {
+            activity_type: "load_sla_policy".to_string(),
+            input: ().as_json_payload()?,
+            start_to_close_timeout: Some(Duration::from_secs(30)),
+            ..Default::default()
+        })
+        .await;
+
+    match resolution.status {
+        Some(activity_resolution::Status::Completed(success)) => {
+            let payload = success
+                .result
+                .ok_or_else(|| anyhow!("load_sla_policy returned no payload"))?;
+            Ok(SlaPolicy::from_json_payload(&payload)?)
+        }
+        status => Err(anyhow!("error loading SLA policy: {:?}", status)),
+    }
+}
+
+/// The application error an activity gave up with, if it asked not to be retried
+fn non_retryable_cause(failure: &Failure) -> Option<&Failure> {
+    match &failure.failure_info {
+        Some(FailureInfo::ApplicationFailureInfo(info)) if info.non_retryable => Some(failure),
+        _ => failure.cause.as_deref().and_then(non_retryable_cause),
+    }
+}
+
+/// Items can change before checkout, and be amended once paid for until the restaurant
+/// accepts the order - one amendment at a time
+fn check_basket_changeable(checked_out: &AtomicBool, amending: &AtomicBool, state: &Mutex<OrderState>) -> Result<()> {
+    if !checked_out.load(Ordering::SeqCst) {
+        return Ok(());
+    }
+
+    match state.lock().unwrap().status {
+        OrderStatus::Default => {
+            debug!("Basket update while checking out");
+            Err(BasketError::CheckedOut.into())
+        }
+        OrderStatus::Pending if amending.load(Ordering::SeqCst) => {
+            debug!("Basket update during another amendment");
+            Err(BasketError::AmendmentInProgress.into())
+        }
+        OrderStatus::Pending => Ok(()),
+        OrderStatus::Cancelled => {
+            debug!("Basket update after the order was cancelled");
+            Err(BasketError::Cancelled.into())
+        }
+        _ => {
+            debug!("Basket update after the order was accepted");
+            Err(BasketError::AmendmentClosed.into())
+        }
+    }
+}
+
+/// Customers can cancel until the restaurant accepts the order, but not while it is
+/// being paid for or changed
+fn check_cancellable(
+    state: &Mutex<OrderState>,
+    checked_out: &AtomicBool,
+    amending: &AtomicBool,
+) -> Result<(), CancelError> {
+    let status = state.lock().unwrap().status.clone();
+    if !status.is_cancellable() {
+        return Err(CancelError::TooLate(status));
+    }
+    if status == OrderStatus::Default && checked_out.load(Ordering::SeqCst) {
+        return Err(CancelError::CheckingOut);
+    }
+    if amending.load(Ordering::SeqCst) {
+        return Err(CancelError::Amending);
+    }
+
+    Ok(())
+}
+
+/// A basket can be checked out once, if it has something in it and the order can be
+/// fulfilled as it stands
+fn check_checkout(state: &Mutex<OrderState>, checked_out: &AtomicBool) -> Result<()> {
+    let state = state.lock().unwrap();
+    if state.status == OrderStatus::Cancelled {
+        return Err(BasketError::Cancelled.into());
+    }
+    check_basket_open(checked_out)?;
+    if state.products.is_empty() {
+        return Err(BasketError::Empty.into());
+    }
+    state.fulfilment().validate()?;
+
+    Ok(())
+}
+
+/// Price the checked out basket to be charged, reopening it if it cannot be priced
+async fn checkout_basket(
+    ctx: &WfContext,
+    state: &Mutex<OrderState>,
+    checked_out: &AtomicBool,
+    to_charge: &Mutex<Option<Amount>>,
+) -> Result<Amount> {
+    let result = price_for_payment(ctx, state).await;
+    match &result {
+        Ok(amount) => *to_charge.lock().unwrap() = Some(*amount),
+        Err(e) => {
+            warn!("Unable to check out basket: {}", e);
+            checked_out.store(false, Ordering::SeqCst);
+        }
+    }
+
+    result
+}
+
+/// Price the checked out basket, returning what the customer will be charged. The
+/// discount is worked out the same way as the live price, so the customer pays what
+/// the order's status showed them.
+async fn price_for_payment(ctx: &WfContext, state: &Mutex<OrderState>) -> Result<Amount> {
+    let (products, restaurant_id) = {
+        let state = state.lock().unwrap();
+        (state.products.clone(), state.restaurant_id.clone())
+    };
+    let priced = price_basket(ctx, restaurant_id.as_deref(), &products).await?;
+
+    let mut state = state.lock().unwrap();
+    let priced = state.discounted(priced);
+    if let (Some(promotion), None) = (&state.promotion, &priced.discount) {
+        warn!("Basket no longer qualifies for discount code {}", promotion.code);
+    }
+    let amount = priced.total();
+    state.pricing = Some(priced);
+    publish_order(ctx, &state);
+
+    Ok(amount)
+}
+
+/// Mark an amendment as started if the basket has been checked out. This happens
+/// before the handler runs, so the next update's validator sees it.
+fn start_amendment(checked_out: &AtomicBool, amending: &Arc<AtomicBool>) -> Option<Arc<AtomicBool>> {
+    if !checked_out.load(Ordering::SeqCst) {
+        return None;
+    }
+
+    amending.store(true, Ordering::SeqCst);
+    Some(amending.clone())
+}
+
+/// A change the customer makes to their basket
+enum BasketChange {
+    Add(OrderProduct),
+    Remove(OrderProduct),
+    /// Nothing but a fresh price, for the basket the order was started with
+    Reprice,
+}
+
+impl BasketChange {
+    fn apply(&self, order: &mut OrderState) -> Result<(), BasketError> {
+        match self {
+            BasketChange::Add(item) => order.add_item(item.clone())?,
+            BasketChange::Remove(item) => order.remove_item(item.clone()),
+            BasketChange::Reprice => {}
+        }
+        Ok(())
+    }
+
+    /// Products can only be added while they are in stock
+    fn check(&self, priced: &PricedOrder) -> Result<()> {
+        if let BasketChange::Add(item) = self {
+            item.check_in_stock(priced)?;
+        }
+        Ok(())
+    }
+}
+
+/// Change the basket, returning the new basket. Before checkout the changed basket is
+/// priced by the `price_basket` activity and kept with its price; after it, the change
+/// is an amendment to the paid-for order.
+async fn change_basket(
+    ctx: &WfContext,
+    state: &Mutex<OrderState>,
+    checked_out: &AtomicBool,
+    amendment: Option<Arc<AtomicBool>>,
+    change: BasketChange,
+) -> Result<Vec<OrderProduct>> {
+    if let Some(amending) = amendment {
+        let mut amended = state.lock().unwrap().clone();
+        change.apply(&mut amended)?;
+        let result = amend_order(ctx, state, amended, &change).await;
+        amending.store(false, Ordering::SeqCst);
+
+        return result;
+    }
+
+    // Another change may land while this one is priced, so it is priced again against
+    // the basket as it is now until nothing else got in first
+    loop {
+        let (before, mut changed) = {
+            let state = state.lock().unwrap();
+            (state.products.clone(), state.clone())
+        };
+        change.apply(&mut changed)?;
+        let priced = price_basket(ctx, changed.restaurant_id.as_deref(), &changed.products).await?;
+        change.check(&priced)?;
+
+        // The customer may have checked out while the basket was priced
+        check_basket_open(checked_out)?;
+        let mut state = state.lock().unwrap();
+        if state.products != before {
+            debug!("Basket changed while it was priced");
+            continue;
+        }
+        let priced = state.discounted(priced);
+        state.products = changed.products;
+        state.pricing = Some(priced);
+        publish_order(ctx, &state);
+        return Ok(state.products.clone());
+    }
+}
+
+/// Apply a promotion to the basket as the `price_basket` activity prices it, so the
+/// discount is worked out from a recorded activity result and is the same on replay
+async fn discount_basket(
+    ctx: &WfContext,
+    state: &Mutex<OrderState>,
+    checked_out: &AtomicBool,
+    promotion: Promotion,
+) -> Result<AppliedDiscount> {
+    // The basket may change while it is priced, so it is priced again until it doesn't
+    loop {
+        let (restaurant_id, products) = {
+            let state = state.lock().unwrap();
+            (state.restaurant_id.clone(), state.products.clone())
+        };
+        if products.is_empty() {
+            return Err(BasketError::Empty.into());
+        }
+        let priced = price_basket(ctx, restaurant_id.as_deref(), &products).await?;
+        let discount = promotion.apply(&priced)?;
+
+        // The customer may have checked out while the basket was priced
+        check_basket_open(checked_out)?;
+        let mut state = state.lock().unwrap();
+        if state.products != products {
+            debug!("Basket changed while it was priced");
+            continue;
+        }
+
+        info!("Applying discount code {}", promotion.code);
+        state.promotion = Some(promotion);
+        let priced = state.discounted(priced);
+        state.pricing = Some(priced);
+        publish_order(ctx, &state);
+        return Ok(discount);
+    }
+}
+
+/// Amend a paid-for order: price the new basket as at checkout, then charge the
+/// customer the difference or refund it, recording each transaction on the order. A
+/// declined charge leaves the order as it was.
+async fn amend_order(
+    ctx: &WfContext,
+    state: &Mutex<OrderState>,
+    amended: OrderState,
+    change: &BasketChange,
+) -> Result<Vec<OrderProduct>> {
+    if amended.products.is_empty() {
+        return Err(BasketError::Empty.into());
+    }
+
+    let priced = price_basket(ctx, amended.restaurant_id.as_deref(), &amended.products).await?;
+    change.check(&priced)?;
+    let priced = amended.discounted(priced);
+
+    // A status update validated alongside this amendment may have got in first
+    if state.lock().unwrap().status != OrderStatus::Pending {
+        return Err(BasketError::AmendmentClosed.into());
+    }
+
+    let paid = net_paid(&amended.transactions)?;
+    let total = priced.total();
+    info!("Amending order from {} to {}", paid, total);
+
+    let mut transactions = Vec::new();
+    if total.amount_in_pence > paid.amount_in_pence {
+        let difference = Amount {
+            amount_in_pence: total.amount_in_pence - paid.amount_in_pence,
+            currency: total.currency,
+        };
+        match take_payment(ctx, difference).await? {
+            Payment::Taken(capture) => transactions.push(Transaction::Charge(capture)),
+            Payment::Declined(reason) => {
+                warn!("Payment for amendment declined: {}", reason);
+                return Err(anyhow!("payment for the change was declined: {}", reason));
+            }
+        }
+    } else {
+        let difference = Amount {
+            amount_in_pence: paid.amount_in_pence - total.amount_in_pence,
+            currency: total.currency,
+        };
+        for request in plan_refunds(&amended.transactions, difference) {
+            transactions.push(Transaction::Refund(refund_payment(ctx, &request).await?));
+        }
+    }
+
+    let snapshot = {
+        let mut state = state.lock().unwrap();
+        state.products = amended.products;
+        state.pricing = Some(priced);
+        state.transactions.extend(transactions);
+        state.clone()
+    };
+    publish_order(ctx, &snapshot);
+
+    Ok(snapshot.products)
+}
+
+/// Items can only change before checkout
+fn check_basket_open(checked_out: &AtomicBool) -> Result<()> {
+    if checked_out.load(Ordering::SeqCst) {
+        debug!("Basket update after checkout");
+        return Err(BasketError::CheckedOut.into());
+    }
+
+    Ok(())
+}
+
+/// Price the basket from the restaurant's menu, or the central catalogue for orders
+/// without a restaurant. A basket the menu cannot price, say for an unknown product,
+/// comes back with the menu's reason.
+async fn price_basket(ctx: &WfContext, restaurant_id: Option<&str>, products: &[OrderProduct]) -> Result<PricedOrder> {
+    let resolution = ctx
+        .activity(at_restaurant(
+            restaurant_id,
+            ActivityOptions {
+                activity_type: "price_basket".to_string(),
+                input: products.as_json_payload()?,
+                start_to_close_timeout: Some(Duration::from_secs(60)),
+                ..Default::default()
+            },
+        ))
+        .await;
+
+    match resolution.status {
+        Some(activity_resolution::Status::Completed(success)) => {
+            let payload = success
+                .result
+                .ok_or_else(|| anyhow!("price_basket returned no payload"))?;
+            Ok(PricedOrder::from_json_payload(&payload)?)
+        }
+        Some(activity_resolution::Status::Failed(failed)) => {
+            let failure = failed.failure.unwrap_or_default();
+            match non_retryable_cause(&failure) {
+                Some(cause) => Err(anyhow!("{}", cause.message)),
+                None => Err(anyhow!("error pricing basket: {}", failure.message)),
+            }
+        }
+        status => Err(anyhow!("error pricing basket: {:?}", status)),
+    }
+}
+
+/// Print the ticket for an accepted order in its restaurant's kitchen
+async fn print_kitchen_ticket(ctx: &WfContext, ticket: &KitchenTicket) -> Result<()> {
+    ctx.activity(at_restaurant(
+        ticket.restaurant_id.as_deref(),
+        ActivityOptions {
+            activity_type: "print_kitchen_ticket".to_string(),
+            input: ticket.as_json_payload()?,
+            start_to_close_timeout: Some(Duration::from_secs(60)),
+            schedule_to_close_timeout: Some(Duration::from_secs(5 * 60)),
+            ..Default::default()
+        },
+    ))
+    .await
+    .success_payload_or_error()?;
+
+    Ok(())
+}
+
+/// Send an activity the restaurant runs itself to the restaurant's task queue. Orders
+/// without a restaurant are run centrally, by the workflow's own workers.
+fn at_restaurant(restaurant_id: Option<&str>, options: ActivityOptions) -> ActivityOptions {
+    match restaurant_id {
+        Some(restaurant_id) => ActivityOptions {
+            task_queue: Some(restaurant_task_queue(restaurant_id)),
+            schedule_to_start_timeout: Some(RESTAURANT_START_TIMEOUT),
+            ..options
+        },
+        None => options,
+    }
+}
+
+/// The workflow's current time, which is the same on replay
+fn workflow_now(ctx: &WfContext) -> DateTime<Utc> {
+    ctx.workflow_time().map(DateTime::<Utc>::from).unwrap_or_default()
+}
+
+/// Move the shared order to a new status, revising its ETA and publishing it, and
+/// return a copy to send to activities
+fn set_status(
+    ctx: &WfContext,
+    state: &Mutex<OrderState>,
+    progress: &Mutex<Progress>,
+    status: OrderStatus,
+) -> OrderState {
+    let now = workflow_now(ctx);
+    progress.lock().unwrap().record(ctx, status.clone());
+
+    let snapshot = {
+        let mut state = state.lock().unwrap();
+        state.status = status;
+        state.revise_eta(now, now);
+        state.clone()
+    };
+    publish_order(ctx, &snapshot);
+    snapshot
+}
+
+/// Keep the order's search attributes and memo in step with it, so in-flight orders
+/// can be listed by status and shown with their basket without asking the workflow
+fn publish_order(ctx: &WfContext, order: &OrderState) {
+    let attributes = order_search_attributes(order)
+        .into_iter()
+        .filter_map(|(name, value)| Some((name, value.as_json_payload().ok()?)));
+    ctx.upsert_search_attributes(attributes);
+
+    match order.as_json_payload() {
+        Ok(payload) => ctx.upsert_memo([(Memos::ORDER.to_string(), payload)]),
+        Err(e) => warn!("Order could not be kept in its memo: {}", e),
+    }
+}
+
+/// Notify the customer of the order's current status, keeping the IDs of what was sent,
+/// and push it to anyone streaming the order
+async fn send_text_message(ctx: &WfContext, state: &OrderState, progress: &Mutex<Progress>) -> Result<()> {
+    publish_status_changes(ctx, progress).await;
+    notify(ctx, "send_text_message", state, progress).await
+}
+
+/// Push the order's status changes so far to the API. The order does not wait on an API
+/// that is down for longer than a minute, and the next change carries this one anyway.
+async fn publish_status_changes(ctx: &WfContext, progress: &Mutex<Progress>) {
+    let changes = progress.lock().unwrap().transitions.clone();
+    let input = match changes.as_json_payload() {
+        Ok(input) => input,
+        Err(e) => {
+            warn!("Unable to publish status changes: {}", e);
+            return;
+        }
+    };
+
+    let published = ctx
+        .activity(ActivityOptions {
+            activity_type: "publish_status_changes".to_string(),
+            input,
+            start_to_close_timeout: Some(Duration::from_secs(10)),
+            schedule_to_close_timeout: Some(Duration::from_secs(60)),
+            ..Default::default()
+        })
+        .await
+        .success_payload_or_error();
+    if let Err(e) = published {
+        warn!("Unable to publish status changes: {}", e);
+    }
+}
+
+/// Tell everyone the order is overdue in its current status
+async fn escalate_order(ctx: &WfContext, state: &OrderState, progress: &Mutex<Progress>) -> Result<()> {
+    notify(ctx, "escalate_order", state, progress).await
+}
+
+/// Store the order's receipt and email the customer a link to it, keeping where it was
+/// stored on the order. The order is complete either way, so a receipt that cannot be
+/// sent is only logged.
+async fn send_receipt(ctx: &WfContext, state: &Mutex<OrderState>, progress: &Mutex<Progress>) {
+    let snapshot = state.lock().unwrap().clone();
+    match issue_receipt(ctx, &snapshot).await {
+        Ok(sent) => {
+            progress
+                .lock()
+                .unwrap()
+                .notification_ids
+                .extend(sent.notifications.into_iter().map(|n| n.notification_id));
+            state.lock().unwrap().receipt = Some(sent.receipt);
+        }
+        Err(e) => warn!("Unable to send receipt: {}", e),
+    }
+}
+
+/// Run the `send_receipt` activity, giving up if the receipt cannot be stored or sent
+/// within ten minutes
+async fn issue_receipt(ctx: &WfContext, order: &OrderState) -> Result<SentReceipt> {
+    let payload = ctx
+        .activity(ActivityOptions {
+            activity_type: "send_receipt".to_string(),
+            input: order.as_json_payload()?,
+            start_to_close_timeout: Some(Duration::from_secs(60)),
+            schedule_to_close_timeout: Some(Duration::from_secs(10 * 60)),
+            ..Default::default()
+        })
+        .await
+        .success_payload_or_error()?
+        .ok_or_else(|| anyhow!("send_receipt returned no payload"))?;
+
+    Ok(SentReceipt::from_json_payload(&payload)?)
+}
+
+/// Run a notification activity, keeping the IDs of what was sent
+async fn notify(ctx: &WfContext, activity_type: &str, state: &OrderState, progress: &Mutex<Progress>) -> Result<()> {
+    let payload = ctx
+        .activity(ActivityOptions {
+            activity_type: activity_type.to_string(),
+            input: state.as_json_payload()?,
+            start_to_close_timeout: Some(Duration::from_secs(60)),
+            ..Default::default()
+        })
+        .await
+        .success_payload_or_error()?
+        .ok_or_else(|| anyhow!("{} returned no payload", activity_type))?;
+
+    let sent = Vec::<Notification>::from_json_payload(&payload)?;
+    progress
+        .lock()
+        .unwrap()
+        .notification_ids
+        .extend(sent.into_iter().map(|n| n.notification_id));
+
+    Ok(())
+}
+
+/// Delivers an order for `order_workflow`. Couriers are assigned from the pool and must
+/// signal PICKED_UP, then DROPPED_OFF, with their ID within `timeouts`. A courier who
+/// runs out of time is released and the delivery offered to another, up to
+/// `max_reassignments` times; after that, or if no courier can be assigned in time,
+/// the workflow fails so the order is refunded. The order is kept up to date with
+/// DELIVERY_PROGRESS signals.
+pub async fn courier_workflow(
+    ctx: WfContext,
+    job: DeliveryJob,
+    timeouts: Arc<DeliveryTimeouts>,
+) -> Result<WfExitValue<DeliveryReport>, anyhow::Error> {
+    let mut picked_up = ctx.make_signal_channel(Signals::PICKED_UP);
+    let mut dropped_off = ctx.make_signal_channel(Signals::DROPPED_OFF);
+    let mut timed_out = Vec::new();
+
+    loop {
+        let request = AssignCourier {
+            job: job.clone(),
+            avoid: timed_out.clone(),
+        };
+        let courier_id = assign_courier(&ctx, &request, timeouts.assign())
+            .await
+            .map_err(|e| DeliveryError::NotAssigned(e.to_string()))?;
+        report_progress(&ctx, &job, &courier_id, DeliveryStage::Assigned).await?;
+
+        if !signalled_within(&ctx, &mut picked_up, &courier_id, timeouts.pickup(), "pickup").await {
+            warn!("Courier {} did not pick up {} in time", courier_id, job.order_id);
+            release_courier(&ctx, &courier_id).await?;
+            timed_out.push(courier_id);
+            timeouts.check_can_reassign(&timed_out)?;
+            continue;
+        }
+        report_progress(&ctx, &job, &courier_id, DeliveryStage::PickedUp).await?;
+
+        if !signalled_within(&ctx, &mut dropped_off, &courier_id, timeouts.drop_off(), "drop-off").await {
+            warn!("Courier {} did not drop off {} in time", courier_id, job.order_id);
+            release_courier(&ctx, &courier_id).await?;
+            timed_out.push(courier_id);
+            timeouts.check_can_reassign(&timed_out)?;
+            continue;
+        }
+
+        info!("Courier {} dropped off {}", courier_id, job.order_id);
+        release_courier(&ctx, &courier_id).await?;
+        return Ok(WfExitValue::Normal(DeliveryReport { courier_id, timed_out }));
+    }
+}
+
+/// Wait for `courier_id` to signal on `channel`, ignoring other couriers, returning
+/// whether they did before `within` was up
+async fn signalled_within(
+    ctx: &WfContext,
+    channel: &mut DrainableSignalStream,
+    courier_id: &str,
+    within: Duration,
+    stage: &str,
+) -> bool {
+    let mut timer = pin!(ctx.timer(TimerOptions {
+        duration: within,
+        summary: Some(format!("{} by {}", stage, courier_id)),
+    }));
+
+    loop {
+        match select(timer.as_mut(), channel.next()).await {
+            Either::Left(_) => return false,
+            Either::Right((Some(signal), timer)) => {
+                let from = signal.input.first().and_then(|p| String::from_json_payload(p).ok());
+                if from.as_deref() == Some(courier_id) {
+                    timer.cancel(ctx);
+                    return true;
+                }
+                debug!("Ignoring {} from {:?}", stage, from);
+            }
+            Either::Right((None, _)) => return false,
+        }
+    }
+}
+
+/// Find a courier for the delivery, waiting up to `within` for one to be free
+async fn assign_courier(ctx: &WfContext, request: &AssignCourier, within: Duration) -> Result<String> {
+    let payload = ctx
+        .activity(ActivityOptions {
+            activity_type: "assign_courier".to_string(),
+            input: request.as_json_payload()?,
+            start_to_close_timeout: Some(Duration::from_secs(60)),
+            schedule_to_close_timeout: Some(within),
+            ..Default::default()
+        })
+        .await
+        .success_payload_or_error()?
+        .ok_or_else(|| anyhow!("assign_courier returned no payload"))?;
+
+    Ok(String::from_json_payload(&payload)?)
+}
+
+/// Let a courier go
+async fn release_courier(ctx: &WfContext, courier_id: &str) -> Result<()> {
     ctx.activity(ActivityOptions {
-        activity_type: "send_text_message".to_string(),
-        input: serde_json::to_vec(&state.clone())?.into(),
+        activity_type: "release_courier".to_string(),
+        input: courier_id.as_json_payload()?,
         start_to_close_timeout: Some(Duration::from_secs(60)),
         ..Default::default()
     })
     .await
-    .unwrap_ok_payload();
+    .success_payload_or_error()?;
 
-    // For now, just wait a bit and then complete
-    // In a real implementation, this would wait for restaurant updates
-    ctx.timer(temporal_sdk::TimerOptions {
-        duration: Duration::from_secs(10),
-        summary: None,
-    })
-    .await;
+    Ok(())
+}
 
-    // Simulate order completion
-    state.status = OrderStatus::Completed;
+/// Tell the order how far its courier has got
+async fn report_progress(ctx: &WfContext, job: &DeliveryJob, courier_id: &str, stage: DeliveryStage) -> Result<()> {
+    let update = DeliveryProgress {
+        courier_id: courier_id.to_string(),
+        stage,
+    };
 
-    // Send final notification
-    ctx.activity(ActivityOptions {
-        activity_type: "send_text_message".to_string(),
-        input: serde_json::to_vec(&state.clone())?.into(),
-        start_to_close_timeout: Some(Duration::from_secs(60)),
+    ctx.signal_workflow(SignalWorkflowOptions::new(
+        job.order_id.as_str(),
+        "",
+        Signals::DELIVERY_PROGRESS,
+        [update.as_json_payload()?],
+    ))
+    .await
+    .map_err(|f| anyhow!("error updating order {}: {}", job.order_id, f.message))?;
+
+    Ok(())
+}
+
+/// Hands out a kitchen's preparation slots to its orders, first come first served.
+/// Orders send a KITCHEN request for a slot once accepted, and release it once the food
+/// is ready; each is sent a KITCHEN_SLOT signal when it is given a slot, and told where
+/// it is in the queue, and roughly how long it will wait, whenever that changes. The
+/// queue is kept in the kitchen's memo. Runs until it is terminated, continuing as new
+/// with its queue to keep its history short.
+pub async fn kitchen_workflow(ctx: WfContext, queue: KitchenQueue) -> Result<WfExitValue<()>, anyhow::Error> {
+    publish_queue(&ctx, &queue);
+    let queue = Arc::new(Mutex::new(queue));
+    let mut requests = ctx.make_signal_channel(Signals::KITCHEN);
+
+    for _ in 0..KITCHEN_REQUESTS_PER_RUN {
+        let signal = requests
+            .next()
+            .await
+            .ok_or_else(|| anyhow!("kitchen signal channel closed"))?;
+        handle_kitchen_request(&ctx, &queue, &signal.input).await;
+    }
+
+    // Requests that have arrived since are handled before the queue is carried over
+    for signal in requests.drain_all() {
+        handle_kitchen_request(&ctx, &queue, &signal.input).await;
+    }
+
+    let queue = queue.lock().unwrap().clone();
+    info!(
+        "Kitchen continuing as new with {} orders preparing and {} waiting",
+        queue.preparing.len(),
+        queue.waiting.len()
+    );
+    Ok(WfExitValue::continue_as_new(ContinueAsNewWorkflowExecution {
+        arguments: vec![queue.as_json_payload()?],
         ..Default::default()
-    })
+    }))
+}
+
+/// Act on a request to the kitchen, then tell the orders given a slot, and those still
+/// waiting whose place in the queue has changed. An order that cannot be told has
+/// finished, so its slot is given up.
+async fn handle_kitchen_request(ctx: &WfContext, queue: &Mutex<KitchenQueue>, input: &[Payload]) {
+    let Some(request) = input.first().and_then(|p| KitchenRequest::from_json_payload(p).ok()) else {
+        warn!("Ignoring malformed kitchen request");
+        return;
+    };
+
+    let original = queue.lock().unwrap().clone();
+    let mut requests = vec![request];
+    while let Some(request) = requests.pop() {
+        let now = workflow_now(ctx);
+        let (granted, waits) = {
+            let mut queue = queue.lock().unwrap();
+            let before = queue.waits(now);
+            let granted = match request {
+                KitchenRequest::Request { order_id } => {
+                    info!("Order {} wants a kitchen slot", order_id);
+                    let granted = queue.request(&order_id, now);
+                    granted.then(|| vec![order_id]).unwrap_or_default()
+                }
+                KitchenRequest::Release { order_id } => {
+                    info!("Order {} has finished with the kitchen", order_id);
+                    queue.release(&order_id, now)
+                }
+                KitchenRequest::SetCapacity(capacity) => {
+                    info!("Kitchen now has {} slots", capacity.slots);
+                    queue.set_capacity(capacity, now)
+                }
+            };
+            let waits: Vec<_> = queue.waits(now).into_iter().filter(|wait| !before.contains(wait)).collect();
+            (granted, waits)
+        };
+
+        let updates = granted.into_iter().map(|order_id| (order_id, KitchenSlot::Granted)).chain(waits);
+        for (order_id, slot) in updates {
+            if let Err(e) = tell_order(ctx, &order_id, &slot).await {
+                warn!("Giving up the kitchen slot of order {}: {}", order_id, e);
+                requests.push(KitchenRequest::Release { order_id });
+            }
+        }
+    }
+
+    let queue = queue.lock().unwrap();
+    if *queue != original {
+        publish_queue(ctx, &queue);
+    }
+}
+
+/// Keep the kitchen's queue in its memo, so it can be read without asking the workflow
+fn publish_queue(ctx: &WfContext, queue: &KitchenQueue) {
+    match queue.as_json_payload() {
+        Ok(payload) => ctx.upsert_memo([(Memos::KITCHEN_QUEUE.to_string(), payload)]),
+        Err(e) => warn!("Kitchen queue could not be kept in its memo: {}", e),
+    }
+}
+
+/// Tell an order what the kitchen has for it
+async fn tell_order(ctx: &WfContext, order_id: &str, slot: &KitchenSlot) -> Result<()> {
+    ctx.signal_workflow(SignalWorkflowOptions::new(
+        order_id,
+        "",
+        Signals::KITCHEN_SLOT,
+        [slot.as_json_payload()?],
+    ))
     .await
-    .unwrap_ok_payload();
+    .map_err(|f| anyhow!("error signalling order {}: {}", order_id, f.message))?;
 
-    info!("Order workflow completed successfully");
-    Ok(WfExitValue::Normal(()))
+    Ok(())
 }
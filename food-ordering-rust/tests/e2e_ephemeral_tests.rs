@@ -0,0 +1,2382 @@
+/*
+ * Copyright 2025 Simon Emms <simon@simonemms.com>
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+/// End-to-End tests with an ephemeral Temporal server
+/// These spin up their own Temporal server, so they are ignored by default.
+/// Run with: cargo test --test e2e_ephemeral_tests -- --ignored --test-threads=1
+
+use food_ordering_rust::api::{router, ApiState};
+use food_ordering_rust::activities::{
+    assign_courier, escalate_order, find_promotion, load_sla_policy, price_basket, print_kitchen_ticket,
+    publish_status_changes, refund_payment, release_courier, send_receipt, send_text_message, take_payment,
+};
+use food_ordering_rust::capacity::{KitchenCapacity, KitchenQueue, KitchenSlot};
+use food_ordering_rust::catalogue::{FileCatalogue, ProductCatalogue, SampleCatalogue};
+use food_ordering_rust::client::{
+    add_item, apply_discount, cancel_order, checkout, courier_dropped_off, courier_picked_up, get_kitchen_status,
+    get_order_outcome, get_order_status, list_running_orders, remove_discount, remove_item, set_fulfilment,
+    signal_checkout, start_kitchen, start_order, update_order_status, RunningOrder,
+};
+use food_ordering_rust::constants::{restaurant_task_queue, ORDER_FOOD_TASK_QUEUE};
+use food_ordering_rust::couriers::{AssignCourier, DeliveryTimeouts, FakeCourierPool};
+use food_ordering_rust::eta::DeliveryTimes;
+use food_ordering_rust::kitchen::{KitchenTicket, MemoryPrinter, TicketLine};
+use food_ordering_rust::notifications::{Channel, EmailNotifier, MemorySink, NotificationKind, Notifier, SmsNotifier};
+use food_ordering_rust::payments::{net_paid, FakePaymentGateway, Refund, RefundRequest, ScriptedOutcome};
+use food_ordering_rust::pricing::Amount;
+use food_ordering_rust::promotions::LocalPromotions;
+use food_ordering_rust::receipts::{receipt_key, MemoryBlobStore};
+use food_ordering_rust::search::{dev_server_args, OrderFilter};
+use food_ordering_rust::sla::{Breach, Deadline, SlaPolicy};
+use food_ordering_rust::streaming::{NoPublisher, StatusPublisher};
+use food_ordering_rust::types::{
+    Address, Fulfilment, OrderOutcome, OrderProduct, OrderState, OrderStatus, StatusChange,
+};
+use food_ordering_rust::workflows::{
+    courier_workflow, delivery_job_from_args, kitchen_queue_from_args, kitchen_workflow, order_state_from_args,
+    order_workflow,
+};
+use std::sync::Arc;
+use temporal_client::{Client, RetryClient, WfClientExt, WorkflowClientTrait, WorkflowOptions};
+use temporal_sdk::{ActContext, Worker};
+use temporal_sdk_core::{
+    ephemeral_server::{EphemeralServer, TemporalDevServerConfigBuilder, default_cached_download},
+    init_worker, ClientOptionsBuilder, CoreRuntime, Url, WorkerConfigBuilder,
+};
+use temporal_sdk_core_api::{
+    telemetry::TelemetryOptionsBuilder, worker::WorkerVersioningStrategy,
+};
+use temporal_sdk_core_protos::coresdk::{AsJsonPayloadExt, FromJsonPayloadExt};
+use temporal_sdk_core_protos::temporal::api::common::v1::Payloads;
+use temporal_sdk_core_protos::temporal::api::enums::v1::UpdateWorkflowExecutionLifecycleStage;
+use temporal_sdk_core_protos::temporal::api::update::v1::{outcome, WaitPolicy};
+
+/// Start an ephemeral Temporal server and connect a client to it
+async fn start_server() -> (EphemeralServer, RetryClient<Client>) {
+    // order_workflow's search attributes must be registered before it can set them
+    let server_config = TemporalDevServerConfigBuilder::default()
+        .exe(default_cached_download())
+        .extra_args(dev_server_args())
+        .build()
+        .unwrap();
+
+    let server = server_config.start_server().await.unwrap();
+    println!("✅ Ephemeral Temporal server started on {}", server.target);
+
+    let client = ClientOptionsBuilder::default()
+        .identity("e2e-test-worker".to_string())
+        .target_url(Url::parse(&format!("http://{}", server.target)).unwrap())
+        .client_name("food-ordering-e2e-test".to_string())
+        .client_version("0.1.0".to_string())
+        .build()
+        .unwrap()
+        .connect("default", None)
+        .await
+        .unwrap();
+
+    (server, client)
+}
+
+/// The couriers a test worker delivers with, and how long they have
+struct Couriers {
+    pool: Arc<FakeCourierPool>,
+    timeouts: DeliveryTimeouts,
+}
+
+impl Default for Couriers {
+    fn default() -> Self {
+        Self {
+            pool: Arc::new(FakeCourierPool::new(["courier-1", "courier-2"])),
+            timeouts: DeliveryTimeouts::default(),
+        }
+    }
+}
+
+/// A worker with order_workflow registered as the worker binary does, taking payments
+/// through `gateway` and sending texts and emails to `sink`. Orders are held to the
+/// default SLA and delivered by the default couriers unless a test sets its own.
+struct OrderWorker {
+    task_queue: String,
+    gateway: Arc<FakePaymentGateway>,
+    sink: Arc<MemorySink>,
+    sla: SlaPolicy,
+    couriers: Couriers,
+    printer: Arc<MemoryPrinter>,
+    publisher: Arc<dyn StatusPublisher>,
+}
+
+impl OrderWorker {
+    fn new(task_queue: &str, gateway: Arc<FakePaymentGateway>, sink: Arc<MemorySink>) -> Self {
+        Self {
+            task_queue: task_queue.to_string(),
+            gateway,
+            sink,
+            sla: SlaPolicy::default(),
+            couriers: Couriers::default(),
+            printer: Arc::new(MemoryPrinter::new()),
+            publisher: Arc::new(NoPublisher),
+        }
+    }
+
+    fn with_sla(mut self, sla: SlaPolicy) -> Self {
+        self.sla = sla;
+        self
+    }
+
+    fn with_couriers(mut self, couriers: Couriers) -> Self {
+        self.couriers = couriers;
+        self
+    }
+
+    /// Print the central kitchen's tickets to `printer`, for tests that watch it
+    fn with_printer(mut self, printer: Arc<MemoryPrinter>) -> Self {
+        self.printer = printer;
+        self
+    }
+
+    /// Push status changes to `publisher`, for tests that stream them
+    fn with_publisher(mut self, publisher: Arc<dyn StatusPublisher>) -> Self {
+        self.publisher = publisher;
+        self
+    }
+
+    fn build(self, runtime: &CoreRuntime, client: RetryClient<Client>) -> Worker {
+        let Self {
+            task_queue,
+            gateway,
+            sink,
+            sla,
+            couriers,
+            printer,
+            publisher,
+        } = self;
+
+        let worker_config = WorkerConfigBuilder::default()
+            .namespace("default")
+            .task_queue(task_queue.clone())
+            .versioning_strategy(WorkerVersioningStrategy::None {
+                build_id: "e2e-test".to_owned(),
+            })
+            .build()
+            .unwrap();
+
+        let core_worker = init_worker(runtime, worker_config, client).unwrap();
+        let mut worker = Worker::new_from_core(Arc::new(core_worker), task_queue);
+
+        worker.register_wf("order_workflow", |ctx: temporal_sdk::WfContext| async move {
+            let state = order_state_from_args(ctx.get_args())?;
+            order_workflow(ctx, state, Arc::new(DeliveryTimes::default())).await
+        });
+        let timeouts = Arc::new(couriers.timeouts);
+        worker.register_wf("courier_workflow", move |ctx: temporal_sdk::WfContext| {
+            let timeouts = timeouts.clone();
+            async move {
+                let job = delivery_job_from_args(ctx.get_args())?;
+                courier_workflow(ctx, job, timeouts).await
+            }
+        });
+        // Kitchens are only throttled in tests that start their kitchen workflow
+        worker.register_wf("kitchen_workflow", |ctx: temporal_sdk::WfContext| async move {
+            let queue = kitchen_queue_from_args(ctx.get_args())?;
+            kitchen_workflow(ctx, queue).await
+        });
+
+        let sla = Arc::new(sla);
+        worker.register_activity("load_sla_policy", move |ctx: ActContext, _: ()| {
+            let sla = sla.clone();
+            async move { load_sla_policy(ctx, sla, ()).await }
+        });
+
+        // Pricing is never faked - orders are priced from the sample catalogue
+        worker.register_activity("price_basket", |ctx: ActContext, products: Vec<OrderProduct>| async move {
+            price_basket(ctx, Arc::new(SampleCatalogue), products).await
+        });
+        // Orders without a restaurant are cooked in the central kitchen
+        worker.register_activity("print_kitchen_ticket", move |ctx: ActContext, ticket: KitchenTicket| {
+            let printer = printer.clone();
+            async move { print_kitchen_ticket(ctx, printer, ticket).await }
+        });
+
+        // Discount codes are the ones shipped with the crate
+        let promotions = Arc::new(
+            LocalPromotions::load(concat!(env!("CARGO_MANIFEST_DIR"), "/promotions.json")).unwrap(),
+        );
+        worker.register_activity("find_promotion", move |ctx: ActContext, code: String| {
+            let promotions = promotions.clone();
+            async move { find_promotion(ctx, promotions, code).await }
+        });
+
+        let payment_gateway = gateway.clone();
+        worker.register_activity("take_payment", move |ctx: ActContext, amount: Amount| {
+            let gateway = payment_gateway.clone();
+            async move { take_payment(ctx, gateway, amount).await }
+        });
+        worker.register_activity("refund_payment", move |ctx: ActContext, request: RefundRequest| {
+            let gateway = gateway.clone();
+            async move { refund_payment(ctx, gateway, request).await }
+        });
+
+        let pool = couriers.pool;
+        let assign_pool = pool.clone();
+        worker.register_activity("assign_courier", move |ctx: ActContext, request: AssignCourier| {
+            let pool = assign_pool.clone();
+            async move { assign_courier(ctx, pool, request).await }
+        });
+        worker.register_activity("release_courier", move |ctx: ActContext, courier_id: String| {
+            let pool = pool.clone();
+            async move { release_courier(ctx, pool, courier_id).await }
+        });
+
+        let notifiers: Arc<Vec<Arc<dyn Notifier>>> = Arc::new(vec![
+            Arc::new(SmsNotifier::new(sink.clone())),
+            Arc::new(EmailNotifier::new(sink)),
+        ]);
+        let escalation_notifiers = notifiers.clone();
+        worker.register_activity("escalate_order", move |ctx: ActContext, state: OrderState| {
+            let notifiers = escalation_notifiers.clone();
+            async move { escalate_order(ctx, notifiers, state).await }
+        });
+        let receipt_notifiers = notifiers.clone();
+        worker.register_activity("send_receipt", move |ctx: ActContext, state: OrderState| {
+            let notifiers = receipt_notifiers.clone();
+            async move { send_receipt(ctx, Arc::new(MemoryBlobStore::new()), notifiers, state).await }
+        });
+        worker.register_activity("send_text_message", move |ctx: ActContext, state: OrderState| {
+            let notifiers = notifiers.clone();
+            async move { send_text_message(ctx, notifiers, state).await }
+        });
+        worker.register_activity("publish_status_changes", move |ctx: ActContext, changes: Vec<StatusChange>| {
+            let publisher = publisher.clone();
+            async move { publish_status_changes(ctx, publisher, changes).await }
+        });
+
+        worker
+    }
+}
+
+/// Create a worker for a restaurant's own kitchen, as the worker binary does with
+/// `WORKER_ROLE=restaurant`, pricing from `menu` and printing tickets to `printer`
+fn restaurant_worker(
+    runtime: &CoreRuntime,
+    client: RetryClient<Client>,
+    restaurant_id: &str,
+    menu: Arc<dyn ProductCatalogue>,
+    printer: Arc<MemoryPrinter>,
+) -> Worker {
+    let task_queue = restaurant_task_queue(restaurant_id);
+    let worker_config = WorkerConfigBuilder::default()
+        .namespace("default")
+        .task_queue(task_queue.clone())
+        .versioning_strategy(WorkerVersioningStrategy::None {
+            build_id: "e2e-test".to_owned(),
+        })
+        .build()
+        .unwrap();
+
+    let core_worker = init_worker(runtime, worker_config, client).unwrap();
+    let mut worker = Worker::new_from_core(Arc::new(core_worker), task_queue);
+
+    worker.register_activity("price_basket", move |ctx: ActContext, products: Vec<OrderProduct>| {
+        let menu = menu.clone();
+        async move { price_basket(ctx, menu, products).await }
+    });
+    worker.register_activity("print_kitchen_ticket", move |ctx: ActContext, ticket: KitchenTicket| {
+        let printer = printer.clone();
+        async move { print_kitchen_ticket(ctx, printer, ticket).await }
+    });
+
+    worker
+}
+
+/// The statuses the customer was emailed about, in order
+fn emailed_statuses(sink: &MemorySink) -> Vec<OrderStatus> {
+    sink.sent()
+        .into_iter()
+        .filter(|n| n.channel == Channel::Email && n.kind == NotificationKind::StatusUpdate)
+        .map(|n| n.status)
+        .collect()
+}
+
+/// Give the worker time to run the first workflow task and register its handlers
+async fn wait_for_handlers() {
+    tokio::time::sleep(std::time::Duration::from_secs(2)).await;
+}
+
+/// Check the basket out and wait for the workflow to take payment
+async fn checkout_and_wait_for_payment(client: &RetryClient<Client>, workflow_id: &str) {
+    checkout(client, workflow_id).await.expect("Failed to checkout");
+    wait_for_payment(client, workflow_id).await;
+}
+
+/// Wait for the workflow to take payment for a checked out basket
+async fn wait_for_payment(client: &RetryClient<Client>, workflow_id: &str) {
+    for _ in 0..20 {
+        let state = get_order_status(client, workflow_id)
+            .await
+            .expect("Failed to get order status");
+        if state.status != OrderStatus::Default {
+            return;
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(500)).await;
+    }
+    panic!("Payment was not taken after checkout");
+}
+
+/// Drive an order through the kitchen to completion, as the restaurant would
+async fn complete_order(client: &RetryClient<Client>, workflow_id: &str) {
+    for status in [OrderStatus::Accepted, OrderStatus::Preparing] {
+        update_order_status(client, workflow_id, status)
+            .await
+            .expect("Failed to update order status");
+    }
+    hand_over_order(client, workflow_id).await;
+}
+
+/// Mark a prepared order ready and hand it over - the restaurant completes collection
+/// orders, and the first courier assigned delivers the rest
+async fn hand_over_order(client: &RetryClient<Client>, workflow_id: &str) {
+    update_order_status(client, workflow_id, OrderStatus::Ready)
+        .await
+        .expect("Failed to update order status");
+
+    let collection = get_order_status(client, workflow_id)
+        .await
+        .expect("Failed to get order status")
+        .collection;
+    if collection {
+        update_order_status(client, workflow_id, OrderStatus::Completed)
+            .await
+            .expect("Failed to update order status");
+        return;
+    }
+
+    let courier_id = wait_for_courier(client, workflow_id, None).await;
+    courier_picked_up(client, workflow_id, &courier_id)
+        .await
+        .expect("Failed to pick up order");
+    wait_for_status(client, workflow_id, OrderStatus::OutForDelivery).await;
+    courier_dropped_off(client, workflow_id, &courier_id)
+        .await
+        .expect("Failed to drop off order");
+}
+
+/// Poll the order until a courier other than `replacing` is assigned to it
+async fn wait_for_courier(client: &RetryClient<Client>, workflow_id: &str, replacing: Option<&str>) -> String {
+    for _ in 0..20 {
+        let state = get_order_status(client, workflow_id)
+            .await
+            .expect("Failed to get order status");
+        if let Some(courier_id) = state.courier_id.filter(|c| Some(c.as_str()) != replacing) {
+            return courier_id;
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(500)).await;
+    }
+    panic!("No courier was assigned");
+}
+
+/// Poll the order until it reaches `status`
+async fn wait_for_status(client: &RetryClient<Client>, workflow_id: &str, status: OrderStatus) {
+    for _ in 0..20 {
+        let state = get_order_status(client, workflow_id)
+            .await
+            .expect("Failed to get order status");
+        if state.status == status {
+            return;
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(500)).await;
+    }
+    panic!("Order did not reach {}", status);
+}
+
+/// A basket the starter would submit
+fn sample_order() -> OrderState {
+    let mut order_state = OrderState::new();
+    order_state.email = "customer@example.com".to_string();
+    order_state.delivery_address = Some(Address {
+        line1: "123 Main St".to_string(),
+        line2: None,
+        line3: None,
+        town: "Anytown".to_string(),
+        county: None,
+        post_code: "AB1 2CD".to_string(),
+    });
+    order_state.add_item(OrderProduct {
+        product_id: 1,
+        quantity: 2,
+    }).unwrap();
+    order_state.add_item(OrderProduct {
+        product_id: 5,
+        quantity: 1,
+    }).unwrap();
+    order_state
+}
+
+/// ✅ The starter's basket reaches the workflow intact
+#[tokio::test]
+#[ignore] // Only run explicitly with: cargo test --test e2e_ephemeral_tests -- --ignored
+async fn e2e_order_workflow_receives_starter_basket() {
+    let (mut server, client) = start_server().await;
+
+    let telemetry = TelemetryOptionsBuilder::default().build().unwrap();
+    let runtime = CoreRuntime::new_assume_tokio(telemetry).unwrap();
+
+    let task_queue = "e2e-order-basket";
+    let gateway = Arc::new(FakePaymentGateway::new());
+    let sink = Arc::new(MemorySink::new());
+    let mut worker = OrderWorker::new(task_queue, gateway.clone(), sink.clone()).build(&runtime, client.clone());
+
+    let mut order = sample_order();
+    order.phone = Some("+447700900123".to_string());
+    let workflow_id = format!("e2e-order-{}", uuid::Uuid::new_v4());
+
+    let handle = client
+        .start_workflow(
+            vec![order.as_json_payload().unwrap()],
+            task_queue.to_string(),
+            workflow_id.clone(),
+            "order_workflow".to_string(),
+            None,
+            WorkflowOptions::default(),
+        )
+        .await
+        .expect("Failed to start workflow");
+
+    let worker_fut = worker.run();
+    let wf_handle = client.get_untyped_workflow_handle(&workflow_id, handle.run_id.clone());
+    let result_fut = async {
+        wait_for_handlers().await;
+        checkout_and_wait_for_payment(&client, &workflow_id).await;
+        complete_order(&client, &workflow_id).await;
+        wf_handle.get_workflow_result(Default::default()).await
+    };
+
+    let result = tokio::select! {
+        res = result_fut => res.expect("Failed to get workflow result"),
+        _ = worker_fut => panic!("Worker stopped unexpectedly"),
+    };
+
+    let outcome = match result {
+        temporal_client::WorkflowExecutionResult::Succeeded(payloads) => {
+            OrderOutcome::from_json_payload(payloads.first().unwrap()).unwrap()
+        }
+        _ => panic!("Workflow should have succeeded"),
+    };
+    let completed = &outcome.order;
+
+    assert_eq!(completed.email, order.email);
+    assert_eq!(completed.collection, order.collection);
+    assert_eq!(completed.products.len(), 2);
+    assert_eq!(completed.products[0].product_id, 1);
+    assert_eq!(completed.products[0].quantity, 2);
+    assert_eq!(completed.products[1].product_id, 5);
+    assert_eq!(
+        completed.delivery_address.as_ref().map(|a| a.post_code.as_str()),
+        Some("AB1 2CD")
+    );
+
+    // 2 x Margherita Pizza @ £12.99 + 1 x Coca Cola @ £2.99, charged in pence
+    let pricing = completed.pricing.as_ref().expect("Order should be priced");
+    assert_eq!(pricing.total_in_pence, 2897);
+    let captures = gateway.captures();
+    assert_eq!(captures.iter().map(|c| c.amount).collect::<Vec<_>>(), vec![pricing.total()]);
+    assert_eq!(completed.payment.as_ref(), captures.first());
+
+    // The customer is texted and emailed at every step, starting with what they paid,
+    // then emailed their receipt
+    let sent = sink.sent();
+    let mut expected = Vec::new();
+    for status in [
+        OrderStatus::Pending,
+        OrderStatus::Accepted,
+        OrderStatus::Preparing,
+        OrderStatus::Ready,
+        OrderStatus::OutForDelivery,
+        OrderStatus::Completed,
+    ] {
+        expected.push((NotificationKind::StatusUpdate, status.clone(), Channel::Sms));
+        expected.push((NotificationKind::StatusUpdate, status, Channel::Email));
+    }
+    expected.push((NotificationKind::Receipt, OrderStatus::Completed, Channel::Email));
+    assert_eq!(
+        sent.iter().map(|n| (n.kind, n.status.clone(), n.channel)).collect::<Vec<_>>(),
+        expected
+    );
+    let pending = sent
+        .iter()
+        .find(|n| n.status == OrderStatus::Pending && n.channel == Channel::Email)
+        .expect("Customer should be emailed that the order is pending");
+    assert_eq!(pending.recipient, order.email);
+    assert!(pending.body.contains("£28.97"));
+    assert!(sent.iter().any(|n| n.channel == Channel::Sms && n.recipient == "+447700900123"));
+    let receipt = completed.receipt.as_ref().expect("Completed order should have a receipt");
+    assert_eq!(
+        receipt.html_url,
+        format!("memory://{}", receipt_key(&workflow_id, &handle.run_id, "html"))
+    );
+    assert!(sent.last().unwrap().body.contains(&receipt.html_url));
+
+    // The outcome tells the customer what happened and when
+    assert_eq!(outcome.status, OrderStatus::Completed);
+    assert_eq!(outcome.charged, Some(pricing.total()));
+    assert_eq!(outcome.refunded, None);
+    assert_eq!(
+        outcome.transitions.iter().map(|t| t.status.clone()).collect::<Vec<_>>(),
+        vec![
+            OrderStatus::Default,
+            OrderStatus::Pending,
+            OrderStatus::Accepted,
+            OrderStatus::Preparing,
+            OrderStatus::Ready,
+            OrderStatus::OutForDelivery,
+            OrderStatus::Completed,
+        ]
+    );
+    assert_eq!(completed.courier_id.as_deref(), Some("courier-1"));
+    let delivery = outcome.delivery.as_ref().expect("Courier workflow should report back");
+    assert_eq!(delivery.courier_id, "courier-1");
+    assert!(delivery.timed_out.is_empty());
+    assert!(outcome.transitions.windows(2).all(|t| t[0].at <= t[1].at));
+    assert_eq!(
+        outcome.notification_ids,
+        sent.iter().map(|n| n.notification_id.clone()).collect::<Vec<_>>()
+    );
+    println!("✅ Basket received intact: {} item(s) for {}", completed.products.len(), completed.email);
+
+    server.shutdown().await.unwrap();
+}
+
+/// ❌ Malformed input fails the workflow instead of panicking the workflow task
+#[tokio::test]
+#[ignore] // Only run explicitly with: cargo test --test e2e_ephemeral_tests -- --ignored
+async fn e2e_order_workflow_rejects_malformed_input() {
+    let (mut server, client) = start_server().await;
+
+    let telemetry = TelemetryOptionsBuilder::default().build().unwrap();
+    let runtime = CoreRuntime::new_assume_tokio(telemetry).unwrap();
+
+    let task_queue = "e2e-order-malformed";
+    let gateway = Arc::new(FakePaymentGateway::new());
+    let sink = Arc::new(MemorySink::new());
+    let mut worker = OrderWorker::new(task_queue, gateway.clone(), sink.clone()).build(&runtime, client.clone());
+
+    let workflow_id = format!("e2e-order-malformed-{}", uuid::Uuid::new_v4());
+
+    let handle = client
+        .start_workflow(
+            vec!["not an order".as_json_payload().unwrap()],
+            task_queue.to_string(),
+            workflow_id.clone(),
+            "order_workflow".to_string(),
+            None,
+            WorkflowOptions::default(),
+        )
+        .await
+        .expect("Failed to start workflow");
+
+    let worker_fut = worker.run();
+    let wf_handle = client.get_untyped_workflow_handle(&workflow_id, handle.run_id.clone());
+    let result_fut = wf_handle.get_workflow_result(Default::default());
+
+    let result = tokio::select! {
+        res = result_fut => res.expect("Failed to get workflow result"),
+        _ = worker_fut => panic!("Worker stopped unexpectedly"),
+    };
+
+    match result {
+        temporal_client::WorkflowExecutionResult::Failed(failure) => {
+            println!("✅ Workflow failed as expected: {}", failure.message);
+            assert!(
+                failure.message.contains("not a valid OrderState"),
+                "Failure message should explain the input is malformed"
+            );
+        }
+        _ => panic!("Workflow should have failed"),
+    }
+
+    server.shutdown().await.unwrap();
+}
+
+/// 🔍 The order's memo has its live state while the workflow is running
+#[tokio::test]
+#[ignore] // Only run explicitly with: cargo test --test e2e_ephemeral_tests -- --ignored
+async fn e2e_order_workflow_get_status() {
+    let (mut server, client) = start_server().await;
+
+    let telemetry = TelemetryOptionsBuilder::default().build().unwrap();
+    let runtime = CoreRuntime::new_assume_tokio(telemetry).unwrap();
+
+    let task_queue = "e2e-order-status";
+    let gateway = Arc::new(FakePaymentGateway::new());
+    let sink = Arc::new(MemorySink::new());
+    let mut worker = OrderWorker::new(task_queue, gateway.clone(), sink.clone()).build(&runtime, client.clone());
+
+    let order = sample_order();
+    let workflow_id = format!("e2e-order-status-{}", uuid::Uuid::new_v4());
+
+    client
+        .start_workflow(
+            vec![order.as_json_payload().unwrap()],
+            task_queue.to_string(),
+            workflow_id.clone(),
+            "order_workflow".to_string(),
+            None,
+            WorkflowOptions::default(),
+        )
+        .await
+        .expect("Failed to start workflow");
+
+    let worker_fut = worker.run();
+    let status_fut = async {
+        wait_for_handlers().await;
+        checkout_and_wait_for_payment(&client, &workflow_id).await;
+        get_order_status(&client, &workflow_id).await
+    };
+
+    let state = tokio::select! {
+        res = status_fut => res.expect("Failed to get order status"),
+        _ = worker_fut => panic!("Worker stopped unexpectedly"),
+    };
+
+    assert_eq!(state.status, OrderStatus::Pending);
+    assert_eq!(state.products.len(), order.products.len());
+    assert!(state.pricing.is_some());
+    assert_eq!(state.email, order.email);
+    assert!(state.delivery_address.is_some());
+
+    // Pizzas take 15 minutes, then the default 30 minutes to deliver
+    let eta = state.eta.expect("Paid-for order should have an ETA");
+    assert_eq!(eta.delivery_minutes, 30);
+    assert!(eta.at > chrono::Utc::now() + chrono::Duration::minutes(40));
+
+    println!("✅ get_order_status returned {} for {}", state.status, workflow_id);
+
+    server.shutdown().await.unwrap();
+}
+
+/// 🍳 The restaurant drives the order with UPDATE_STATUS and bad or illegal statuses are rejected
+#[tokio::test]
+#[ignore] // Only run explicitly with: cargo test --test e2e_ephemeral_tests -- --ignored
+async fn e2e_order_workflow_update_status() {
+    let (mut server, client) = start_server().await;
+
+    let telemetry = TelemetryOptionsBuilder::default().build().unwrap();
+    let runtime = CoreRuntime::new_assume_tokio(telemetry).unwrap();
+
+    let task_queue = "e2e-order-update-status";
+    let gateway = Arc::new(FakePaymentGateway::new());
+    let sink = Arc::new(MemorySink::new());
+    let mut worker = OrderWorker::new(task_queue, gateway.clone(), sink.clone()).build(&runtime, client.clone());
+
+    let workflow_id = format!("e2e-order-update-{}", uuid::Uuid::new_v4());
+
+    let handle = client
+        .start_workflow(
+            vec![sample_order().as_json_payload().unwrap()],
+            task_queue.to_string(),
+            workflow_id.clone(),
+            "order_workflow".to_string(),
+            None,
+            WorkflowOptions::default(),
+        )
+        .await
+        .expect("Failed to start workflow");
+
+    let worker_fut = worker.run();
+    let wf_handle = client.get_untyped_workflow_handle(&workflow_id, handle.run_id.clone());
+    let result_fut = async {
+        wait_for_handlers().await;
+        checkout_and_wait_for_payment(&client, &workflow_id).await;
+
+        // The validator rejects statuses OrderStatus cannot parse
+        let response = client
+            .update_workflow_execution(
+                workflow_id.clone(),
+                String::new(),
+                "UPDATE_STATUS".to_string(),
+                WaitPolicy {
+                    lifecycle_stage: UpdateWorkflowExecutionLifecycleStage::Completed as i32,
+                },
+                Some(Payloads {
+                    payloads: vec!["ON_FIRE".as_json_payload().unwrap()],
+                }),
+            )
+            .await
+            .expect("Failed to send update");
+        assert!(matches!(
+            response.outcome.and_then(|o| o.value),
+            Some(outcome::Value::Failure(_))
+        ));
+
+        // As are statuses that skip the kitchen
+        let skipped = update_order_status(&client, &workflow_id, OrderStatus::Completed).await;
+        assert!(skipped.unwrap_err().to_string().contains("cannot move order from PENDING to COMPLETED"));
+
+        complete_order(&client, &workflow_id).await;
+        wf_handle.get_workflow_result(Default::default()).await
+    };
+
+    let result = tokio::select! {
+        res = result_fut => res.expect("Failed to get workflow result"),
+        _ = worker_fut => panic!("Worker stopped unexpectedly"),
+    };
+
+    assert!(
+        matches!(result, temporal_client::WorkflowExecutionResult::Succeeded(_)),
+        "Workflow should have succeeded"
+    );
+
+    assert_eq!(
+        emailed_statuses(&sink),
+        vec![
+            OrderStatus::Pending,
+            OrderStatus::Accepted,
+            OrderStatus::Preparing,
+            OrderStatus::Ready,
+            OrderStatus::OutForDelivery,
+            OrderStatus::Completed,
+        ]
+    );
+
+    println!("✅ Order completed via UPDATE_STATUS");
+
+    server.shutdown().await.unwrap();
+}
+
+/// Reject an order after the restaurant has moved it through `statuses`, returning the
+/// outcome, the statuses the customer was notified of and the refunds made
+async fn reject_order_after(
+    task_queue: &str,
+    statuses: &[OrderStatus],
+) -> (OrderOutcome, Vec<OrderStatus>, Vec<Refund>) {
+    let (mut server, client) = start_server().await;
+
+    let telemetry = TelemetryOptionsBuilder::default().build().unwrap();
+    let runtime = CoreRuntime::new_assume_tokio(telemetry).unwrap();
+
+    let gateway = Arc::new(FakePaymentGateway::new());
+    let sink = Arc::new(MemorySink::new());
+    let mut worker = OrderWorker::new(task_queue, gateway.clone(), sink.clone()).build(&runtime, client.clone());
+
+    let workflow_id = format!("e2e-order-reject-{}", uuid::Uuid::new_v4());
+
+    let handle = client
+        .start_workflow(
+            vec![sample_order().as_json_payload().unwrap()],
+            task_queue.to_string(),
+            workflow_id.clone(),
+            "order_workflow".to_string(),
+            None,
+            WorkflowOptions::default(),
+        )
+        .await
+        .expect("Failed to start workflow");
+
+    let worker_fut = worker.run();
+    let wf_handle = client.get_untyped_workflow_handle(&workflow_id, handle.run_id.clone());
+    let result_fut = async {
+        wait_for_handlers().await;
+        checkout_and_wait_for_payment(&client, &workflow_id).await;
+        for status in statuses {
+            update_order_status(&client, &workflow_id, status.clone())
+                .await
+                .expect("Failed to update order status");
+        }
+        update_order_status(&client, &workflow_id, OrderStatus::Rejected)
+            .await
+            .expect("Failed to reject order");
+        wf_handle.get_workflow_result(Default::default()).await
+    };
+
+    let result = tokio::select! {
+        res = result_fut => res.expect("Failed to get workflow result"),
+        _ = worker_fut => panic!("Worker stopped unexpectedly"),
+    };
+
+    // Rejection is a business outcome, not a workflow failure
+    let outcome = match result {
+        temporal_client::WorkflowExecutionResult::Succeeded(payloads) => {
+            OrderOutcome::from_json_payload(payloads.first().unwrap()).unwrap()
+        }
+        _ => panic!("Rejected order should complete successfully"),
+    };
+
+    server.shutdown().await.unwrap();
+
+    (outcome, emailed_statuses(&sink), gateway.refunds())
+}
+
+/// ↩️ Rejecting a pending order refunds the customer and tells them
+#[tokio::test]
+#[ignore] // Only run explicitly with: cargo test --test e2e_ephemeral_tests -- --ignored
+async fn e2e_order_workflow_rejected_before_accepted() {
+    let (outcome, notified, refunds) = reject_order_after("e2e-order-reject-pending", &[]).await;
+
+    assert_eq!(outcome.status, OrderStatus::Rejected);
+    assert_eq!(refunds.len(), 1, "Payment should be refunded exactly once");
+    assert_eq!(Some(&refunds[0].transaction_id), outcome.order.payment.as_ref().map(|p| &p.transaction_id));
+    assert_eq!(outcome.refunded, Some(refunds[0].amount));
+    assert_eq!(outcome.refunded, outcome.charged);
+    assert_eq!(notified, vec![OrderStatus::Pending, OrderStatus::Rejected]);
+
+    println!("✅ Pending order rejected and refunded");
+}
+
+/// ↩️ Rejecting an accepted order refunds the customer and tells them
+#[tokio::test]
+#[ignore] // Only run explicitly with: cargo test --test e2e_ephemeral_tests -- --ignored
+async fn e2e_order_workflow_rejected_after_accepted() {
+    let (outcome, notified, refunds) =
+        reject_order_after("e2e-order-reject-accepted", &[OrderStatus::Accepted]).await;
+
+    assert_eq!(outcome.status, OrderStatus::Rejected);
+    assert_eq!(refunds.len(), 1, "Payment should be refunded exactly once");
+    assert_eq!(Some(&refunds[0].transaction_id), outcome.order.payment.as_ref().map(|p| &p.transaction_id));
+    assert_eq!(outcome.refunded, Some(refunds[0].amount));
+    assert_eq!(outcome.refunded, outcome.charged);
+    assert_eq!(
+        notified,
+        vec![OrderStatus::Pending, OrderStatus::Accepted, OrderStatus::Rejected]
+    );
+
+    println!("✅ Accepted order rejected and refunded");
+}
+
+/// 💳 A declined card ends the order and tells the customer, without failing the workflow
+#[tokio::test]
+#[ignore] // Only run explicitly with: cargo test --test e2e_ephemeral_tests -- --ignored
+async fn e2e_order_workflow_payment_declined() {
+    let (mut server, client) = start_server().await;
+
+    let telemetry = TelemetryOptionsBuilder::default().build().unwrap();
+    let runtime = CoreRuntime::new_assume_tokio(telemetry).unwrap();
+
+    let task_queue = "e2e-order-declined";
+    let gateway = Arc::new(
+        FakePaymentGateway::new().with_script([ScriptedOutcome::Decline("insufficient funds".to_string())]),
+    );
+    let sink = Arc::new(MemorySink::new());
+    let mut worker = OrderWorker::new(task_queue, gateway.clone(), sink.clone()).build(&runtime, client.clone());
+
+    let workflow_id = format!("e2e-order-declined-{}", uuid::Uuid::new_v4());
+
+    let handle = client
+        .start_workflow(
+            vec![sample_order().as_json_payload().unwrap()],
+            task_queue.to_string(),
+            workflow_id.clone(),
+            "order_workflow".to_string(),
+            None,
+            WorkflowOptions::default(),
+        )
+        .await
+        .expect("Failed to start workflow");
+
+    let worker_fut = worker.run();
+    let wf_handle = client.get_untyped_workflow_handle(&workflow_id, handle.run_id.clone());
+    let result_fut = async {
+        wait_for_handlers().await;
+        checkout(&client, &workflow_id).await.expect("Failed to checkout");
+        wf_handle.get_workflow_result(Default::default()).await
+    };
+
+    let result = tokio::select! {
+        res = result_fut => res.expect("Failed to get workflow result"),
+        _ = worker_fut => panic!("Worker stopped unexpectedly"),
+    };
+
+    // A decline is a business outcome, not a workflow failure
+    let outcome = match result {
+        temporal_client::WorkflowExecutionResult::Succeeded(payloads) => {
+            OrderOutcome::from_json_payload(payloads.first().unwrap()).unwrap()
+        }
+        _ => panic!("Declined order should complete successfully"),
+    };
+
+    assert_eq!(outcome.status, OrderStatus::PaymentDeclined);
+    assert!(outcome.order.decline_reason.unwrap().contains("insufficient funds"));
+    assert_eq!(outcome.charged, None);
+    assert!(gateway.captures().is_empty());
+    assert_eq!(emailed_statuses(&sink), vec![OrderStatus::PaymentDeclined]);
+
+    println!("✅ Declined payment ended the order");
+
+    server.shutdown().await.unwrap();
+}
+
+/// ⏰ An order the restaurant never accepts is rejected and refunded when its deadline passes
+#[tokio::test]
+#[ignore] // Only run explicitly with: cargo test --test e2e_ephemeral_tests -- --ignored
+async fn e2e_order_workflow_sla_rejects_unaccepted_order() {
+    let (mut server, client) = start_server().await;
+
+    let telemetry = TelemetryOptionsBuilder::default().build().unwrap();
+    let runtime = CoreRuntime::new_assume_tokio(telemetry).unwrap();
+
+    let task_queue = "e2e-order-sla-reject";
+    let gateway = Arc::new(FakePaymentGateway::new());
+    let sink = Arc::new(MemorySink::new());
+    let sla = SlaPolicy::new(vec![Deadline {
+        status: OrderStatus::Pending,
+        within_secs: 3,
+        on_breach: Breach::Reject,
+    }])
+    .unwrap();
+    let mut worker = OrderWorker::new(task_queue, gateway.clone(), sink.clone())
+        .with_sla(sla)
+        .build(&runtime, client.clone());
+
+    let workflow_id = format!("e2e-order-sla-reject-{}", uuid::Uuid::new_v4());
+
+    let handle = client
+        .start_workflow(
+            vec![sample_order().as_json_payload().unwrap()],
+            task_queue.to_string(),
+            workflow_id.clone(),
+            "order_workflow".to_string(),
+            None,
+            WorkflowOptions::default(),
+        )
+        .await
+        .expect("Failed to start workflow");
+
+    let worker_fut = worker.run();
+    let wf_handle = client.get_untyped_workflow_handle(&workflow_id, handle.run_id.clone());
+    let result_fut = async {
+        wait_for_handlers().await;
+        checkout(&client, &workflow_id).await.expect("Failed to checkout");
+        // Nobody at the restaurant answers
+        wf_handle.get_workflow_result(Default::default()).await
+    };
+
+    let result = tokio::select! {
+        res = result_fut => res.expect("Failed to get workflow result"),
+        _ = worker_fut => panic!("Worker stopped unexpectedly"),
+    };
+
+    let outcome = match result {
+        temporal_client::WorkflowExecutionResult::Succeeded(payloads) => {
+            OrderOutcome::from_json_payload(payloads.first().unwrap()).unwrap()
+        }
+        _ => panic!("Overdue order should complete successfully"),
+    };
+
+    assert_eq!(outcome.status, OrderStatus::Rejected);
+    assert_eq!(outcome.refunded, outcome.charged);
+    assert_eq!(gateway.refunds().len(), 1);
+    assert_eq!(emailed_statuses(&sink), vec![OrderStatus::Pending, OrderStatus::Rejected]);
+
+    println!("✅ Unaccepted order rejected and refunded at its deadline");
+
+    server.shutdown().await.unwrap();
+}
+
+/// 📣 A late order is escalated, then carries on when the restaurant catches up
+#[tokio::test]
+#[ignore] // Only run explicitly with: cargo test --test e2e_ephemeral_tests -- --ignored
+async fn e2e_order_workflow_sla_escalates_late_order() {
+    let (mut server, client) = start_server().await;
+
+    let telemetry = TelemetryOptionsBuilder::default().build().unwrap();
+    let runtime = CoreRuntime::new_assume_tokio(telemetry).unwrap();
+
+    let task_queue = "e2e-order-sla-escalate";
+    let gateway = Arc::new(FakePaymentGateway::new());
+    let sink = Arc::new(MemorySink::new());
+    let sla = SlaPolicy::new(vec![Deadline {
+        status: OrderStatus::Preparing,
+        within_secs: 2,
+        on_breach: Breach::Escalate,
+    }])
+    .unwrap();
+    let mut worker = OrderWorker::new(task_queue, gateway.clone(), sink.clone())
+        .with_sla(sla)
+        .build(&runtime, client.clone());
+
+    let workflow_id = format!("e2e-order-sla-escalate-{}", uuid::Uuid::new_v4());
+
+    let handle = client
+        .start_workflow(
+            vec![sample_order().as_json_payload().unwrap()],
+            task_queue.to_string(),
+            workflow_id.clone(),
+            "order_workflow".to_string(),
+            None,
+            WorkflowOptions::default(),
+        )
+        .await
+        .expect("Failed to start workflow");
+
+    let worker_fut = worker.run();
+    let wf_handle = client.get_untyped_workflow_handle(&workflow_id, handle.run_id.clone());
+    let result_fut = async {
+        wait_for_handlers().await;
+        checkout_and_wait_for_payment(&client, &workflow_id).await;
+        for status in [OrderStatus::Accepted, OrderStatus::Preparing] {
+            update_order_status(&client, &workflow_id, status)
+                .await
+                .expect("Failed to update order status");
+        }
+
+        // The kitchen takes longer than it should
+        tokio::time::sleep(std::time::Duration::from_secs(5)).await;
+
+        hand_over_order(&client, &workflow_id).await;
+        wf_handle.get_workflow_result(Default::default()).await
+    };
+
+    let result = tokio::select! {
+        res = result_fut => res.expect("Failed to get workflow result"),
+        _ = worker_fut => panic!("Worker stopped unexpectedly"),
+    };
+
+    let outcome = match result {
+        temporal_client::WorkflowExecutionResult::Succeeded(payloads) => {
+            OrderOutcome::from_json_payload(payloads.first().unwrap()).unwrap()
+        }
+        _ => panic!("Workflow should have succeeded"),
+    };
+
+    // Escalated once, and still completed
+    assert_eq!(outcome.status, OrderStatus::Completed);
+    let escalations: Vec<_> = sink
+        .sent()
+        .into_iter()
+        .filter(|n| n.kind == NotificationKind::Escalation)
+        .collect();
+    assert_eq!(escalations.len(), 1);
+    assert_eq!(escalations[0].status, OrderStatus::Preparing);
+    assert!(outcome.notification_ids.contains(&escalations[0].notification_id));
+
+    println!("✅ Late order escalated and completed");
+
+    server.shutdown().await.unwrap();
+}
+
+/// 🛒 Items can be added and removed until checkout, then the basket is frozen
+#[tokio::test]
+#[ignore] // Only run explicitly with: cargo test --test e2e_ephemeral_tests -- --ignored
+async fn e2e_order_workflow_basket_updates() {
+    let (mut server, client) = start_server().await;
+
+    let telemetry = TelemetryOptionsBuilder::default().build().unwrap();
+    let runtime = CoreRuntime::new_assume_tokio(telemetry).unwrap();
+
+    let task_queue = "e2e-order-basket-updates";
+    let gateway = Arc::new(FakePaymentGateway::new());
+    let sink = Arc::new(MemorySink::new());
+    let mut worker = OrderWorker::new(task_queue, gateway.clone(), sink.clone()).build(&runtime, client.clone());
+
+    // Start with an empty basket
+    let mut order = sample_order();
+    order.products.clear();
+    let workflow_id = format!("e2e-order-basket-updates-{}", uuid::Uuid::new_v4());
+
+    client
+        .start_workflow(
+            vec![order.as_json_payload().unwrap()],
+            task_queue.to_string(),
+            workflow_id.clone(),
+            "order_workflow".to_string(),
+            None,
+            WorkflowOptions::default(),
+        )
+        .await
+        .expect("Failed to start workflow");
+
+    let worker_fut = worker.run();
+    let basket_fut = async {
+        wait_for_handlers().await;
+
+        // There is nothing to check out yet. A CHECKOUT signal cannot be rejected, so it
+        // is ignored and the basket stays open.
+        let empty = checkout(&client, &workflow_id).await;
+        assert!(empty.unwrap_err().to_string().contains("at least one item"));
+        signal_checkout(&client, &workflow_id).await.expect("Failed to signal checkout");
+
+        let basket = add_item(&client, &workflow_id, OrderProduct { product_id: 2, quantity: 3 })
+            .await
+            .expect("Failed to add item");
+        assert_eq!(basket.len(), 1);
+        assert_eq!(basket[0].quantity, 3);
+
+        let basket = remove_item(&client, &workflow_id, OrderProduct { product_id: 2, quantity: 1 })
+            .await
+            .expect("Failed to remove item");
+        assert_eq!(basket[0].quantity, 2);
+
+        // Products not in the catalogue cannot be priced, so they are not added
+        let unknown = add_item(&client, &workflow_id, OrderProduct { product_id: 999, quantity: 1 }).await;
+        assert!(unknown.unwrap_err().to_string().contains("unknown product"));
+
+        // No payment until checkout, which callers of the Go workflow signal
+        assert!(gateway.captures().is_empty());
+
+        signal_checkout(&client, &workflow_id).await.expect("Failed to signal checkout");
+        wait_for_payment(&client, &workflow_id).await;
+
+        // The basket is frozen once the restaurant accepts the order
+        update_order_status(&client, &workflow_id, OrderStatus::Accepted)
+            .await
+            .expect("Failed to accept order");
+        let late = add_item(&client, &workflow_id, OrderProduct { product_id: 1, quantity: 1 }).await;
+        assert!(late.unwrap_err().to_string().contains("until the restaurant accepts"));
+
+        get_order_status(&client, &workflow_id).await
+    };
+
+    let state = tokio::select! {
+        res = basket_fut => res.expect("Failed to get order status"),
+        _ = worker_fut => panic!("Worker stopped unexpectedly"),
+    };
+
+    assert_eq!(gateway.captures().len(), 1);
+    assert_eq!(state.products.len(), 1);
+    assert_eq!(state.products[0].product_id, 2);
+    assert_eq!(state.products[0].quantity, 2);
+
+    println!("✅ Basket updated before checkout and frozen once accepted");
+
+    server.shutdown().await.unwrap();
+}
+
+/// 🔁 Paid-for orders can be amended until accepted, charging or refunding the difference
+#[tokio::test]
+#[ignore] // Only run explicitly with: cargo test --test e2e_ephemeral_tests -- --ignored
+async fn e2e_order_workflow_amendments() {
+    let (mut server, client) = start_server().await;
+
+    let telemetry = TelemetryOptionsBuilder::default().build().unwrap();
+    let runtime = CoreRuntime::new_assume_tokio(telemetry).unwrap();
+
+    let task_queue = "e2e-order-amendments";
+    // The first top-up is declined, the rest approved
+    let gateway = Arc::new(FakePaymentGateway::new().with_script([
+        ScriptedOutcome::Approve,
+        ScriptedOutcome::Approve,
+        ScriptedOutcome::Decline("insufficient funds".to_string()),
+    ]));
+    let sink = Arc::new(MemorySink::new());
+    let mut worker = OrderWorker::new(task_queue, gateway.clone(), sink.clone()).build(&runtime, client.clone());
+
+    // Two pizzas and a cola - £28.97
+    let workflow_id = format!("e2e-order-amendments-{}", uuid::Uuid::new_v4());
+    client
+        .start_workflow(
+            vec![sample_order().as_json_payload().unwrap()],
+            task_queue.to_string(),
+            workflow_id.clone(),
+            "order_workflow".to_string(),
+            None,
+            WorkflowOptions::default(),
+        )
+        .await
+        .expect("Failed to start workflow");
+
+    let worker_fut = worker.run();
+    let amend_fut = async {
+        wait_for_handlers().await;
+        checkout_and_wait_for_payment(&client, &workflow_id).await;
+
+        // A declined top-up leaves the order as it was
+        let declined = add_item(&client, &workflow_id, OrderProduct { product_id: 3, quantity: 1 }).await;
+        assert!(declined.unwrap_err().to_string().contains("insufficient funds"));
+        let state = get_order_status(&client, &workflow_id).await.unwrap();
+        assert_eq!(state.products.len(), 2);
+        assert_eq!(state.transactions.len(), 1);
+
+        // Adding a salad charges £8.99 more
+        add_item(&client, &workflow_id, OrderProduct { product_id: 3, quantity: 1 })
+            .await
+            .expect("Failed to add item");
+
+        // Taking a pizza off refunds £12.99, from the top-up first
+        remove_item(&client, &workflow_id, OrderProduct { product_id: 1, quantity: 1 })
+            .await
+            .expect("Failed to remove item");
+        let state = get_order_status(&client, &workflow_id).await.unwrap();
+        assert_eq!(state.pricing.unwrap().total_in_pence, 1299 + 899 + 299);
+        assert_eq!(net_paid(&state.transactions).unwrap().amount_in_pence, 1299 + 899 + 299);
+
+        // Everything but the cola can go, but the basket cannot be emptied
+        for product_id in [1, 3] {
+            remove_item(&client, &workflow_id, OrderProduct { product_id, quantity: 1 })
+                .await
+                .expect("Failed to remove item");
+        }
+        let emptied = remove_item(&client, &workflow_id, OrderProduct { product_id: 5, quantity: 1 }).await;
+        assert!(emptied.unwrap_err().to_string().contains("at least one item"));
+
+        update_order_status(&client, &workflow_id, OrderStatus::Rejected)
+            .await
+            .expect("Failed to reject order");
+        get_order_outcome(&client, &workflow_id).await
+    };
+
+    let outcome = tokio::select! {
+        res = amend_fut => res.expect("Failed to get order outcome"),
+        _ = worker_fut => panic!("Worker stopped unexpectedly"),
+    };
+
+    // £28.97 + £8.99 charged, then £12.99, £12.99 and £8.99 refunded for the amendments,
+    // and the cola's £2.99 when the order was rejected
+    let captures: Vec<u64> = gateway.captures().iter().map(|c| c.amount.amount_in_pence).collect();
+    assert_eq!(captures, vec![2897, 899]);
+    let refunds = gateway.refunds();
+    assert_eq!(refunds[0].transaction_id, gateway.captures()[1].transaction_id);
+    assert_eq!(refunds.iter().map(|r| r.amount.amount_in_pence).sum::<u64>(), 2897 + 899);
+    assert_eq!(outcome.charged.map(|a| a.amount_in_pence), Some(2897 + 899));
+    assert_eq!(outcome.refunded, outcome.charged);
+    assert_eq!(outcome.order.transactions.len(), gateway.captures().len() + gateway.refunds().len());
+    assert_eq!(net_paid(&outcome.order.transactions).unwrap().amount_in_pence, 0);
+
+    println!("✅ Amendments charged and refunded the difference");
+
+    server.shutdown().await.unwrap();
+}
+
+/// 🚫 Customers can cancel until the restaurant accepts, and are refunded in full
+#[tokio::test]
+#[ignore] // Only run explicitly with: cargo test --test e2e_ephemeral_tests -- --ignored
+async fn e2e_order_workflow_customer_cancels() {
+    let (mut server, client) = start_server().await;
+
+    let telemetry = TelemetryOptionsBuilder::default().build().unwrap();
+    let runtime = CoreRuntime::new_assume_tokio(telemetry).unwrap();
+
+    let task_queue = "e2e-order-cancels";
+    let gateway = Arc::new(FakePaymentGateway::new());
+    let sink = Arc::new(MemorySink::new());
+    let printer = Arc::new(MemoryPrinter::new());
+    let mut worker = OrderWorker::new(task_queue, gateway.clone(), sink.clone())
+        .with_printer(printer.clone())
+        .build(&runtime, client.clone());
+
+    let mut workflow_ids = Vec::new();
+    for name in ["unpaid", "pending", "accepted"] {
+        let workflow_id = format!("e2e-order-cancels-{}-{}", name, uuid::Uuid::new_v4());
+        client
+            .start_workflow(
+                vec![sample_order().as_json_payload().unwrap()],
+                task_queue.to_string(),
+                workflow_id.clone(),
+                "order_workflow".to_string(),
+                None,
+                WorkflowOptions::default(),
+            )
+            .await
+            .expect("Failed to start workflow");
+        workflow_ids.push(workflow_id);
+    }
+    let (unpaid, pending, accepted) = (&workflow_ids[0], &workflow_ids[1], &workflow_ids[2]);
+
+    let worker_fut = worker.run();
+    let cancel_fut = async {
+        wait_for_handlers().await;
+
+        // Nothing has been paid before checkout, so there is nothing to refund
+        cancel_order(&client, unpaid).await.expect("Failed to cancel unpaid order");
+        let unpaid = get_order_outcome(&client, unpaid).await.expect("Failed to get order outcome");
+
+        // A paid-for order the restaurant has not accepted is refunded
+        checkout_and_wait_for_payment(&client, pending).await;
+        cancel_order(&client, pending).await.expect("Failed to cancel pending order");
+        let rejected = add_item(&client, pending, OrderProduct { product_id: 3, quantity: 1 }).await;
+        assert!(rejected.is_err());
+        let pending = get_order_outcome(&client, pending).await.expect("Failed to get order outcome");
+
+        // Once accepted it is too late
+        checkout_and_wait_for_payment(&client, accepted).await;
+        update_order_status(&client, accepted, OrderStatus::Accepted)
+            .await
+            .expect("Failed to accept order");
+        let too_late = cancel_order(&client, accepted).await;
+        assert!(too_late.unwrap_err().to_string().contains("can no longer be cancelled"));
+        update_order_status(&client, accepted, OrderStatus::Rejected)
+            .await
+            .expect("Failed to reject order");
+        get_order_outcome(&client, accepted).await.expect("Failed to get order outcome");
+
+        (unpaid, pending)
+    };
+
+    let (unpaid, pending) = tokio::select! {
+        res = cancel_fut => res,
+        _ = worker_fut => panic!("Worker stopped unexpectedly"),
+    };
+
+    assert_eq!(unpaid.order.status, OrderStatus::Cancelled);
+    assert_eq!(unpaid.charged, None);
+    assert!(unpaid.order.transactions.is_empty());
+
+    assert_eq!(pending.order.status, OrderStatus::Cancelled);
+    assert_eq!(pending.charged.map(|a| a.amount_in_pence), Some(2897));
+    assert_eq!(pending.refunded, pending.charged);
+    assert_eq!(net_paid(&pending.order.transactions).unwrap().amount_in_pence, 0);
+
+    // The restaurant is told not to cook the paid-for order; it never saw the unpaid one
+    let notices: Vec<_> = printer.printed().into_iter().filter(|t| t.cancelled).collect();
+    assert_eq!(notices.len(), 1);
+    assert_eq!(notices[0].order_id, workflow_ids[1]);
+    assert!(notices[0].render().contains("CANCELLED - do not cook"));
+
+    // Only the two paid-for orders were charged, and both were refunded
+    assert_eq!(gateway.captures().len(), 2);
+    assert_eq!(gateway.refunds().len(), 2);
+    assert!(emailed_statuses(&sink).contains(&OrderStatus::Cancelled));
+
+    println!("✅ Customers cancelled until the restaurant accepted");
+
+    server.shutdown().await.unwrap();
+}
+
+/// 🏷️ Discount codes follow the basket to checkout, and the discounted amount is what is charged and refunded
+#[tokio::test]
+#[ignore] // Only run explicitly with: cargo test --test e2e_ephemeral_tests -- --ignored
+async fn e2e_order_workflow_discount_codes() {
+    let (mut server, client) = start_server().await;
+
+    let telemetry = TelemetryOptionsBuilder::default().build().unwrap();
+    let runtime = CoreRuntime::new_assume_tokio(telemetry).unwrap();
+
+    let task_queue = "e2e-order-discount-codes";
+    let gateway = Arc::new(FakePaymentGateway::new());
+    let sink = Arc::new(MemorySink::new());
+    let mut worker = OrderWorker::new(task_queue, gateway.clone(), sink.clone()).build(&runtime, client.clone());
+
+    // Two pizzas and a cola - £28.97
+    let workflow_id = format!("e2e-order-discount-codes-{}", uuid::Uuid::new_v4());
+    client
+        .start_workflow(
+            vec![sample_order().as_json_payload().unwrap()],
+            task_queue.to_string(),
+            workflow_id.clone(),
+            "order_workflow".to_string(),
+            None,
+            WorkflowOptions::default(),
+        )
+        .await
+        .expect("Failed to start workflow");
+
+    let worker_fut = worker.run();
+    let discount_fut = async {
+        wait_for_handlers().await;
+
+        // £5 off a spend of £25 or more
+        let discount = apply_discount(&client, &workflow_id, "fiveoff")
+            .await
+            .expect("Failed to apply discount");
+        assert_eq!(discount.code, "FIVEOFF");
+        assert_eq!(discount.amount_in_pence, 500);
+        let pricing = get_order_status(&client, &workflow_id).await.unwrap().pricing.unwrap();
+        assert_eq!(pricing.total_in_pence, 2397);
+
+        // Unknown codes are rejected with the store's reason
+        let unknown = apply_discount(&client, &workflow_id, "NOPE").await;
+        assert!(unknown.unwrap_err().to_string().contains("unknown discount code"));
+
+        // Below the minimum spend the basket is full price, until it qualifies again
+        remove_item(&client, &workflow_id, OrderProduct { product_id: 1, quantity: 1 })
+            .await
+            .expect("Failed to remove item");
+        let pricing = get_order_status(&client, &workflow_id).await.unwrap().pricing.unwrap();
+        assert_eq!(pricing.total_in_pence, 1598);
+        assert_eq!(pricing.discount, None);
+        add_item(&client, &workflow_id, OrderProduct { product_id: 1, quantity: 1 })
+            .await
+            .expect("Failed to add item");
+        let pricing = get_order_status(&client, &workflow_id).await.unwrap().pricing.unwrap();
+        assert_eq!(pricing.total_in_pence, 2397);
+
+        // A new code replaces the old one, and codes can be taken off altogether
+        let pricing = remove_discount(&client, &workflow_id).await.unwrap().unwrap();
+        assert_eq!(pricing.total_in_pence, 2897);
+        apply_discount(&client, &workflow_id, "WELCOME10")
+            .await
+            .expect("Failed to apply discount");
+
+        checkout_and_wait_for_payment(&client, &workflow_id).await;
+
+        // The code cannot change once the basket is checked out
+        let late = apply_discount(&client, &workflow_id, "FIVEOFF").await;
+        assert!(late.unwrap_err().to_string().contains("checked out"));
+
+        update_order_status(&client, &workflow_id, OrderStatus::Rejected)
+            .await
+            .expect("Failed to reject order");
+        get_order_outcome(&client, &workflow_id).await
+    };
+
+    let outcome = tokio::select! {
+        res = discount_fut => res.expect("Failed to get order outcome"),
+        _ = worker_fut => panic!("Worker stopped unexpectedly"),
+    };
+
+    // 10% off £28.97 is £2.90, charged and refunded as £26.07
+    let captures = gateway.captures();
+    assert_eq!(captures.len(), 1);
+    assert_eq!(captures[0].amount.amount_in_pence, 2607);
+    assert_eq!(outcome.charged.map(|a| a.amount_in_pence), Some(2607));
+    assert_eq!(outcome.refunded, outcome.charged);
+    assert_eq!(outcome.order.pricing.unwrap().discount.unwrap().code, "WELCOME10");
+
+    println!("✅ Discounted order charged and refunded at the discounted price");
+
+    server.shutdown().await.unwrap();
+}
+
+/// 🚲 Orders that cannot be delivered cannot be checked out, and collection orders skip the courier
+#[tokio::test]
+#[ignore] // Only run explicitly with: cargo test --test e2e_ephemeral_tests -- --ignored
+async fn e2e_order_workflow_fulfilment() {
+    let (mut server, client) = start_server().await;
+
+    let telemetry = TelemetryOptionsBuilder::default().build().unwrap();
+    let runtime = CoreRuntime::new_assume_tokio(telemetry).unwrap();
+
+    let task_queue = "e2e-order-fulfilment";
+    let gateway = Arc::new(FakePaymentGateway::new());
+    let sink = Arc::new(MemorySink::new());
+    let mut worker = OrderWorker::new(task_queue, gateway.clone(), sink.clone()).build(&runtime, client.clone());
+
+    // A delivery address no courier could find
+    let mut order = sample_order();
+    if let Some(address) = order.delivery_address.as_mut() {
+        address.post_code = "12345".to_string();
+    }
+    let workflow_id = format!("e2e-order-fulfilment-{}", uuid::Uuid::new_v4());
+
+    let handle = client
+        .start_workflow(
+            vec![order.as_json_payload().unwrap()],
+            task_queue.to_string(),
+            workflow_id.clone(),
+            "order_workflow".to_string(),
+            None,
+            WorkflowOptions::default(),
+        )
+        .await
+        .expect("Failed to start workflow");
+
+    let worker_fut = worker.run();
+    let wf_handle = client.get_untyped_workflow_handle(&workflow_id, handle.run_id.clone());
+    let result_fut = async {
+        wait_for_handlers().await;
+
+        // Checkout is refused until the order can be fulfilled
+        let undeliverable = checkout(&client, &workflow_id).await;
+        assert!(undeliverable.unwrap_err().to_string().contains("invalid post code"));
+        let state = get_order_status(&client, &workflow_id)
+            .await
+            .expect("Failed to get order status");
+        assert_eq!(state.status, OrderStatus::Default);
+        assert!(gateway.captures().is_empty());
+
+        // A collection order cannot also have an address
+        let both = set_fulfilment(
+            &client,
+            &workflow_id,
+            Fulfilment {
+                collection: true,
+                delivery_address: order.delivery_address.clone(),
+            },
+        )
+        .await;
+        assert!(both.unwrap_err().to_string().contains("cannot have a delivery address"));
+
+        let fulfilment = set_fulfilment(
+            &client,
+            &workflow_id,
+            Fulfilment {
+                collection: true,
+                delivery_address: None,
+            },
+        )
+        .await
+        .expect("Failed to set fulfilment");
+        assert!(fulfilment.collection);
+
+        checkout_and_wait_for_payment(&client, &workflow_id).await;
+
+        // Only couriers send orders out for delivery
+        let out = update_order_status(&client, &workflow_id, OrderStatus::OutForDelivery).await;
+        assert!(out.is_err());
+
+        complete_order(&client, &workflow_id).await;
+        wf_handle.get_workflow_result(Default::default()).await
+    };
+
+    let result = tokio::select! {
+        res = result_fut => res.expect("Failed to get workflow result"),
+        _ = worker_fut => panic!("Worker stopped unexpectedly"),
+    };
+
+    let outcome = match result {
+        temporal_client::WorkflowExecutionResult::Succeeded(payloads) => {
+            OrderOutcome::from_json_payload(payloads.first().unwrap()).unwrap()
+        }
+        _ => panic!("Workflow should have succeeded"),
+    };
+
+    // Collected from the restaurant - no courier, straight from READY to COMPLETED
+    assert_eq!(outcome.status, OrderStatus::Completed);
+    assert!(outcome.order.collection);
+    assert_eq!(outcome.order.courier_id, None);
+    assert_eq!(
+        emailed_statuses(&sink),
+        vec![
+            OrderStatus::Pending,
+            OrderStatus::Accepted,
+            OrderStatus::Preparing,
+            OrderStatus::Ready,
+            OrderStatus::Completed,
+        ]
+    );
+
+    println!("✅ Undeliverable order switched to collection and completed");
+
+    server.shutdown().await.unwrap();
+}
+
+/// 🛵 A courier who does not pick up in time is replaced, and the replacement delivers
+#[tokio::test]
+#[ignore] // Only run explicitly with: cargo test --test e2e_ephemeral_tests -- --ignored
+async fn e2e_order_workflow_reassigns_late_courier() {
+    let (mut server, client) = start_server().await;
+
+    let telemetry = TelemetryOptionsBuilder::default().build().unwrap();
+    let runtime = CoreRuntime::new_assume_tokio(telemetry).unwrap();
+
+    let task_queue = "e2e-order-courier-reassigned";
+    let gateway = Arc::new(FakePaymentGateway::new());
+    let sink = Arc::new(MemorySink::new());
+    let pool = Arc::new(FakeCourierPool::new(["courier-1", "courier-2"]));
+    let couriers = Couriers {
+        pool: pool.clone(),
+        timeouts: DeliveryTimeouts {
+            pickup_secs: 3,
+            ..DeliveryTimeouts::default()
+        },
+    };
+    let mut worker = OrderWorker::new(task_queue, gateway.clone(), sink.clone())
+        .with_couriers(couriers)
+        .build(&runtime, client.clone());
+
+    let workflow_id = format!("e2e-order-courier-{}", uuid::Uuid::new_v4());
+
+    let handle = client
+        .start_workflow(
+            vec![sample_order().as_json_payload().unwrap()],
+            task_queue.to_string(),
+            workflow_id.clone(),
+            "order_workflow".to_string(),
+            None,
+            WorkflowOptions::default(),
+        )
+        .await
+        .expect("Failed to start workflow");
+
+    let worker_fut = worker.run();
+    let wf_handle = client.get_untyped_workflow_handle(&workflow_id, handle.run_id.clone());
+    let result_fut = async {
+        wait_for_handlers().await;
+        checkout_and_wait_for_payment(&client, &workflow_id).await;
+        for status in [OrderStatus::Accepted, OrderStatus::Preparing, OrderStatus::Ready] {
+            update_order_status(&client, &workflow_id, status)
+                .await
+                .expect("Failed to update order status");
+        }
+
+        // The first courier never turns up, so the order is offered to the next
+        let late = wait_for_courier(&client, &workflow_id, None).await;
+        let courier_id = wait_for_courier(&client, &workflow_id, Some(late.as_str())).await;
+
+        // The late courier is no longer expected
+        courier_picked_up(&client, &workflow_id, &late)
+            .await
+            .expect("Failed to pick up order");
+        tokio::time::sleep(std::time::Duration::from_secs(1)).await;
+        let state = get_order_status(&client, &workflow_id)
+            .await
+            .expect("Failed to get order status");
+        assert_eq!(state.status, OrderStatus::Ready);
+
+        courier_picked_up(&client, &workflow_id, &courier_id)
+            .await
+            .expect("Failed to pick up order");
+        wait_for_status(&client, &workflow_id, OrderStatus::OutForDelivery).await;
+
+        // Only the courier can complete a delivery
+        let completed = update_order_status(&client, &workflow_id, OrderStatus::Completed).await;
+        assert!(completed.unwrap_err().to_string().contains("courier drops them off"));
+
+        courier_dropped_off(&client, &workflow_id, &courier_id)
+            .await
+            .expect("Failed to drop off order");
+        wf_handle.get_workflow_result(Default::default()).await
+    };
+
+    let result = tokio::select! {
+        res = result_fut => res.expect("Failed to get workflow result"),
+        _ = worker_fut => panic!("Worker stopped unexpectedly"),
+    };
+
+    let outcome = match result {
+        temporal_client::WorkflowExecutionResult::Succeeded(payloads) => {
+            OrderOutcome::from_json_payload(payloads.first().unwrap()).unwrap()
+        }
+        _ => panic!("Workflow should have succeeded"),
+    };
+
+    assert_eq!(outcome.status, OrderStatus::Completed);
+    let delivery = outcome.delivery.expect("Courier workflow should report back");
+    assert_eq!(delivery.courier_id, "courier-2");
+    assert_eq!(delivery.timed_out, vec!["courier-1".to_string()]);
+    assert_eq!(outcome.order.courier_id.as_deref(), Some("courier-2"));
+
+    // Both couriers were let go once they were done with the order
+    assert_eq!(pool.assignments(), vec!["courier-1", "courier-2"]);
+    assert!(pool.busy().is_empty());
+
+    println!("✅ Late courier replaced and order delivered");
+
+    server.shutdown().await.unwrap();
+}
+
+/// 🚫 An order no courier delivers within the reassignment limit is refunded, not failed
+#[tokio::test]
+#[ignore] // Only run explicitly with: cargo test --test e2e_ephemeral_tests -- --ignored
+async fn e2e_order_workflow_refunds_undelivered_order() {
+    let (mut server, client) = start_server().await;
+
+    let telemetry = TelemetryOptionsBuilder::default().build().unwrap();
+    let runtime = CoreRuntime::new_assume_tokio(telemetry).unwrap();
+
+    let task_queue = "e2e-order-delivery-failed";
+    let gateway = Arc::new(FakePaymentGateway::new());
+    let sink = Arc::new(MemorySink::new());
+    let pool = Arc::new(FakeCourierPool::new(["courier-1", "courier-2"]));
+    let couriers = Couriers {
+        pool: pool.clone(),
+        timeouts: DeliveryTimeouts {
+            pickup_secs: 3,
+            max_reassignments: 1,
+            ..DeliveryTimeouts::default()
+        },
+    };
+    let mut worker = OrderWorker::new(task_queue, gateway.clone(), sink.clone())
+        .with_couriers(couriers)
+        .build(&runtime, client.clone());
+
+    let workflow_id = format!("e2e-order-delivery-failed-{}", uuid::Uuid::new_v4());
+
+    let handle = client
+        .start_workflow(
+            vec![sample_order().as_json_payload().unwrap()],
+            task_queue.to_string(),
+            workflow_id.clone(),
+            "order_workflow".to_string(),
+            None,
+            WorkflowOptions::default(),
+        )
+        .await
+        .expect("Failed to start workflow");
+
+    let worker_fut = worker.run();
+    let wf_handle = client.get_untyped_workflow_handle(&workflow_id, handle.run_id.clone());
+    let result_fut = async {
+        wait_for_handlers().await;
+        checkout_and_wait_for_payment(&client, &workflow_id).await;
+        for status in [OrderStatus::Accepted, OrderStatus::Preparing, OrderStatus::Ready] {
+            update_order_status(&client, &workflow_id, status)
+                .await
+                .expect("Failed to update order status");
+        }
+
+        // Only the courier can fail a delivery
+        let failed = update_order_status(&client, &workflow_id, OrderStatus::DeliveryFailed).await;
+        assert!(failed.unwrap_err().to_string().contains("only the courier"));
+
+        // Neither courier turns up, and only one may replace the first
+        wf_handle.get_workflow_result(Default::default()).await
+    };
+
+    let result = tokio::select! {
+        res = result_fut => res.expect("Failed to get workflow result"),
+        _ = worker_fut => panic!("Worker stopped unexpectedly"),
+    };
+
+    let outcome = match result {
+        temporal_client::WorkflowExecutionResult::Succeeded(payloads) => {
+            OrderOutcome::from_json_payload(payloads.first().unwrap()).unwrap()
+        }
+        _ => panic!("Workflow should have succeeded"),
+    };
+
+    assert_eq!(outcome.status, OrderStatus::DeliveryFailed);
+    assert!(outcome.delivery.is_none());
+    assert_eq!(outcome.order.delivery_failure.as_deref(), Some("no courier could deliver it"));
+    assert_eq!(pool.assignments(), vec!["courier-1", "courier-2"]);
+    assert!(pool.busy().is_empty());
+
+    // Everything the customer paid was given back, and they were told why
+    assert_eq!(net_paid(&outcome.order.transactions).unwrap().amount_in_pence, 0);
+    assert_eq!(gateway.refunds().len(), 1);
+    assert_eq!(emailed_statuses(&sink).last(), Some(&OrderStatus::DeliveryFailed));
+
+    println!("✅ Undelivered order refunded");
+
+    server.shutdown().await.unwrap();
+}
+
+/// 🌐 The web UI's API drives an order from basket to collection
+#[tokio::test]
+#[ignore] // Only run explicitly with: cargo test --test e2e_ephemeral_tests -- --ignored
+async fn e2e_api_order_lifecycle() {
+    let (mut server, client) = start_server().await;
+
+    let telemetry = TelemetryOptionsBuilder::default().build().unwrap();
+    let runtime = CoreRuntime::new_assume_tokio(telemetry).unwrap();
+
+    // The API starts orders on the worker binary's task queue
+    let gateway = Arc::new(FakePaymentGateway::new());
+    let sink = Arc::new(MemorySink::new());
+    let mut worker =
+        OrderWorker::new(ORDER_FOOD_TASK_QUEUE, gateway.clone(), sink.clone()).build(&runtime, client.clone());
+
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let base = format!("http://{}/api/order", listener.local_addr().unwrap());
+    let app = router(ApiState::new(client.clone()).with_admin_token("secret"));
+    tokio::spawn(async move { axum::serve(listener, app).await });
+
+    let worker_fut = worker.run();
+    let result_fut = async {
+        let http = reqwest::Client::new();
+
+        // Without a status the basket stays open until it is checked out
+        let created: serde_json::Value = http
+            .post(&base)
+            .json(&serde_json::json!({
+                "collection": true,
+                "email": "customer@example.com",
+                "products": [{ "productId": 1, "quantity": 2 }]
+            }))
+            .send()
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+        let order_id = created["orderId"].as_str().expect("No order ID").to_string();
+        wait_for_handlers().await;
+
+        let basket: serde_json::Value = http
+            .post(format!("{}/{}/items", base, order_id))
+            .json(&serde_json::json!({ "productId": 5, "quantity": 1 }))
+            .send()
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+        assert_eq!(basket.as_array().unwrap().len(), 2);
+
+        let basket: serde_json::Value = http
+            .delete(format!("{}/{}/items/1", base, order_id))
+            .send()
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+        assert_eq!(basket[0]["quantity"], 1);
+
+        // Updates the workflow rejects become bad requests
+        let unknown = http
+            .post(format!("{}/{}/items", base, order_id))
+            .json(&serde_json::json!({ "productId": 999, "quantity": 1 }))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(unknown.status(), 400);
+
+        let checked_out = http.post(format!("{}/{}/checkout", base, order_id)).send().await.unwrap();
+        assert_eq!(checked_out.status(), 202);
+        wait_for_status(&client, &order_id, OrderStatus::Pending).await;
+
+        // Admin endpoints need the token
+        let listed = http.get(&base).send().await.unwrap();
+        assert_eq!(listed.status(), 401);
+
+        // The list comes from visibility, which may take a moment to catch up
+        let mut listed = serde_json::Value::Null;
+        for _ in 0..20 {
+            let list: serde_json::Value = http
+                .get(&base)
+                .bearer_auth("secret")
+                .send()
+                .await
+                .unwrap()
+                .json()
+                .await
+                .unwrap();
+            let found = list["orders"]
+                .as_array()
+                .unwrap()
+                .iter()
+                .find(|o| o["orderId"] == order_id.as_str())
+                .cloned();
+            if let Some(order) = found.filter(|o| o["state"]["status"] == "PENDING") {
+                listed = order;
+                break;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(500)).await;
+        }
+        assert_eq!(listed["state"]["status"], "PENDING", "Order not listed as pending");
+        assert!(listed["state"]["total"].as_f64().unwrap() > 0.0);
+        let products = listed["state"]["products"].as_array().expect("Order listed without products");
+        assert_eq!(products.len(), 2);
+        assert!(products.iter().all(|p| p["name"].is_string() && p["price"].as_f64().unwrap() > 0.0));
+
+        // The web UI's order form posts a status and never checks out, so the API does
+        let legacy: serde_json::Value = http
+            .post(&base)
+            .json(&serde_json::json!({
+                "collection": true,
+                "deliveryAddress": null,
+                "email": "customer@example.com",
+                "status": "DEFAULT",
+                "products": [{ "productId": 1, "quantity": 1 }]
+            }))
+            .send()
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+        let legacy_id = legacy["orderId"].as_str().expect("No order ID").to_string();
+        wait_for_status(&client, &legacy_id, OrderStatus::Pending).await;
+        cancel_order(&client, &legacy_id).await.expect("Failed to cancel order");
+
+        let bogus = http
+            .post(format!("{}/{}/status", base, order_id))
+            .bearer_auth("secret")
+            .json(&serde_json::json!({ "status": "BOGUS" }))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(bogus.status(), 400);
+
+        for status in ["ACCEPTED", "PREPARING", "READY", "COMPLETED"] {
+            let updated = http
+                .post(format!("{}/{}/status", base, order_id))
+                .bearer_auth("secret")
+                .json(&serde_json::json!({ "status": status }))
+                .send()
+                .await
+                .unwrap();
+            assert_eq!(updated.status(), 200, "Failed to move order to {}", status);
+        }
+
+        let outcome = get_order_outcome(&client, &order_id)
+            .await
+            .expect("Failed to get order outcome");
+
+        // Finished orders still show their final state, but can no longer be changed
+        let view: serde_json::Value = http
+            .get(format!("{}/{}/status", base, order_id))
+            .send()
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+        assert_eq!(view["status"], "COMPLETED");
+        assert_eq!(view["total"], "£15.98");
+
+        let late = http
+            .post(format!("{}/{}/items", base, order_id))
+            .json(&serde_json::json!({ "productId": 1, "quantity": 1 }))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(late.status(), 409);
+
+        let missing = http.get(format!("{}/order-missing/status", base)).send().await.unwrap();
+        assert_eq!(missing.status(), 404);
+
+        outcome
+    };
+
+    let outcome = tokio::select! {
+        outcome = result_fut => outcome,
+        _ = worker_fut => panic!("Worker stopped unexpectedly"),
+    };
+
+    assert_eq!(outcome.status, OrderStatus::Completed);
+    assert_eq!(gateway.captures().len(), 1);
+
+    println!("✅ Order driven through the API");
+
+    server.shutdown().await.unwrap();
+}
+
+/// Read a server-sent event stream to its end, returning each event's ID and status
+async fn read_status_events(mut response: reqwest::Response) -> Vec<(usize, String)> {
+    let mut body = String::new();
+    while let Some(chunk) = response.chunk().await.expect("Failed to read event stream") {
+        body.push_str(&String::from_utf8_lossy(&chunk));
+    }
+
+    let mut events = Vec::new();
+    let mut id = None;
+    for line in body.lines() {
+        if let Some(value) = line.strip_prefix("id:") {
+            id = Some(value.trim().parse().unwrap());
+        } else if let Some(value) = line.strip_prefix("data:") {
+            let data: serde_json::Value = serde_json::from_str(value.trim()).unwrap();
+            events.push((id.take().expect("Event has no ID"), data["status"].as_str().unwrap().to_string()));
+        }
+    }
+    events
+}
+
+/// 📡 Status changes are pushed as they happen, and reconnecting clients pick up where they left off
+#[tokio::test]
+#[ignore] // Only run explicitly with: cargo test --test e2e_ephemeral_tests -- --ignored
+async fn e2e_api_status_events() {
+    let (mut server, client) = start_server().await;
+
+    let telemetry = TelemetryOptionsBuilder::default().build().unwrap();
+    let runtime = CoreRuntime::new_assume_tokio(telemetry).unwrap();
+
+    let gateway = Arc::new(FakePaymentGateway::new());
+    let sink = Arc::new(MemorySink::new());
+    // The worker pushes status changes straight to the API it shares the process with
+    let api = ApiState::new(client.clone());
+    let mut worker = OrderWorker::new(ORDER_FOOD_TASK_QUEUE, gateway, sink)
+        .with_publisher(Arc::new(api.clone()))
+        .build(&runtime, client.clone());
+
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let base = format!("http://{}/api/order", listener.local_addr().unwrap());
+    let app = router(api);
+    tokio::spawn(async move { axum::serve(listener, app).await });
+
+    let worker_fut = worker.run();
+    let result_fut = async {
+        let http = reqwest::Client::new();
+
+        let created: serde_json::Value = http
+            .post(&base)
+            .json(&serde_json::json!({
+                "collection": true,
+                "email": "customer@example.com",
+                "products": [{ "productId": 1, "quantity": 1 }]
+            }))
+            .send()
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+        let order_id = created["orderId"].as_str().expect("No order ID").to_string();
+        wait_for_handlers().await;
+
+        // Without a token the admin endpoints are disabled, not open
+        let listed = http.get(&base).send().await.unwrap();
+        assert_eq!(listed.status(), 403);
+        // ...so nobody else can push status changes to the order's customers
+        let pushed = http
+            .post(format!("{}/{}/status-changes", base, order_id))
+            .json(&serde_json::json!([]))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(pushed.status(), 403);
+
+        let stream = http
+            .get(format!("{}/{}/events", base, order_id))
+            .send()
+            .await
+            .expect("Failed to open event stream");
+        assert_eq!(stream.status(), 200);
+
+        // A second client watching the same order is sent the same pushes
+        let shared = http
+            .get(format!("{}/{}/events", base, order_id))
+            .send()
+            .await
+            .expect("Failed to open event stream");
+
+        // The restaurant works through the order while the customers watch
+        let drive = async {
+            checkout_and_wait_for_payment(&client, &order_id).await;
+            for status in [
+                OrderStatus::Accepted,
+                OrderStatus::Preparing,
+                OrderStatus::Ready,
+                OrderStatus::Completed,
+            ] {
+                update_order_status(&client, &order_id, status)
+                    .await
+                    .expect("Failed to update order status");
+            }
+        };
+        let (live, shared, _) = tokio::join!(read_status_events(stream), read_status_events(shared), drive);
+
+        let outcome = get_order_outcome(&client, &order_id)
+            .await
+            .expect("Failed to get order outcome");
+
+        // A client that dropped out after ACCEPTED gets the rest from the finished order
+        let resumed = http
+            .get(format!("{}/{}/events", base, order_id))
+            .header("Last-Event-ID", "2")
+            .send()
+            .await
+            .expect("Failed to reopen event stream");
+        let resumed = read_status_events(resumed).await;
+
+        // A client that saw everything gets an empty stream
+        let caught_up = http
+            .get(format!("{}/{}/events?lastEventId=5", base, order_id))
+            .send()
+            .await
+            .expect("Failed to reopen event stream");
+        let caught_up = read_status_events(caught_up).await;
+
+        (live, shared, resumed, caught_up, outcome)
+    };
+
+    let (live, shared, resumed, caught_up, outcome) = tokio::select! {
+        res = result_fut => res,
+        _ = worker_fut => panic!("Worker stopped unexpectedly"),
+    };
+
+    let statuses = ["DEFAULT", "PENDING", "ACCEPTED", "PREPARING", "READY", "COMPLETED"];
+    let expected: Vec<(usize, String)> = statuses.iter().map(|s| s.to_string()).enumerate().collect();
+    assert_eq!(live, expected);
+    assert_eq!(shared, expected);
+    assert_eq!(resumed, expected[3..].to_vec());
+    assert!(caught_up.is_empty());
+    assert_eq!(outcome.transitions.len(), statuses.len());
+
+    println!("✅ Status changes streamed live and resumed");
+
+    server.shutdown().await.unwrap();
+}
+
+/// Poll the visibility store until listing with `filter` finds exactly `expected`
+async fn wait_for_listing(client: &RetryClient<Client>, filter: &OrderFilter, expected: &[&str]) -> Vec<RunningOrder> {
+    let mut ids = Vec::new();
+    for _ in 0..20 {
+        let orders = list_running_orders(client, filter).await.expect("Failed to list orders");
+        ids = orders.iter().map(|o| o.workflow_id.clone()).collect::<Vec<_>>();
+        ids.sort();
+        if ids == expected {
+            return orders;
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(500)).await;
+    }
+    panic!("Listing {:?} found {:?}, not {:?}", filter, ids, expected);
+}
+
+/// 🔎 In-flight orders are listed and filtered by the search attributes the workflow keeps
+#[tokio::test]
+#[ignore] // Only run explicitly with: cargo test --test e2e_ephemeral_tests -- --ignored
+async fn e2e_order_search_attributes() {
+    let (mut server, client) = start_server().await;
+
+    let telemetry = TelemetryOptionsBuilder::default().build().unwrap();
+    let runtime = CoreRuntime::new_assume_tokio(telemetry).unwrap();
+
+    let gateway = Arc::new(FakePaymentGateway::new());
+    let sink = Arc::new(MemorySink::new());
+    let mut worker = OrderWorker::new(ORDER_FOOD_TASK_QUEUE, gateway, sink).build(&runtime, client.clone());
+
+    let mut paid = sample_order();
+    paid.restaurant_id = Some("pizza-place".to_string());
+    let mut browsing = sample_order();
+    browsing.email = "someone.else@example.com".to_string();
+
+    let worker_fut = worker.run();
+    let result_fut = async {
+        start_order(&client, "e2e-search-paid", &paid).await.expect("Failed to start order");
+        start_order(&client, "e2e-search-browsing", &browsing)
+            .await
+            .expect("Failed to start order");
+        wait_for_handlers().await;
+        checkout_and_wait_for_payment(&client, "e2e-search-paid").await;
+
+        let all = wait_for_listing(&client, &OrderFilter::default(), &["e2e-search-browsing", "e2e-search-paid"]).await;
+
+        // Paid orders carry their status, total and restaurant
+        let pending = OrderFilter::default().with_status(OrderStatus::Pending);
+        let listed = wait_for_listing(&client, &pending, &["e2e-search-paid"]).await;
+        assert_eq!(listed[0].status, Some(OrderStatus::Pending));
+        assert_eq!(listed[0].total_in_pence, Some(2 * 1299 + 299));
+        assert_eq!(listed[0].restaurant_id.as_deref(), Some("pizza-place"));
+
+        let unpaid = OrderFilter::default().with_status(OrderStatus::Default);
+        wait_for_listing(&client, &unpaid, &["e2e-search-browsing"]).await;
+
+        wait_for_listing(&client, &OrderFilter::default().with_restaurant("pizza-place"), &["e2e-search-paid"]).await;
+        wait_for_listing(
+            &client,
+            &OrderFilter::default().with_email("Someone.Else@example.com"),
+            &["e2e-search-browsing"],
+        )
+        .await;
+
+        // Orders move between statuses as the restaurant works
+        update_order_status(&client, "e2e-search-paid", OrderStatus::Accepted)
+            .await
+            .expect("Failed to update order status");
+        wait_for_listing(&client, &pending, &[]).await;
+        wait_for_listing(
+            &client,
+            &OrderFilter::default().with_status(OrderStatus::Accepted),
+            &["e2e-search-paid"],
+        )
+        .await;
+
+        all
+    };
+
+    let all = tokio::select! {
+        all = result_fut => all,
+        _ = worker_fut => panic!("Worker stopped unexpectedly"),
+    };
+    assert_eq!(all.len(), 2);
+
+    println!("✅ Orders listed by status, restaurant and customer");
+
+    server.shutdown().await.unwrap();
+}
+
+/// 🍕 Orders from a restaurant are priced and ticketed by that restaurant's worker, and paid for centrally
+#[tokio::test]
+#[ignore] // Only run explicitly with: cargo test --test e2e_ephemeral_tests -- --ignored
+async fn e2e_order_routed_to_restaurant() {
+    let (mut server, client) = start_server().await;
+
+    let telemetry = TelemetryOptionsBuilder::default().build().unwrap();
+    let runtime = CoreRuntime::new_assume_tokio(telemetry).unwrap();
+
+    let task_queue = "e2e-order-restaurant";
+    let gateway = Arc::new(FakePaymentGateway::new());
+    let sink = Arc::new(MemorySink::new());
+    let mut central = OrderWorker::new(task_queue, gateway.clone(), sink).build(&runtime, client.clone());
+
+    // The restaurant's menu is cheaper than the central catalogue
+    let menu = FileCatalogue::from_json(
+        r#"{ "products": [
+            { "product_id": 1, "name": "Margherita Pizza", "price": 10.00 },
+            { "product_id": 5, "name": "Coca Cola", "price": 2.50 }
+        ] }"#,
+    )
+    .unwrap();
+    let printer = Arc::new(MemoryPrinter::new());
+    let mut restaurant = restaurant_worker(&runtime, client.clone(), "pizza-place", Arc::new(menu), printer.clone());
+
+    let mut order = sample_order();
+    order.restaurant_id = Some("pizza-place".to_string());
+    let workflow_id = format!("e2e-order-restaurant-{}", uuid::Uuid::new_v4());
+
+    let handle = client
+        .start_workflow(
+            vec![order.as_json_payload().unwrap()],
+            task_queue.to_string(),
+            workflow_id.clone(),
+            "order_workflow".to_string(),
+            None,
+            WorkflowOptions::default(),
+        )
+        .await
+        .expect("Failed to start workflow");
+
+    let central_fut = central.run();
+    let restaurant_fut = restaurant.run();
+    let wf_handle = client.get_untyped_workflow_handle(&workflow_id, handle.run_id.clone());
+    let result_fut = async {
+        wait_for_handlers().await;
+        checkout_and_wait_for_payment(&client, &workflow_id).await;
+
+        // Nothing is printed until the kitchen accepts the order
+        assert!(printer.printed().is_empty());
+        update_order_status(&client, &workflow_id, OrderStatus::Accepted)
+            .await
+            .expect("Failed to update order status");
+        assert_eq!(printer.printed().len(), 1);
+
+        complete_order(&client, &workflow_id).await;
+        wf_handle.get_workflow_result(Default::default()).await
+    };
+
+    let result = tokio::select! {
+        res = result_fut => res.expect("Failed to get workflow result"),
+        _ = central_fut => panic!("Central worker stopped unexpectedly"),
+        _ = restaurant_fut => panic!("Restaurant worker stopped unexpectedly"),
+    };
+
+    let outcome = match result {
+        temporal_client::WorkflowExecutionResult::Succeeded(payloads) => {
+            OrderOutcome::from_json_payload(payloads.first().unwrap()).unwrap()
+        }
+        _ => panic!("Workflow should have succeeded"),
+    };
+
+    // Charged at the restaurant's prices, through the central gateway
+    let charged = Amount {
+        amount_in_pence: 2 * 1000 + 250,
+        ..Default::default()
+    };
+    assert_eq!(outcome.status, OrderStatus::Completed);
+    assert_eq!(outcome.charged, Some(charged));
+    assert_eq!(gateway.captures().len(), 1);
+
+    assert_eq!(
+        printer.printed(),
+        vec![KitchenTicket {
+            order_id: workflow_id.clone(),
+            restaurant_id: Some("pizza-place".to_string()),
+            collection: false,
+            lines: vec![
+                TicketLine {
+                    product_id: 1,
+                    name: "Margherita Pizza".to_string(),
+                    quantity: 2,
+                },
+                TicketLine {
+                    product_id: 5,
+                    name: "Coca Cola".to_string(),
+                    quantity: 1,
+                },
+            ],
+            cancelled: false,
+        }]
+    );
+
+    println!("✅ Order priced and ticketed by its restaurant");
+
+    server.shutdown().await.unwrap();
+}
+
+/// Poll the order until the kitchen has either queued it or given it a slot
+async fn wait_for_kitchen_slot(client: &RetryClient<Client>, workflow_id: &str, granted: bool) -> KitchenSlot {
+    for _ in 0..20 {
+        let state = get_order_status(client, workflow_id)
+            .await
+            .expect("Failed to get order status");
+        match state.kitchen_slot {
+            Some(KitchenSlot::Granted) if granted => return KitchenSlot::Granted,
+            Some(slot @ KitchenSlot::Queued { .. }) if !granted => return slot,
+            _ => tokio::time::sleep(std::time::Duration::from_millis(500)).await,
+        }
+    }
+    panic!("Kitchen did not {} the order", if granted { "give a slot to" } else { "queue" });
+}
+
+/// Wait for the central kitchen to publish a queue with no orders in it
+async fn wait_for_empty_kitchen(client: &RetryClient<Client>) -> KitchenQueue {
+    for _ in 0..20 {
+        let kitchen = get_kitchen_status(client, None)
+            .await
+            .expect("Failed to get kitchen status");
+        if kitchen.preparing.is_empty() && kitchen.waiting.is_empty() {
+            return kitchen;
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(500)).await;
+    }
+    panic!("Kitchen did not give up its slots");
+}
+
+/// 🍳 A full kitchen queues accepted orders, first come first served, until a slot is free
+#[tokio::test]
+#[ignore] // Only run explicitly with: cargo test --test e2e_ephemeral_tests -- --ignored
+async fn e2e_order_kitchen_capacity() {
+    let (mut server, client) = start_server().await;
+
+    let telemetry = TelemetryOptionsBuilder::default().build().unwrap();
+    let runtime = CoreRuntime::new_assume_tokio(telemetry).unwrap();
+
+    let gateway = Arc::new(FakePaymentGateway::new());
+    let sink = Arc::new(MemorySink::new());
+    let mut worker = OrderWorker::new(ORDER_FOOD_TASK_QUEUE, gateway, sink).build(&runtime, client.clone());
+
+    // The central kitchen can only prepare one order at a time
+    let capacity = KitchenCapacity {
+        slots: 1,
+        prep_secs: 600,
+    };
+    start_kitchen(&client, None, capacity).await.expect("Failed to start kitchen");
+
+    let worker_fut = worker.run();
+    let result_fut = async {
+        for workflow_id in ["e2e-kitchen-first", "e2e-kitchen-second"] {
+            start_order(&client, workflow_id, &sample_order()).await.expect("Failed to start order");
+        }
+        wait_for_handlers().await;
+        for workflow_id in ["e2e-kitchen-first", "e2e-kitchen-second"] {
+            checkout_and_wait_for_payment(&client, workflow_id).await;
+            update_order_status(&client, workflow_id, OrderStatus::Accepted)
+                .await
+                .expect("Failed to accept order");
+        }
+
+        // The first order gets the only slot, and the second waits for it
+        wait_for_kitchen_slot(&client, "e2e-kitchen-first", true).await;
+        let queued = wait_for_kitchen_slot(&client, "e2e-kitchen-second", false).await;
+        assert!(matches!(queued, KitchenSlot::Queued { ahead: 0, estimated_wait_secs } if estimated_wait_secs <= 600));
+
+        let full = update_order_status(&client, "e2e-kitchen-second", OrderStatus::Preparing).await;
+        assert!(full.unwrap_err().to_string().contains("kitchen is full"));
+        let kitchen = get_kitchen_status(&client, None).await.expect("Failed to get kitchen status");
+        assert!(kitchen.is_preparing("e2e-kitchen-first"));
+        assert_eq!(kitchen.waiting, ["e2e-kitchen-second"]);
+
+        // The slot is freed once the first order's food is ready
+        update_order_status(&client, "e2e-kitchen-first", OrderStatus::Preparing)
+            .await
+            .expect("Failed to prepare order");
+        hand_over_order(&client, "e2e-kitchen-first").await;
+        wait_for_kitchen_slot(&client, "e2e-kitchen-second", true).await;
+        update_order_status(&client, "e2e-kitchen-second", OrderStatus::Preparing)
+            .await
+            .expect("Failed to prepare order");
+        hand_over_order(&client, "e2e-kitchen-second").await;
+
+        let first = get_order_outcome(&client, "e2e-kitchen-first").await.expect("Failed to get order outcome");
+        let second = get_order_outcome(&client, "e2e-kitchen-second").await.expect("Failed to get order outcome");
+        let kitchen = wait_for_empty_kitchen(&client).await;
+        (first, second, kitchen)
+    };
+
+    let (first, second, kitchen) = tokio::select! {
+        res = result_fut => res,
+        _ = worker_fut => panic!("Worker stopped unexpectedly"),
+    };
+
+    assert_eq!(first.status, OrderStatus::Completed);
+    assert_eq!(second.status, OrderStatus::Completed);
+    assert_eq!(second.order.kitchen_slot, None);
+    assert!(kitchen.preparing.is_empty());
+    assert!(kitchen.waiting.is_empty());
+
+    println!("✅ Kitchen prepared one order at a time, in turn");
+
+    server.shutdown().await.unwrap();
+}
@@ -14,7 +14,47 @@
  * limitations under the License.
  */
 
-use food_ordering_rust::types::{Address, OrderProduct, OrderState, OrderStatus};
+use food_ordering_rust::api::{
+    first_unseen, ApiError, NewOrder, OrderSummary, OrderSummaryState, OrderView, StatusEvent, SummaryProduct,
+};
+use food_ordering_rust::constants::{restaurant_task_queue, SearchAttributes, ORDER_FOOD_TASK_QUEUE};
+use food_ordering_rust::catalogue::{FileCatalogue, ProductCatalogue, SampleCatalogue};
+use food_ordering_rust::client::RunningOrder;
+use food_ordering_rust::couriers::{
+    courier_workflow_id, AssignCourier, CourierError, CourierPool, DeliveryError, DeliveryJob, DeliveryReport,
+    DeliveryTimeouts, FakeCourierPool,
+};
+use food_ordering_rust::capacity::{
+    kitchen_workflow_id, CapacityError, KitchenCapacity, KitchenQueue, KitchenRequest, KitchenSlot, SlotError,
+};
+use food_ordering_rust::eta::{prep_minutes, DeliveryTimes, Eta, DEFAULT_PREP_MINUTES};
+use food_ordering_rust::kitchen::{KitchenTicket, MemoryPrinter, TicketLine, TicketPrinter};
+use food_ordering_rust::notifications::{
+    notify_all, Channel, EmailNotifier, FileSink, MemorySink, MessageTemplate, NotificationKind, Notifier,
+    SmsNotifier, WebhookNotifier, SMS_MAX_LENGTH,
+};
+use food_ordering_rust::payments::{
+    idempotency_key, net_paid, plan_refunds, total_charged, total_refunded, Capture, FakePaymentGateway,
+    PaymentError, PaymentGateway, Refund, RefundRequest, ScriptedOutcome, Transaction,
+};
+use food_ordering_rust::pricing::{price_order, to_pence, Amount, AppliedDiscount, Currency, PricingError};
+use food_ordering_rust::promotions::{
+    Discount, LocalPromotions, Promotion, PromotionError, PromotionStore, PromotionStoreError,
+};
+use food_ordering_rust::receipts::{
+    receipt_key, BlobStore, BlobStoreError, LocalBlobStore, MemoryBlobStore, Receipt, ReceiptError, StoredReceipt,
+    VatBreakdown,
+};
+use food_ordering_rust::search::{dev_server_args, email_hash, order_search_attributes, OrderFilter};
+use food_ordering_rust::sla::{Breach, Deadline, SlaError, SlaPolicy};
+use food_ordering_rust::types::{
+    is_valid_post_code, Address, BasketError, CancelError, Fulfilment, FulfilmentError, OrderInputError, OrderOutcome,
+    OrderProduct, OrderState, OrderStatus, RestaurantStatusError, StatusChange, StatusTransitionError,
+};
+use food_ordering_rust::workflows::order_state_from_args;
+use std::sync::Arc;
+use temporal_sdk::ActivityError;
+use temporal_sdk_core_protos::coresdk::AsJsonPayloadExt;
 use tracing::info;
 
 #[tokio::test]
@@ -29,18 +69,18 @@ async fn test_order_state_operations() {
     order_state.add_item(OrderProduct {
         product_id: 1,
         quantity: 2,
-    });
+    }).unwrap();
     
     order_state.add_item(OrderProduct {
         product_id: 2,
         quantity: 1,
-    });
+    }).unwrap();
     
     // Test adding same item (should increase quantity)
     order_state.add_item(OrderProduct {
         product_id: 1,
         quantity: 1,
-    });
+    }).unwrap();
     
     assert_eq!(order_state.products.len(), 2);
     assert_eq!(order_state.products[0].quantity, 3); // 2 + 1
@@ -77,6 +117,10 @@ async fn test_order_status_parsing() {
     assert_eq!("READY".parse::<OrderStatus>().unwrap(), OrderStatus::Ready);
     assert_eq!("COMPLETED".parse::<OrderStatus>().unwrap(), OrderStatus::Completed);
     assert_eq!("REJECTED".parse::<OrderStatus>().unwrap(), OrderStatus::Rejected);
+    assert_eq!("PAYMENT_DECLINED".parse::<OrderStatus>().unwrap(), OrderStatus::PaymentDeclined);
+    assert_eq!("OUT_FOR_DELIVERY".parse::<OrderStatus>().unwrap(), OrderStatus::OutForDelivery);
+    assert_eq!("CANCELLED".parse::<OrderStatus>().unwrap(), OrderStatus::Cancelled);
+    assert_eq!("DELIVERY_FAILED".parse::<OrderStatus>().unwrap(), OrderStatus::DeliveryFailed);
     
     // Test case insensitive parsing
     assert_eq!("default".parse::<OrderStatus>().unwrap(), OrderStatus::Default);
@@ -85,6 +129,16 @@ async fn test_order_status_parsing() {
     // Test invalid status
     assert!("INVALID".parse::<OrderStatus>().is_err());
     
+    // Only completed, rejected, declined, cancelled and undelivered orders are finished
+    assert!(OrderStatus::Completed.is_terminal());
+    assert!(OrderStatus::Rejected.is_terminal());
+    assert!(OrderStatus::PaymentDeclined.is_terminal());
+    assert!(OrderStatus::Cancelled.is_terminal());
+    assert!(OrderStatus::DeliveryFailed.is_terminal());
+    assert!(!OrderStatus::Pending.is_terminal());
+    assert!(!OrderStatus::Ready.is_terminal());
+    assert!(!OrderStatus::OutForDelivery.is_terminal());
+
     // Test display formatting
     assert_eq!(format!("{}", OrderStatus::Default), "DEFAULT");
     assert_eq!(format!("{}", OrderStatus::Pending), "PENDING");
@@ -107,11 +161,23 @@ async fn test_order_state_serialization() {
             post_code: "12345".to_string(),
         }),
         email: "test@example.com".to_string(),
+        phone: None,
         products: vec![OrderProduct {
             product_id: 1,
             quantity: 2,
         }],
         status: OrderStatus::Pending,
+        pricing: None,
+        payment: None,
+        transactions: Vec::new(),
+        decline_reason: None,
+        delivery_failure: None,
+        courier_id: None,
+        restaurant_id: None,
+        promotion: None,
+        kitchen_slot: None,
+        eta: None,
+        receipt: None,
     };
     
     // Test serialization
@@ -127,3 +193,1403 @@ async fn test_order_state_serialization() {
     
     info!("Order state serialization test passed");
 }
+
+#[tokio::test]
+async fn test_order_state_from_workflow_args() {
+    let _ = tracing_subscriber::fmt::try_init();
+
+    let mut order_state = OrderState::new();
+    order_state.email = "test@example.com".to_string();
+    order_state.add_item(OrderProduct {
+        product_id: 3,
+        quantity: 4,
+    }).unwrap();
+
+    // Valid input is deserialized intact
+    let args = vec![order_state.as_json_payload().unwrap()];
+    let deserialized = order_state_from_args(&args).unwrap();
+    assert_eq!(deserialized.email, "test@example.com");
+    assert_eq!(deserialized.products.len(), 1);
+    assert_eq!(deserialized.products[0].product_id, 3);
+    assert_eq!(deserialized.products[0].quantity, 4);
+
+    // Missing input is a typed error rather than a panic
+    assert!(matches!(order_state_from_args(&[]), Err(OrderInputError::MissingInput)));
+
+    // As is input of the wrong shape
+    let args = vec!["not an order".as_json_payload().unwrap()];
+    assert!(matches!(order_state_from_args(&args), Err(OrderInputError::Malformed(_))));
+
+    info!("Order state from workflow args test passed");
+}
+
+#[tokio::test]
+async fn test_order_product_validation() {
+    let _ = tracing_subscriber::fmt::try_init();
+
+    // Products in the catalogue are accepted once priced
+    let pizza = OrderProduct { product_id: 1, quantity: 2 };
+    assert_eq!(pizza.check_quantity(), Ok(()));
+    let priced = price_order(&SampleCatalogue, std::slice::from_ref(&pizza)).unwrap();
+    assert_eq!(pizza.check_in_stock(&priced), Ok(()));
+    assert_eq!(priced.line_items[0].name, "Margherita Pizza");
+
+    // Unknown products and empty quantities are not
+    let unknown = OrderProduct { product_id: 999, quantity: 1 };
+    assert_eq!(unknown.check_in_stock(&priced), Err(BasketError::UnknownProduct(999)));
+    assert_eq!(
+        OrderProduct { product_id: 1, quantity: 0 }.check_quantity(),
+        Err(BasketError::InvalidQuantity)
+    );
+
+    // Adding more of a product than a quantity can hold is refused, leaving the basket as it was
+    let mut order = OrderState::new();
+    order.add_item(OrderProduct { product_id: 1, quantity: u32::MAX }).unwrap();
+    let more = OrderProduct { product_id: 1, quantity: 1 };
+    assert_eq!(order.check_can_add(&more), Err(BasketError::QuantityTooLarge(1)));
+    assert_eq!(order.add_item(more), Err(BasketError::QuantityTooLarge(1)));
+    assert_eq!(order.products[0].quantity, u32::MAX);
+    assert_eq!(order.check_can_add(&OrderProduct { product_id: 2, quantity: 1 }), Ok(()));
+
+    info!("Order product validation test passed");
+}
+
+#[tokio::test]
+async fn test_order_status_transitions() {
+    let _ = tracing_subscriber::fmt::try_init();
+
+    // The happy path through the kitchen is legal
+    assert!(OrderStatus::Default.can_transition_to(&OrderStatus::Pending));
+    assert!(OrderStatus::Pending.can_transition_to(&OrderStatus::Accepted));
+    assert!(OrderStatus::Accepted.can_transition_to(&OrderStatus::Preparing));
+    assert!(OrderStatus::Preparing.can_transition_to(&OrderStatus::Ready));
+    assert!(OrderStatus::Ready.can_transition_to(&OrderStatus::Completed));
+
+    // The kitchen can reject until the food is ready
+    assert!(OrderStatus::Pending.can_transition_to(&OrderStatus::Rejected));
+    assert!(OrderStatus::Preparing.can_transition_to(&OrderStatus::Rejected));
+    assert!(!OrderStatus::Ready.can_transition_to(&OrderStatus::Rejected));
+
+    // Only an unpaid order can have its payment declined
+    assert!(OrderStatus::Default.can_transition_to(&OrderStatus::PaymentDeclined));
+    assert!(!OrderStatus::Pending.can_transition_to(&OrderStatus::PaymentDeclined));
+
+    // Customers cancel until the restaurant accepts - the restaurant cannot cancel for them
+    assert!(OrderStatus::Default.is_cancellable());
+    assert!(OrderStatus::Pending.is_cancellable());
+    assert!(!OrderStatus::Accepted.is_cancellable());
+    assert!(!OrderStatus::Preparing.is_cancellable());
+    assert!(!OrderStatus::Pending.can_transition_to(&OrderStatus::Cancelled));
+    assert_eq!(
+        CancelError::TooLate(OrderStatus::Preparing).to_string(),
+        "order can no longer be cancelled - it is PREPARING"
+    );
+
+    // Skipping ahead or going backwards is not
+    assert!(!OrderStatus::Default.can_transition_to(&OrderStatus::Completed));
+    assert!(!OrderStatus::Completed.can_transition_to(&OrderStatus::Preparing));
+    assert!(!OrderStatus::Pending.can_transition_to(&OrderStatus::Pending));
+    assert_eq!(
+        OrderStatus::Completed.check_transition(&OrderStatus::Preparing),
+        Err(StatusTransitionError {
+            from: OrderStatus::Completed,
+            to: OrderStatus::Preparing,
+        })
+    );
+    assert_eq!(
+        OrderStatus::Completed
+            .check_transition(&OrderStatus::Preparing)
+            .unwrap_err()
+            .to_string(),
+        "cannot move order from COMPLETED to PREPARING"
+    );
+
+    // UIs are told what can happen next
+    assert_eq!(
+        OrderStatus::Pending.next_statuses(),
+        vec![OrderStatus::Accepted, OrderStatus::Rejected]
+    );
+    assert!(OrderStatus::Rejected.next_statuses().is_empty());
+
+    info!("Order status transitions test passed");
+}
+
+fn delivery_address(post_code: &str) -> Address {
+    Address {
+        line1: "1 High Street".to_string(),
+        line2: None,
+        line3: None,
+        town: "Testville".to_string(),
+        county: None,
+        post_code: post_code.to_string(),
+    }
+}
+
+#[tokio::test]
+async fn test_post_codes() {
+    let _ = tracing_subscriber::fmt::try_init();
+
+    // Every outward code shape, with or without the space and in any case
+    for post_code in ["M1 1AE", "B33 8TH", "CR2 6XH", "DN55 1PT", "W1A 0AX", "EC1A 1BB", "sw1a1aa", " ab1 2cd "] {
+        assert!(is_valid_post_code(post_code), "{} should be valid", post_code);
+    }
+
+    for post_code in ["", "12345", "AB1", "AB1 22D", "1AB 2CD", "ABC1 2CD", "AB1 2CDE", "AB-1 2CD"] {
+        assert!(!is_valid_post_code(post_code), "{} should be invalid", post_code);
+    }
+
+    info!("Post code test passed");
+}
+
+#[tokio::test]
+async fn test_fulfilment_validation() {
+    let _ = tracing_subscriber::fmt::try_init();
+
+    let delivery = |address: Option<Address>| Fulfilment {
+        collection: false,
+        delivery_address: address,
+    };
+
+    assert_eq!(delivery(Some(delivery_address("AB1 2CD"))).validate(), Ok(()));
+    assert_eq!(delivery(None).validate(), Err(FulfilmentError::MissingAddress));
+    assert_eq!(
+        delivery(Some(delivery_address("12345"))).validate(),
+        Err(FulfilmentError::InvalidPostCode("12345".to_string()))
+    );
+    assert_eq!(
+        delivery(Some(Address {
+            line1: " ".to_string(),
+            ..delivery_address("AB1 2CD")
+        }))
+        .validate(),
+        Err(FulfilmentError::MissingLine1)
+    );
+    assert_eq!(
+        delivery(Some(Address {
+            town: String::new(),
+            ..delivery_address("AB1 2CD")
+        }))
+        .validate(),
+        Err(FulfilmentError::MissingTown)
+    );
+
+    // Collection orders are picked up from the restaurant
+    let collection = |address: Option<Address>| Fulfilment {
+        collection: true,
+        delivery_address: address,
+    };
+    assert_eq!(collection(None).validate(), Ok(()));
+    assert_eq!(
+        collection(Some(delivery_address("AB1 2CD"))).validate(),
+        Err(FulfilmentError::UnexpectedAddress)
+    );
+
+    info!("Fulfilment validation test passed");
+}
+
+#[tokio::test]
+async fn test_fulfilment_transitions() {
+    let _ = tracing_subscriber::fmt::try_init();
+
+    // Delivery orders go out for delivery before they are completed, unless no courier
+    // can deliver them - the courier makes those moves, not the restaurant
+    let mut order = OrderState::new();
+    order.set_fulfilment(Fulfilment {
+        collection: false,
+        delivery_address: Some(delivery_address("AB1 2CD")),
+    });
+    order.status = OrderStatus::Ready;
+    assert!(order.restaurant_next_statuses().is_empty());
+    assert_eq!(
+        order.check_restaurant_transition(&OrderStatus::OutForDelivery),
+        Err(RestaurantStatusError::OutForDelivery)
+    );
+    assert_eq!(
+        order.check_restaurant_transition(&OrderStatus::DeliveryFailed),
+        Err(RestaurantStatusError::DeliveryFailed)
+    );
+    assert_eq!(
+        order.check_restaurant_transition(&OrderStatus::Completed),
+        Err(RestaurantStatusError::DeliveryCompleted)
+    );
+    order.status = OrderStatus::OutForDelivery;
+    assert!(order.restaurant_next_statuses().is_empty());
+
+    // Collection orders are completed by the restaurant when collected
+    order.set_fulfilment(Fulfilment {
+        collection: true,
+        delivery_address: None,
+    });
+    order.status = OrderStatus::Ready;
+    assert_eq!(order.restaurant_next_statuses(), vec![OrderStatus::Completed]);
+    assert_eq!(order.check_restaurant_transition(&OrderStatus::Completed), Ok(()));
+    assert_eq!(
+        order.check_restaurant_transition(&OrderStatus::Preparing),
+        Err(RestaurantStatusError::Transition(StatusTransitionError {
+            from: OrderStatus::Ready,
+            to: OrderStatus::Preparing,
+        }))
+    );
+
+    // Earlier statuses don't depend on how the order is fulfilled, but only the
+    // workflow moves an order on before it is paid for
+    order.status = OrderStatus::Pending;
+    assert_eq!(order.restaurant_next_statuses(), OrderStatus::Pending.next_statuses());
+    order.status = OrderStatus::Default;
+    assert!(order.restaurant_next_statuses().is_empty());
+    assert_eq!(
+        order.check_restaurant_transition(&OrderStatus::Pending),
+        Err(RestaurantStatusError::NotPaid)
+    );
+
+    info!("Fulfilment transitions test passed");
+}
+
+#[tokio::test]
+async fn test_order_pricing() {
+    let _ = tracing_subscriber::fmt::try_init();
+
+    let priced = price_order(&SampleCatalogue, &[
+        OrderProduct { product_id: 1, quantity: 2 }, // Margherita Pizza @ £12.99
+        OrderProduct { product_id: 5, quantity: 3 }, // Coca Cola @ £2.99
+    ])
+    .unwrap();
+
+    assert_eq!(priced.line_items.len(), 2);
+    assert_eq!(priced.line_items[0].name, "Margherita Pizza");
+    assert_eq!(priced.line_items[0].unit_price_in_pence, 1299);
+    assert_eq!(priced.line_items[0].total_in_pence, 2598);
+    assert_eq!(priced.line_items[1].total_in_pence, 897);
+    assert_eq!(priced.total_in_pence, 3495);
+    assert_eq!(priced.currency, Currency::Gbp);
+    assert_eq!(priced.total().to_string(), "£34.95");
+
+    // An empty basket costs nothing
+    assert_eq!(price_order(&SampleCatalogue, &[]).unwrap().total_in_pence, 0);
+
+    // Unknown products cannot be priced
+    assert_eq!(
+        price_order(&SampleCatalogue, &[OrderProduct { product_id: 999, quantity: 1 }]),
+        Err(PricingError::UnknownProduct(999))
+    );
+
+    // A basket without a promotion is priced as it is
+    let mut order_state = OrderState::new();
+    order_state.add_item(OrderProduct { product_id: 2, quantity: 1 }).unwrap();
+    let priced = price_order(&SampleCatalogue, &order_state.products).unwrap();
+    assert_eq!(order_state.discounted(priced).total_in_pence, 1499);
+
+    info!("Order pricing test passed");
+}
+
+#[tokio::test]
+async fn test_price_rounding() {
+    let _ = tracing_subscriber::fmt::try_init();
+
+    // 12.99 * 100 is 1298.9999... in floating point - it must not be truncated
+    assert_eq!(to_pence(1, 12.99), Ok(1299));
+    assert_eq!(to_pence(1, 0.29), Ok(29));
+    assert_eq!(to_pence(1, 3.5), Ok(350));
+
+    // Sub-penny prices round to the nearest penny
+    assert_eq!(to_pence(1, 2.994), Ok(299));
+    assert_eq!(to_pence(1, 2.996), Ok(300));
+
+    // Nonsense prices are rejected
+    assert_eq!(to_pence(7, -1.0), Err(PricingError::InvalidPrice(7)));
+    assert_eq!(to_pence(7, f64::NAN), Err(PricingError::InvalidPrice(7)));
+
+    // Amounts are displayed in major units
+    let amount = Amount {
+        amount_in_pence: 1005,
+        currency: Currency::Gbp,
+    };
+    assert_eq!(amount.to_string(), "£10.05");
+
+    info!("Price rounding test passed");
+}
+
+#[tokio::test]
+async fn test_file_catalogue() {
+    let _ = tracing_subscriber::fmt::try_init();
+
+    let json = FileCatalogue::from_json(
+        r#"{
+            "products": [
+                { "product_id": 1, "name": "Chips", "price": 3.5 },
+                { "product_id": 2, "name": "Battered cod", "price": 8.75, "available": false }
+            ]
+        }"#,
+    )
+    .unwrap();
+
+    let toml = FileCatalogue::from_toml(
+        r#"
+            [[products]]
+            product_id = 1
+            name = "Chips"
+            price = 3.5
+
+            [[products]]
+            product_id = 2
+            name = "Battered cod"
+            price = 8.75
+            available = false
+        "#,
+    )
+    .unwrap();
+
+    for catalogue in [&json as &dyn ProductCatalogue, &toml] {
+        assert_eq!(catalogue.products().len(), 2);
+
+        // Products are available unless flagged otherwise
+        assert!(catalogue.find(1).unwrap().available);
+        assert!(!catalogue.find(2).unwrap().available);
+
+        // Workflows only see stock through a priced basket, which still prices the cod so
+        // it can be removed
+        let chips = OrderProduct { product_id: 1, quantity: 1 };
+        let cod = OrderProduct { product_id: 2, quantity: 1 };
+        let priced = price_order(catalogue, &[chips.clone(), cod.clone()]).unwrap();
+        assert!(chips.check_in_stock(&priced).is_ok());
+        assert_eq!(cod.check_in_stock(&priced), Err(BasketError::OutOfStock(2)));
+        let missing = OrderProduct { product_id: 3, quantity: 1 };
+        assert_eq!(missing.check_in_stock(&priced), Err(BasketError::UnknownProduct(3)));
+
+        // Orders are priced from this catalogue, not the sample one
+        assert_eq!(price_order(catalogue, &[chips]).unwrap().total_in_pence, 350);
+    }
+
+    // The sample catalogue shipped with the crate loads
+    let shipped = FileCatalogue::load(concat!(env!("CARGO_MANIFEST_DIR"), "/products.json")).unwrap();
+    assert_eq!(shipped.products().len(), 5);
+
+    // Unknown formats are rejected
+    assert!(FileCatalogue::load("products.yaml").is_err());
+
+    info!("File catalogue test passed");
+}
+
+#[tokio::test]
+async fn test_promotions() {
+    let _ = tracing_subscriber::fmt::try_init();
+
+    // £25.98 of pizza and £2.99 of cola
+    let priced = price_order(&SampleCatalogue, &[
+        OrderProduct { product_id: 1, quantity: 2 },
+        OrderProduct { product_id: 5, quantity: 1 },
+    ])
+    .unwrap();
+    let promotion = |code: &str, discount, min_spend_in_pence| Promotion {
+        code: code.to_string(),
+        discount,
+        min_spend_in_pence,
+    };
+
+    // Percentages round to the nearest penny
+    let tenth = promotion("TENTH", Discount::Percentage { percent: 10 }, 0);
+    assert_eq!(tenth.apply(&priced).unwrap(), AppliedDiscount {
+        code: "TENTH".to_string(),
+        amount_in_pence: 290,
+    });
+
+    // Fixed amounts never take the order below nothing
+    let fiver = promotion("FIVER", Discount::FixedAmount { amount_in_pence: 500 }, 0);
+    assert_eq!(fiver.apply(&priced).unwrap().amount_in_pence, 500);
+    let huge = promotion("HUGE", Discount::FixedAmount { amount_in_pence: 10_000 }, 0);
+    assert_eq!(huge.apply(&priced).unwrap().amount_in_pence, 2897);
+
+    // A free item is one of that product, if it is in the basket
+    let cola = promotion("COLA", Discount::FreeItem { product_id: 5 }, 0);
+    assert_eq!(cola.apply(&priced).unwrap().amount_in_pence, 299);
+    let salad = promotion("SALAD", Discount::FreeItem { product_id: 3 }, 0);
+    assert_eq!(salad.apply(&priced), Err(PromotionError::ItemNotInBasket {
+        code: "SALAD".to_string(),
+        product_id: 3,
+    }));
+
+    // The minimum spend is checked against the line items
+    let big_spender = promotion("BIG", Discount::Percentage { percent: 20 }, 3000);
+    assert!(matches!(big_spender.apply(&priced), Err(PromotionError::MinimumSpend { .. })));
+
+    // The discount comes off the total, and can be taken off again
+    let mut discounted = priced.clone();
+    discounted.apply_discount(fiver.apply(&priced).unwrap());
+    assert_eq!(discounted.subtotal_in_pence(), 2897);
+    assert_eq!(discounted.total().to_string(), "£23.97");
+    discounted.remove_discount();
+    assert_eq!(discounted, priced);
+
+    // The order keeps its promotion on the live price while the basket qualifies
+    let mut order_state = OrderState::new();
+    order_state.add_item(OrderProduct { product_id: 1, quantity: 2 }).unwrap();
+    order_state.promotion = Some(promotion("BIG", Discount::FixedAmount { amount_in_pence: 500 }, 2000));
+    let priced = price_order(&SampleCatalogue, &order_state.products).unwrap();
+    assert_eq!(order_state.discounted(priced).total_in_pence, 2098);
+    order_state.remove_item(OrderProduct { product_id: 1, quantity: 1 });
+    let priced = order_state.discounted(price_order(&SampleCatalogue, &order_state.products).unwrap());
+    assert_eq!(priced.total_in_pence, 1299);
+    assert_eq!(priced.discount, None);
+
+    // Codes are looked up ignoring case and surrounding spaces
+    let store = LocalPromotions::from_json(
+        r#"{
+            "promotions": [
+                { "code": "WELCOME10", "discount": { "type": "percentage", "percent": 10 } },
+                {
+                    "code": "FIVEOFF",
+                    "discount": { "type": "fixed_amount", "amount_in_pence": 500 },
+                    "min_spend_in_pence": 2500
+                }
+            ]
+        }"#,
+    )
+    .unwrap();
+    assert_eq!(store.find(" welcome10 ").unwrap().discount, Discount::Percentage { percent: 10 });
+    assert_eq!(store.find("FIVEOFF").unwrap().min_spend_in_pence, 2500);
+    assert!(store.find("NOPE").is_none());
+    assert!(LocalPromotions::default().find("WELCOME10").is_none());
+
+    // More than everything off is a mistake in the file
+    let too_generous = r#"{ "promotions": [{ "code": "X", "discount": { "type": "percentage", "percent": 150 } }] }"#;
+    assert!(matches!(
+        LocalPromotions::from_json(too_generous),
+        Err(PromotionStoreError::InvalidPercentage(_))
+    ));
+
+    // The promotions shipped with the crate load, and unknown codes are not retried
+    let shipped = LocalPromotions::load(concat!(env!("CARGO_MANIFEST_DIR"), "/promotions.json")).unwrap();
+    assert!(shipped.find("FREECOLA").is_some());
+    let err: ActivityError = PromotionError::UnknownCode("NOPE".to_string()).into();
+    assert!(matches!(err, ActivityError::NonRetryable(_)));
+
+    info!("Promotions test passed");
+}
+
+#[tokio::test]
+async fn test_fake_payment_gateway() {
+    let _ = tracing_subscriber::fmt::try_init();
+
+    let amount = Amount { amount_in_pence: 2897, currency: Currency::Gbp };
+    let gateway = FakePaymentGateway::new().with_script([
+        ScriptedOutcome::Decline("insufficient funds".to_string()),
+        ScriptedOutcome::Timeout,
+    ]);
+
+    // Scripted outcomes are used in order, then everything is approved
+    assert_eq!(
+        gateway.authorize("order-1/1/authorize", amount).await,
+        Err(PaymentError::Declined("insufficient funds".to_string()))
+    );
+    assert_eq!(gateway.authorize("order-1/1/authorize", amount).await, Err(PaymentError::Timeout));
+    let authorization = gateway.authorize("order-1/1/authorize", amount).await.unwrap();
+    assert_eq!(authorization.amount, amount);
+
+    // A retried capture with the same key does not charge twice
+    let capture = gateway.capture("order-1/1/capture", &authorization).await.unwrap();
+    let retried = gateway.capture("order-1/1/capture", &authorization).await.unwrap();
+    assert_eq!(capture, retried);
+    assert_eq!(gateway.captures(), vec![capture.clone()]);
+
+    let request = RefundRequest { transaction_id: capture.transaction_id.clone(), amount };
+    let refund = gateway.refund("order-1/2/refund", &request).await.unwrap();
+    assert_eq!(refund.transaction_id, capture.transaction_id);
+    assert_eq!(gateway.refund("order-1/2/refund", &request).await.unwrap(), refund);
+    assert_eq!(gateway.refunds().len(), 1);
+
+    info!("Fake payment gateway test passed");
+}
+
+#[tokio::test]
+async fn test_payment_errors() {
+    let _ = tracing_subscriber::fmt::try_init();
+
+    // Declines are final, anything else may succeed on retry
+    let declined: ActivityError = PaymentError::Declined("card expired".to_string()).into();
+    assert!(matches!(declined, ActivityError::NonRetryable(_)));
+
+    for error in [PaymentError::Timeout, PaymentError::Unavailable("maintenance".to_string())] {
+        assert!(error.is_retryable());
+        let error: ActivityError = error.into();
+        assert!(matches!(error, ActivityError::Retryable { .. }));
+    }
+
+    // Keys are stable for an activity and distinct per operation
+    let key = idempotency_key("order-1", "run-1", "3", "capture");
+    assert_eq!(key, idempotency_key("order-1", "run-1", "3", "capture"));
+    assert_ne!(key, idempotency_key("order-1", "run-1", "3", "authorize"));
+    assert_ne!(key, idempotency_key("order-2", "run-1", "3", "capture"));
+    // A reused order ID starts its activity IDs again, but in a new run
+    assert_ne!(key, idempotency_key("order-1", "run-2", "3", "capture"));
+
+    info!("Payment errors test passed");
+}
+
+#[tokio::test]
+async fn test_transaction_history() {
+    let _ = tracing_subscriber::fmt::try_init();
+
+    let pence = |amount_in_pence| Amount {
+        amount_in_pence,
+        currency: Currency::Gbp,
+    };
+    let charge = |transaction_id: &str, amount_in_pence| {
+        Transaction::Charge(Capture {
+            transaction_id: transaction_id.to_string(),
+            amount: pence(amount_in_pence),
+        })
+    };
+    let refund = |transaction_id: &str, amount_in_pence| {
+        Transaction::Refund(Refund {
+            refund_id: format!("refund-{}", transaction_id),
+            transaction_id: transaction_id.to_string(),
+            amount: pence(amount_in_pence),
+        })
+    };
+
+    // Nothing paid yet
+    assert_eq!(net_paid(&[]), Ok(Amount::default()));
+    assert_eq!(total_charged(&[]), Ok(None));
+    assert!(plan_refunds(&[], pence(100)).is_empty());
+
+    // Paid £20, topped up by £5, then £3 given back
+    let history = vec![charge("txn-1", 2000), charge("txn-2", 500), refund("txn-2", 300)];
+    assert_eq!(total_charged(&history), Ok(Some(pence(2500))));
+    assert_eq!(total_refunded(&history), Ok(Some(pence(300))));
+    assert_eq!(net_paid(&history), Ok(pence(2200)));
+
+    // Totals too large to hold are an error rather than wrapping round
+    let huge = vec![charge("txn-1", u64::MAX), charge("txn-2", 1)];
+    assert_eq!(total_charged(&huge), Err(PricingError::Overflow));
+    assert_eq!(net_paid(&huge), Err(PricingError::Overflow));
+
+    // Refunds come from the latest charge first, never more than is left of it
+    assert_eq!(plan_refunds(&history, pence(100)), vec![RefundRequest {
+        transaction_id: "txn-2".to_string(),
+        amount: pence(100),
+    }]);
+    assert_eq!(plan_refunds(&history, pence(2200)), vec![
+        RefundRequest {
+            transaction_id: "txn-2".to_string(),
+            amount: pence(200),
+        },
+        RefundRequest {
+            transaction_id: "txn-1".to_string(),
+            amount: pence(2000),
+        },
+    ]);
+
+    // There is only so much to give back
+    let requested: u64 = plan_refunds(&history, pence(5000)).iter().map(|r| r.amount.amount_in_pence).sum();
+    assert_eq!(requested, 2200);
+
+    // The history is tagged by type
+    let json = serde_json::to_value(&history[0]).unwrap();
+    assert_eq!(json["type"], "charge");
+    assert_eq!(json["transaction_id"], "txn-1");
+
+    info!("Transaction history test passed");
+}
+
+#[tokio::test]
+async fn test_fake_courier_pool() {
+    let _ = tracing_subscriber::fmt::try_init();
+
+    let pool = FakeCourierPool::new(["courier-1", "courier-2"]);
+    let job = DeliveryJob {
+        order_id: "order-1".to_string(),
+        address: delivery_address("AB1 2CD"),
+    };
+    let request = |avoid: &[&str]| AssignCourier {
+        job: job.clone(),
+        avoid: avoid.iter().map(|c| c.to_string()).collect(),
+    };
+
+    // Couriers are assigned in order, and a retried assignment gets the same courier
+    assert_eq!(pool.assign("order-1-courier/1/assign", &request(&[])).await.unwrap(), "courier-1");
+    assert_eq!(pool.assign("order-1-courier/1/assign", &request(&[])).await.unwrap(), "courier-1");
+    assert_eq!(pool.busy(), vec!["courier-1".to_string()]);
+
+    // Busy couriers are not assigned twice
+    assert_eq!(pool.assign("order-2-courier/1/assign", &request(&[])).await.unwrap(), "courier-2");
+    assert_eq!(
+        pool.assign("order-3-courier/1/assign", &request(&[])).await,
+        Err(CourierError::NoneAvailable)
+    );
+
+    // Couriers who timed out are only used again if nobody else is free
+    pool.release("courier-1").await.unwrap();
+    pool.release("courier-2").await.unwrap();
+    assert_eq!(
+        pool.assign("order-1-courier/2/assign", &request(&["courier-1"])).await.unwrap(),
+        "courier-2"
+    );
+    assert_eq!(
+        pool.assign("order-1-courier/3/assign", &request(&["courier-1", "courier-2"])).await.unwrap(),
+        "courier-1"
+    );
+    assert_eq!(
+        pool.assignments(),
+        vec!["courier-1", "courier-2", "courier-2", "courier-1"]
+    );
+
+    // Pool errors are worth retrying - couriers come back on shift
+    let error: ActivityError = CourierError::NoneAvailable.into();
+    assert!(matches!(error, ActivityError::Retryable { .. }));
+
+    info!("Fake courier pool test passed");
+}
+
+#[tokio::test]
+async fn test_delivery_types() {
+    let _ = tracing_subscriber::fmt::try_init();
+
+    assert_eq!(courier_workflow_id("order-1"), "order-1-courier");
+
+    let timeouts = DeliveryTimeouts::default();
+    assert_eq!(timeouts.pickup(), std::time::Duration::from_secs(15 * 60));
+    assert_eq!(timeouts.drop_off(), std::time::Duration::from_secs(45 * 60));
+    assert_eq!(timeouts.assign(), std::time::Duration::from_secs(30 * 60));
+
+    // Late couriers are replaced up to the limit, then the delivery is given up on
+    let late = |n: usize| (1..=n).map(|i| format!("courier-{}", i)).collect::<Vec<_>>();
+    assert!(timeouts.check_can_reassign(&late(3)).is_ok());
+    let error = timeouts.check_can_reassign(&late(4)).unwrap_err();
+    assert!(matches!(error, DeliveryError::TooManyReassignments { max: 3, .. }));
+    assert_eq!(error.to_string(), "gave up after 4 couriers ran out of time (3 reassignments allowed)");
+
+    // Reports survive the trip through a child workflow result payload
+    let report = DeliveryReport {
+        courier_id: "courier-2".to_string(),
+        timed_out: vec!["courier-1".to_string()],
+    };
+    let json = serde_json::to_string(&report).unwrap();
+    assert_eq!(serde_json::from_str::<DeliveryReport>(&json).unwrap(), report);
+
+    info!("Delivery types test passed");
+}
+
+#[tokio::test]
+async fn test_api_bodies() {
+    let _ = tracing_subscriber::fmt::try_init();
+
+    // The body the web UI posts to create an order
+    let order: NewOrder = serde_json::from_value(serde_json::json!({
+        "collection": false,
+        "deliveryAddress": {
+            "line1": "1 High Street",
+            "town": "Anytown",
+            "postCode": "AB1 2CD"
+        },
+        "email": "test@example.com",
+        "status": "PENDING",
+        "products": [{ "productId": 1, "quantity": 2 }, { "productId": 1, "quantity": 1 }]
+    }))
+    .unwrap();
+
+    let mut state = OrderState::try_from(order).unwrap();
+    assert_eq!(state.status, OrderStatus::Default);
+    assert_eq!(state.email, "test@example.com");
+    assert_eq!(state.delivery_address.as_ref().unwrap().post_code, "AB1 2CD");
+    assert!(state.fulfilment().validate().is_ok());
+    assert_eq!(state.products.len(), 1);
+    assert_eq!(state.products[0].quantity, 3);
+
+    // Customers see the order in camelCase, with its total once priced but without
+    // their contact details
+    state.pricing = Some(price_order(&SampleCatalogue, &state.products).unwrap());
+    let view = serde_json::to_value(OrderView::from(&state)).unwrap();
+    assert_eq!(view["status"], "DEFAULT");
+    assert_eq!(view["nextStatuses"], serde_json::json!([]));
+    assert_eq!(serde_json::from_value::<OrderView>(view.clone()).unwrap(), OrderView::from(&state));
+    assert!(view.get("email").is_none() && view.get("deliveryAddress").is_none());
+    assert_eq!(view["products"][0]["productId"], 1);
+    assert_eq!(view["total"], "£38.97");
+    assert!(view["courierId"].is_null());
+    assert!(view["ordersAhead"].is_null());
+
+    // While the kitchen is too busy to start on it, customers see how long it will be
+    state.kitchen_slot = Some(KitchenSlot::Queued {
+        ahead: 2,
+        estimated_wait_secs: 601,
+    });
+    let view = serde_json::to_value(OrderView::from(&state)).unwrap();
+    assert_eq!(view["ordersAhead"], 2);
+    assert_eq!(view["estimatedWaitMinutes"], 11);
+    assert!(view["eta"].is_null());
+
+    let mut eta = Eta::new(30);
+    eta.at = chrono::DateTime::from_timestamp(1_700_000_000, 0).unwrap();
+    state.eta = Some(eta);
+    let view = serde_json::to_value(OrderView::from(&state)).unwrap();
+    assert_eq!(view["eta"], "2023-11-14T22:13:20Z");
+    assert!(view["receiptUrl"].is_null());
+
+    // The admin list is built from search attributes and the memo alone, pricing in pounds
+    let mut listed = OrderState::new();
+    listed.collection = true;
+    listed.add_item(OrderProduct { product_id: 1, quantity: 2 }).unwrap();
+    let unpriced = OrderSummary::from(RunningOrder {
+        workflow_id: "order-1".to_string(),
+        started: None,
+        status: None,
+        restaurant_id: None,
+        total_in_pence: None,
+        order: Some(listed.clone()),
+    });
+    assert_eq!(unpriced.state.products, vec![SummaryProduct {
+        id: 1,
+        name: None,
+        price: None,
+        quantity: 2,
+    }]);
+
+    listed.pricing = Some(price_order(&SampleCatalogue, &listed.products).unwrap());
+    let summary = OrderSummary::from(RunningOrder {
+        workflow_id: "order-1".to_string(),
+        started: None,
+        status: Some(OrderStatus::Pending),
+        restaurant_id: Some("pizza-palace".to_string()),
+        total_in_pence: Some(3897),
+        order: Some(listed),
+    });
+    assert_eq!(summary.state, OrderSummaryState {
+        status: "PENDING".to_string(),
+        restaurant_id: Some("pizza-palace".to_string()),
+        total: Some(38.97),
+        collection: true,
+        products: vec![SummaryProduct {
+            id: 1,
+            name: Some("Margherita Pizza".to_string()),
+            price: Some(12.99),
+            quantity: 2,
+        }],
+    });
+    let json = serde_json::to_value(&summary).unwrap();
+    assert_eq!(json["orderId"], "order-1");
+    assert_eq!(json["state"]["restaurantId"], "pizza-palace");
+    assert_eq!(json["state"]["products"][0]["name"], "Margherita Pizza");
+
+    // Orders that have not set their search attributes yet are not paid for
+    let unlisted = RunningOrder {
+        workflow_id: "order-2".to_string(),
+        started: None,
+        status: None,
+        restaurant_id: None,
+        total_in_pence: None,
+        order: None,
+    };
+    let unlisted = OrderSummary::from(unlisted);
+    assert_eq!(unlisted.state.status, "DEFAULT");
+    assert!(unlisted.state.products.is_empty());
+
+    assert_eq!(ApiError::NotFound("order-1".to_string()).status_code(), 404);
+    assert_eq!(ApiError::Finished("order-1".to_string()).status_code(), 409);
+    assert_eq!(ApiError::BadRequest("bad".to_string()).status_code(), 400);
+    assert_eq!(ApiError::Unauthorized.status_code(), 401);
+
+    info!("API bodies test passed");
+}
+
+#[tokio::test]
+async fn test_status_events() {
+    let _ = tracing_subscriber::fmt::try_init();
+
+    // Event IDs count status changes from 0, so a client resumes after the last it saw
+    assert_eq!(first_unseen(None).unwrap(), 0);
+    assert_eq!(first_unseen(Some("0")).unwrap(), 1);
+    assert_eq!(first_unseen(Some("4")).unwrap(), 5);
+    assert_eq!(first_unseen(Some("latest")).unwrap_err().status_code(), 400);
+    assert!(first_unseen(Some("-1")).is_err());
+    assert_eq!(first_unseen(Some(&usize::MAX.to_string())).unwrap(), usize::MAX);
+
+    let change = StatusChange {
+        status: OrderStatus::OutForDelivery,
+        at: chrono::DateTime::from_timestamp(1_700_000_000, 0).unwrap(),
+    };
+    let event = serde_json::to_value(StatusEvent::from(&change)).unwrap();
+    assert_eq!(event["status"], "OUT_FOR_DELIVERY");
+    assert_eq!(event["at"], "2023-11-14T22:13:20Z");
+
+    info!("Status events test passed");
+}
+
+#[tokio::test]
+async fn test_search_attributes() {
+    let _ = tracing_subscriber::fmt::try_init();
+
+    // Customers are found by a hash of their normalised email address
+    let hash = email_hash("test@example.com");
+    assert_eq!(hash, "973dfe463ec85785f5f95af5ba3906eedb2d931c24e69824a89ea65dba4e813b");
+    assert_eq!(email_hash("  Test@Example.COM "), hash);
+
+    let mut order = OrderState::new();
+    order.email = "test@example.com".to_string();
+    order.add_item(OrderProduct {
+        product_id: 1,
+        quantity: 2,
+    }).unwrap();
+
+    // Unpriced orders from no particular restaurant have no total or restaurant
+    let attributes = order_search_attributes(&order);
+    assert_eq!(attributes, vec![
+        (SearchAttributes::ORDER_STATUS.to_string(), serde_json::json!("DEFAULT")),
+        (SearchAttributes::CUSTOMER_EMAIL_HASH.to_string(), serde_json::json!(hash)),
+    ]);
+
+    order.status = OrderStatus::Pending;
+    order.restaurant_id = Some("pizza-place".to_string());
+    order.pricing = Some(price_order(&SampleCatalogue, &order.products).unwrap());
+    let attributes = order_search_attributes(&order);
+    assert_eq!(attributes.len(), 4);
+    assert!(attributes.contains(&(SearchAttributes::ORDER_STATUS.to_string(), serde_json::json!("PENDING"))));
+    assert!(attributes.contains(&(SearchAttributes::RESTAURANT_ID.to_string(), serde_json::json!("pizza-place"))));
+    assert!(attributes.contains(&(SearchAttributes::ORDER_TOTAL_PENCE.to_string(), serde_json::json!(2598))));
+    // Only the hash of the email address is indexed
+    assert!(!attributes.iter().any(|(_, value)| value == "test@example.com"));
+
+    // Every attribute the workflow sets is registered with the dev server
+    let args = dev_server_args();
+    assert_eq!(args.len(), SearchAttributes::ALL.len() * 2);
+    for (name, _) in &attributes {
+        assert!(args.iter().any(|a| a.starts_with(&format!("{}=", name))), "{} is not registered", name);
+    }
+    assert!(args.contains(&"OrderTotalPence=Int".to_string()));
+
+    assert_eq!(
+        OrderFilter::default().query(),
+        r#"WorkflowType="order_workflow" AND ExecutionStatus="Running""#
+    );
+    let filter = OrderFilter::default()
+        .with_status(OrderStatus::Pending)
+        .with_restaurant(r#"Bob's "Pizza""#)
+        .with_email("Test@Example.com");
+    assert_eq!(
+        filter.query(),
+        format!(
+            r#"WorkflowType="order_workflow" AND ExecutionStatus="Running" AND OrderStatus="PENDING" AND RestaurantId="Bob's \"Pizza\"" AND CustomerEmailHash="{}""#,
+            hash
+        )
+    );
+
+    info!("Search attributes test passed");
+}
+
+#[tokio::test]
+async fn test_kitchen_tickets() {
+    let _ = tracing_subscriber::fmt::try_init();
+
+    assert_eq!(restaurant_task_queue("pizza-place"), format!("{}-pizza-place", ORDER_FOOD_TASK_QUEUE));
+
+    let mut order = OrderState::new();
+    order.restaurant_id = Some("pizza-place".to_string());
+    order.collection = true;
+    order.add_item(OrderProduct {
+        product_id: 1,
+        quantity: 2,
+    }).unwrap();
+    order.add_item(OrderProduct {
+        product_id: 5,
+        quantity: 1,
+    }).unwrap();
+
+    // Products are named from the order's pricing
+    order.pricing = Some(price_order(&SampleCatalogue, &order.products).unwrap());
+    let ticket = KitchenTicket::for_order("order-1", &order);
+    assert_eq!(ticket.restaurant_id.as_deref(), Some("pizza-place"));
+    assert!(ticket.collection);
+    assert_eq!(ticket.lines[0], TicketLine {
+        product_id: 1,
+        name: "Margherita Pizza".to_string(),
+        quantity: 2,
+    });
+    assert_eq!(ticket.render(), "Order order-1 (collection)\n2 x Margherita Pizza\n1 x Coca Cola\n");
+
+    order.pricing = None;
+    assert_eq!(KitchenTicket::for_order("order-1", &order).lines[1].name, "Product 5");
+
+    // A retried activity does not print the ticket twice
+    let printer = MemoryPrinter::new();
+    printer.print("key-1", &ticket).await.unwrap();
+    printer.print("key-1", &ticket).await.unwrap();
+    assert_eq!(printer.printed(), vec![ticket.clone()]);
+    printer.print("key-2", &ticket).await.unwrap();
+    assert_eq!(printer.printed().len(), 2);
+
+    // A cancelled order tells the kitchen not to cook it
+    let cancellation = KitchenTicket::cancellation("order-1", &order);
+    assert!(cancellation.cancelled);
+    assert!(cancellation.render().starts_with("Order order-1 (collection) CANCELLED - do not cook\n"));
+
+    info!("Kitchen tickets test passed");
+}
+
+#[tokio::test]
+async fn test_kitchen_capacity() {
+    let _ = tracing_subscriber::fmt::try_init();
+
+    assert_eq!(kitchen_workflow_id(Some("pizza-place")), "kitchen-pizza-place");
+    assert_eq!(kitchen_workflow_id(None), "kitchen");
+    assert_eq!(KitchenCapacity { slots: 0, prep_secs: 600 }.validate(), Err(CapacityError::NoSlots));
+
+    // Two slots, ten minutes an order
+    let start = chrono::DateTime::from_timestamp(1_700_000_000, 0).unwrap();
+    let minutes = |m: i64| start + chrono::Duration::minutes(m);
+    let mut kitchen = KitchenQueue::new(KitchenCapacity { slots: 2, prep_secs: 600 });
+    assert!(kitchen.request("order-1", start));
+    assert!(kitchen.request("order-2", minutes(4)));
+    assert!(!kitchen.request("order-3", minutes(5)));
+    assert!(!kitchen.request("order-4", minutes(5)));
+
+    // Asking again keeps an order's place
+    assert!(!kitchen.request("order-3", minutes(5)));
+    assert!(kitchen.request("order-1", minutes(5)));
+    assert_eq!(kitchen.waiting, ["order-3", "order-4"]);
+
+    // Waiting orders start as the orders before them should be done
+    assert_eq!(kitchen.waits(minutes(5)), vec![
+        ("order-3".to_string(), KitchenSlot::Queued { ahead: 0, estimated_wait_secs: 5 * 60 }),
+        ("order-4".to_string(), KitchenSlot::Queued { ahead: 1, estimated_wait_secs: 9 * 60 }),
+    ]);
+    assert_eq!(kitchen.waits(minutes(20))[1].1, KitchenSlot::Queued {
+        ahead: 1,
+        estimated_wait_secs: 0,
+    });
+
+    // A released slot goes to the front of the queue, and waiting orders can leave it
+    assert_eq!(kitchen.release("order-1", minutes(11)), vec!["order-3"]);
+    assert!(kitchen.release("order-4", minutes(11)).is_empty());
+    assert!(kitchen.waiting.is_empty());
+    assert!(kitchen.is_preparing("order-3"));
+
+    // A kitchen that shrinks lets orders finish before it takes on more
+    assert!(kitchen.set_capacity(KitchenCapacity { slots: 1, prep_secs: 600 }, minutes(12)).is_empty());
+    assert!(!kitchen.request("order-5", minutes(12)));
+    assert_eq!(kitchen.waits(minutes(12)), vec![(
+        "order-5".to_string(),
+        KitchenSlot::Queued { ahead: 0, estimated_wait_secs: 9 * 60 }
+    )]);
+    assert!(kitchen.release("order-2", minutes(14)).is_empty());
+    assert_eq!(kitchen.release("order-3", minutes(21)), vec!["order-5"]);
+
+    // The restaurant can only start on an order with a slot
+    assert!(KitchenSlot::Granted.check_can_prepare().is_ok());
+    assert_eq!(KitchenSlot::Requested.check_can_prepare(), Err(SlotError::Requested));
+    let queued = KitchenSlot::Queued {
+        ahead: 2,
+        estimated_wait_secs: 601,
+    };
+    assert_eq!(
+        queued.check_can_prepare().unwrap_err().to_string(),
+        "kitchen is full - about 11 minutes to wait, with 2 ahead"
+    );
+
+    // Requests and the queue are sent between workflows as JSON
+    let request = KitchenRequest::Request {
+        order_id: "order-6".to_string(),
+    };
+    let json = serde_json::to_value(&request).unwrap();
+    assert_eq!(json["type"], "request");
+    assert_eq!(serde_json::from_value::<KitchenRequest>(json).unwrap(), request);
+    let json = serde_json::to_string(&kitchen).unwrap();
+    assert_eq!(serde_json::from_str::<KitchenQueue>(&json).unwrap(), kitchen);
+
+    info!("Kitchen capacity test passed");
+}
+
+#[tokio::test]
+async fn test_eta() {
+    let _ = tracing_subscriber::fmt::try_init();
+
+    // The slowest product sets the preparation time, with a default for products
+    // the catalogue has no time for
+    let mut priced = price_order(
+        &SampleCatalogue,
+        &[OrderProduct { product_id: 1, quantity: 2 }, OrderProduct { product_id: 3, quantity: 1 }],
+    )
+    .unwrap();
+    assert_eq!(prep_minutes(&priced), 15);
+    priced.line_items[0].prep_minutes = None;
+    priced.line_items[1].prep_minutes = Some(25);
+    assert_eq!(prep_minutes(&priced), 25);
+    priced.line_items[1].prep_minutes = None;
+    assert_eq!(prep_minutes(&priced), DEFAULT_PREP_MINUTES);
+    priced.line_items.clear();
+    assert_eq!(prep_minutes(&priced), 0);
+
+    // Districts are looked up before areas, then the default
+    let times = DeliveryTimes::from_json(r#"{ "default_minutes": 40, "post_codes": { "AB1": 10, "ab": 20 } }"#)
+        .unwrap();
+    assert_eq!(times.minutes_to("AB1 2CD"), 10);
+    assert_eq!(times.minutes_to("ab12cd"), 10);
+    assert_eq!(times.minutes_to("AB10 2CD"), 20);
+    assert_eq!(times.minutes_to("XY1 2CD"), 40);
+    assert_eq!(times.minutes_to(""), 40);
+    assert_eq!(times.minutes_to("AB1"), 40);
+
+    // Anything else, however it is written, takes the default time
+    assert_eq!(times.minutes_to("AB1 2CÉ"), 40);
+    assert_eq!(times.minutes_to("ÉAB"), 40);
+    assert_eq!(times.minutes_to("日本語"), 40);
+    assert_eq!(DeliveryTimes::default().minutes_to("AB1 2CD"), 30);
+    assert!(DeliveryTimes::from_json("{}").is_err());
+
+    // Orders waiting for the kitchen add their wait, and late ones are about to finish
+    let start = chrono::DateTime::from_timestamp(1_700_000_000, 0).unwrap();
+    let minutes = |m: i64| start + chrono::Duration::minutes(m);
+    let mut eta = Eta::new(20);
+    eta.revise(15, &OrderStatus::Pending, None, start, start);
+    assert_eq!(eta.at, minutes(35));
+    let queued = KitchenSlot::Queued { ahead: 1, estimated_wait_secs: 600 };
+    eta.revise(15, &OrderStatus::Accepted, Some(&queued), start, minutes(2));
+    assert_eq!(eta.at, minutes(47));
+    eta.revise(15, &OrderStatus::Preparing, Some(&KitchenSlot::Granted), minutes(10), minutes(12));
+    assert_eq!(eta.at, minutes(45));
+    eta.revise(15, &OrderStatus::Preparing, Some(&KitchenSlot::Granted), minutes(10), minutes(30));
+    assert_eq!(eta.at, minutes(50));
+    eta.revise(15, &OrderStatus::Ready, None, minutes(31), minutes(33));
+    assert_eq!(eta.at, minutes(53));
+    eta.revise(15, &OrderStatus::OutForDelivery, None, minutes(35), minutes(40));
+    assert_eq!(eta.at, minutes(55));
+    eta.revise(15, &OrderStatus::Completed, None, minutes(50), minutes(50));
+    assert_eq!(eta.at, minutes(55));
+
+    // The order keeps its ETA up to date from its basket and queue
+    let mut order_state = OrderState::new();
+    order_state.add_item(OrderProduct { product_id: 4, quantity: 1 }).unwrap();
+    order_state.pricing = Some(price_order(&SampleCatalogue, &order_state.products).unwrap());
+    order_state.revise_eta(start, start);
+    assert_eq!(order_state.eta, None);
+    order_state.eta = Some(Eta::new(0));
+    order_state.status = OrderStatus::Accepted;
+    order_state.kitchen_slot = Some(queued);
+    order_state.revise_eta(start, start);
+    assert_eq!(order_state.eta.map(|e| e.at), Some(minutes(30)));
+
+    info!("ETA test passed");
+}
+
+#[tokio::test]
+async fn test_receipts() {
+    let _ = tracing_subscriber::fmt::try_init();
+
+    let pence = |amount_in_pence| Amount { amount_in_pence, currency: Currency::Gbp };
+    let issued_at = chrono::DateTime::from_timestamp(1_700_000_000, 0).unwrap();
+
+    // VAT is included in prices, and rounded to the nearest penny
+    let vat = VatBreakdown::included_in(pence(2598), 20).unwrap();
+    assert_eq!((vat.net, vat.vat, vat.gross), (pence(2165), pence(433), pence(2598)));
+    assert_eq!(VatBreakdown::included_in(pence(299), 20).unwrap().vat, pence(50));
+    assert_eq!(VatBreakdown::included_in(pence(0), 20).unwrap().vat, pence(0));
+
+    // The largest gross splits without wrapping round, and only an absurd rate overflows
+    let vat = VatBreakdown::included_in(pence(u64::MAX), 20).unwrap();
+    assert_eq!(vat.vat, pence(3_074_457_345_618_258_603));
+    assert_eq!(vat.net.amount_in_pence + vat.vat.amount_in_pence, u64::MAX);
+    assert_eq!(VatBreakdown::included_in(pence(u64::MAX), u64::MAX), Err(PricingError::Overflow));
+
+    let mut order_state = OrderState::new();
+    assert_eq!(Receipt::for_order("order-1", &order_state, issued_at), Err(ReceiptError::NotPriced));
+
+    // 2 x Margherita Pizza, 1 x Caesar Salad, less 10%, with the salad refunded after
+    // it was taken out
+    order_state.delivery_address = Some(Address {
+        line1: "1 High St".to_string(),
+        line2: None,
+        line3: None,
+        town: "Anytown".to_string(),
+        county: None,
+        post_code: "AB1 2CD".to_string(),
+    });
+    order_state.add_item(OrderProduct { product_id: 1, quantity: 2 }).unwrap();
+    order_state.add_item(OrderProduct { product_id: 3, quantity: 1 }).unwrap();
+    let mut priced = price_order(&SampleCatalogue, &order_state.products).unwrap();
+    order_state.pricing = Some(priced.clone());
+    priced.apply_discount(AppliedDiscount { code: "TENTH".to_string(), amount_in_pence: 350 });
+    order_state.transactions = vec![
+        Transaction::Charge(Capture { transaction_id: "txn-1".to_string(), amount: priced.total() }),
+        Transaction::Refund(Refund {
+            refund_id: "refund-1".to_string(),
+            transaction_id: "txn-1".to_string(),
+            amount: pence(809),
+        }),
+    ];
+    order_state.remove_item(OrderProduct { product_id: 3, quantity: 1 });
+    priced = price_order(&SampleCatalogue, &order_state.products).unwrap();
+    priced.apply_discount(AppliedDiscount { code: "<TENTH>".to_string(), amount_in_pence: 260 });
+    order_state.pricing = Some(priced);
+
+    let receipt = Receipt::for_order("order-1", &order_state, issued_at).unwrap();
+    assert_eq!(receipt.lines.len(), 1);
+    assert_eq!(receipt.lines[0].unit_price, pence(1299));
+    assert_eq!(receipt.lines[0].total, pence(2598));
+    assert_eq!(receipt.subtotal, pence(2598));
+    assert_eq!(receipt.total, pence(2338));
+    assert_eq!(receipt.charged, pence(3147));
+    assert_eq!(receipt.refunded, pence(809));
+    assert_eq!(receipt.paid, pence(2338));
+    assert_eq!(receipt.vat.vat, pence(390));
+    assert_eq!(receipt.post_code.as_deref(), Some("AB1 2CD"));
+
+    let text = receipt.to_text();
+    assert!(text.starts_with("Receipt for order order-1\nIssued 2023-11-14 22:13 UTC\nDelivered to AB1 2CD\n"));
+    assert!(text.contains("2 x Margherita Pizza @ £12.99"));
+    assert!(text.contains("Discount (<TENTH>)"));
+    assert!(text.contains("-£8.09"));
+
+    // What was charged, less what was refunded, is what was paid
+    let summary: Vec<&str> = text.lines().skip_while(|l| !l.starts_with("Total")).take(4).collect();
+    assert_eq!(summary.len(), 4);
+    for (row, (label, amount)) in summary.iter().zip([
+        ("Total", "£23.38"),
+        ("Charged", "£31.47"),
+        ("Refunded", "-£8.09"),
+        ("Paid", "£23.38"),
+    ]) {
+        assert!(row.starts_with(label) && row.ends_with(amount), "{} is not {} {}", row, label, amount);
+    }
+    assert!(text.contains("VAT at 20% included"));
+
+    // Everything from the order is escaped in HTML
+    let html = receipt.to_html();
+    assert!(html.starts_with("<!DOCTYPE html>"));
+    assert!(html.contains("<td>Margherita Pizza</td><td>2</td><td>£12.99</td><td>£25.98</td>"));
+    assert!(html.contains("Discount (&lt;TENTH&gt;)"));
+    assert!(!html.contains("<TENTH>"));
+
+    // Stores replace what is under a key, and keys cannot leave the store
+    let store = MemoryBlobStore::new();
+    assert_eq!(store.put("order-1.txt", "text/plain", b"one".to_vec()).await.unwrap(), "memory://order-1.txt");
+    store.put("order-1.txt", "text/plain", b"two".to_vec()).await.unwrap();
+    assert_eq!(store.get("order-1.txt"), Some(("text/plain".to_string(), b"two".to_vec())));
+    for key in ["", "../order-1.txt", "a/b.txt", ".hidden"] {
+        assert!(matches!(store.put(key, "text/plain", Vec::new()).await, Err(BlobStoreError::InvalidKey(_))));
+    }
+
+    // Each run of an order has its own receipt, whatever its ID holds
+    assert_eq!(receipt_key("order-1", "run-1", "html"), "order-1.run-1.html");
+    assert_ne!(receipt_key("order-1", "run-2", "html"), receipt_key("order-1", "run-1", "html"));
+    assert_eq!(receipt_key("../shop/order 1", "run-1", "txt"), "_2E_2E_2Fshop_2Forder_201.run-1.txt");
+    assert_ne!(receipt_key("order_2F", "run-1", "txt"), receipt_key("order/", "run-1", "txt"));
+    let key = receipt_key("shop/order-1", "run-1", "txt");
+    assert!(store.put(&key, "text/plain", Vec::new()).await.is_ok());
+
+    let dir = std::env::temp_dir().join(format!("receipts-{}", std::process::id()));
+    let local = LocalBlobStore::new(&dir);
+    let url = local.put("order-1.html", "text/html", html.clone().into_bytes()).await.unwrap();
+    assert!(url.starts_with("file://") && url.ends_with("order-1.html"));
+    assert_eq!(std::fs::read_to_string(dir.join("order-1.html")).unwrap(), html);
+    let served = LocalBlobStore::new(&dir).with_base_url("https://example.com/receipts/");
+    assert_eq!(
+        served.put("order-1.html", "text/html", Vec::new()).await.unwrap(),
+        "https://example.com/receipts/order-1.html"
+    );
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    // The receipt email links to it
+    order_state.status = OrderStatus::Completed;
+    order_state.receipt = Some(StoredReceipt {
+        html_url: "https://example.com/receipts/order-1.html".to_string(),
+        text_url: "https://example.com/receipts/order-1.txt".to_string(),
+    });
+    let template = MessageTemplate::receipt();
+    assert_eq!(template.kind, NotificationKind::Receipt);
+    let (subject, body) = template.render(&order_state);
+    assert_eq!(subject, "Your receipt");
+    assert_eq!(body, "Thanks for your order of £23.38. Your receipt is at https://example.com/receipts/order-1.html");
+
+    info!("Receipts test passed");
+}
+
+#[tokio::test]
+async fn test_notifications() {
+    let _ = tracing_subscriber::fmt::try_init();
+
+    let mut order_state = OrderState::new();
+    order_state.email = "test@example.com".to_string();
+    order_state.add_item(OrderProduct { product_id: 1, quantity: 2 }).unwrap();
+    order_state.pricing = Some(price_order(&SampleCatalogue, &order_state.products).unwrap());
+    order_state.status = OrderStatus::Pending;
+
+    // Every status has its own message, filled in from the order
+    let (subject, body) = MessageTemplate::for_status(&OrderStatus::Pending).render(&order_state);
+    assert_eq!(subject, "Order received");
+    assert!(body.contains("£25.98"));
+
+    let sink = Arc::new(MemorySink::new());
+    let notifiers: Vec<Arc<dyn Notifier>> = vec![
+        Arc::new(SmsNotifier::new(sink.clone())),
+        Arc::new(EmailNotifier::new(sink.clone())),
+    ];
+
+    // No phone number, so only an email is sent
+    let sent = notify_all(&notifiers, "order-1/1/notify", &order_state).await.unwrap();
+    assert_eq!(sent.len(), 1);
+    assert_eq!(sent[0].channel, Channel::Email);
+    assert_eq!(sent[0].recipient, "test@example.com");
+    assert_eq!(sent[0].notification_id, "order-1/1/notify/email");
+    assert_eq!(sink.sent(), sent);
+    assert_eq!(sent[0].kind, NotificationKind::StatusUpdate);
+
+    // Escalations have their own message
+    let template = MessageTemplate::escalation_for(&OrderStatus::Pending);
+    assert_eq!(template.kind, NotificationKind::Escalation);
+    assert_eq!(template.render(&order_state).0, "Order delayed");
+
+    let (subject, body) = MessageTemplate::for_status(&OrderStatus::Cancelled).render(&order_state);
+    assert_eq!(subject, "Order cancelled");
+    assert!(body.contains("refunded"));
+
+    // Undelivered orders say why
+    order_state.delivery_failure = Some("no courier could deliver it".to_string());
+    let (subject, body) = MessageTemplate::for_status(&OrderStatus::DeliveryFailed).render(&order_state);
+    assert_eq!(subject, "Delivery failed");
+    assert!(body.contains("no courier could deliver it"));
+    order_state.delivery_failure = None;
+
+    // Orders on their way say when to expect them
+    let mut eta = Eta::new(30);
+    eta.at = chrono::DateTime::from_timestamp(1_700_000_000, 0).unwrap();
+    order_state.eta = Some(eta);
+    let (_, body) = MessageTemplate::for_status(&OrderStatus::Pending).render(&order_state);
+    assert!(body.ends_with("It should be with you at about 22:13 UTC."));
+    order_state.collection = true;
+    let (_, body) = MessageTemplate::for_status(&OrderStatus::Pending).render(&order_state);
+    assert!(body.ends_with("It should be ready to collect at about 22:13 UTC."));
+    order_state.collection = false;
+
+    // Texts carry the subject in the body and fit in one message
+    order_state.phone = Some("+447700900123".to_string());
+    order_state.status = OrderStatus::PaymentDeclined;
+    order_state.decline_reason = Some("x".repeat(200));
+    let sent = notify_all(&notifiers, "order-1/2/notify", &order_state).await.unwrap();
+    assert_eq!(sent.len(), 2);
+    assert_eq!(sent[0].channel, Channel::Sms);
+    assert!(sent[0].body.starts_with("Payment declined: "));
+    assert_eq!(sent[0].body.chars().count(), SMS_MAX_LENGTH);
+    assert_eq!(sink.sent().len(), 3);
+
+    // Webhooks that refuse a notification are not retried, unless they asked for it to
+    // be sent again later
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let webhooks = format!("http://{}", listener.local_addr().unwrap());
+    let app = axum::Router::new().route(
+        "/{status}",
+        axum::routing::post(|axum::extract::Path(status): axum::extract::Path<u16>| async move {
+            axum::http::StatusCode::from_u16(status).unwrap()
+        }),
+    );
+    tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+    for (status, retryable) in [(400, false), (404, false), (408, true), (429, true), (500, true), (503, true)] {
+        let webhook = WebhookNotifier::new(format!("{}/{}", webhooks, status));
+        let error = webhook.send(&sent[0]).await.unwrap_err();
+        assert_eq!(error.is_retryable(), retryable, "HTTP {}", status);
+        let error: ActivityError = error.into();
+        assert_eq!(matches!(error, ActivityError::Retryable { .. }), retryable, "HTTP {}", status);
+    }
+    assert!(WebhookNotifier::new(format!("{}/204", webhooks)).send(&sent[0]).await.is_ok());
+
+    // The development file sink writes one JSON line per notification
+    let path = std::env::temp_dir().join(format!("notifications-{}.jsonl", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    let file_notifiers: Vec<Arc<dyn Notifier>> = vec![Arc::new(EmailNotifier::new(Arc::new(FileSink::new(&path))))];
+    notify_all(&file_notifiers, "order-1/3/notify", &order_state).await.unwrap();
+    notify_all(&file_notifiers, "order-1/4/notify", &order_state).await.unwrap();
+    let written = std::fs::read_to_string(&path).unwrap();
+    assert_eq!(written.lines().count(), 2);
+    assert!(written.contains("order-1/4/notify/email"));
+    std::fs::remove_file(&path).unwrap();
+
+    info!("Notifications test passed");
+}
+
+#[tokio::test]
+async fn test_order_outcome_serialization() {
+    let _ = tracing_subscriber::fmt::try_init();
+
+    let mut order = OrderState::new();
+    order.status = OrderStatus::Rejected;
+    let amount = Amount { amount_in_pence: 1299, currency: Currency::Gbp };
+    let outcome = OrderOutcome {
+        status: OrderStatus::Rejected,
+        charged: Some(amount),
+        refunded: Some(amount),
+        transitions: vec![
+            StatusChange { status: OrderStatus::Default, at: "2025-01-01T12:00:00Z".parse().unwrap() },
+            StatusChange { status: OrderStatus::Pending, at: "2025-01-01T12:01:00Z".parse().unwrap() },
+            StatusChange { status: OrderStatus::Rejected, at: "2025-01-01T12:05:00Z".parse().unwrap() },
+        ],
+        notification_ids: vec!["order-1/5/notify/email".to_string()],
+        delivery: None,
+        order,
+    };
+
+    // The outcome survives the trip through a workflow result payload
+    let json = serde_json::to_string(&outcome).unwrap();
+    let deserialized: OrderOutcome = serde_json::from_str(&json).unwrap();
+    assert_eq!(deserialized.status, OrderStatus::Rejected);
+    assert_eq!(deserialized.charged, deserialized.refunded);
+    assert_eq!(deserialized.transitions, outcome.transitions);
+    assert_eq!(deserialized.notification_ids, outcome.notification_ids);
+    assert_eq!(deserialized.order.status, OrderStatus::Rejected);
+
+    info!("Order outcome serialization test passed");
+}
+
+#[tokio::test]
+async fn test_sla_policy() {
+    let _ = tracing_subscriber::fmt::try_init();
+
+    // The restaurant has 5 minutes to accept, then late orders are escalated
+    let policy = SlaPolicy::default();
+    let pending = policy.deadline_for(&OrderStatus::Pending).unwrap();
+    assert_eq!(pending.within(), std::time::Duration::from_secs(300));
+    assert_eq!(pending.on_breach, Breach::Reject);
+    assert_eq!(policy.deadline_for(&OrderStatus::Preparing).unwrap().on_breach, Breach::Escalate);
+    assert!(policy.deadline_for(&OrderStatus::Completed).is_none());
+    assert!(SlaPolicy::none().deadline_for(&OrderStatus::Pending).is_none());
+
+    let deadline = |status: OrderStatus, on_breach: Breach| Deadline { status, within_secs: 60, on_breach };
+
+    // Ready orders cannot be rejected, so cannot be rejected for being late
+    assert!(matches!(
+        SlaPolicy::new(vec![deadline(OrderStatus::Ready, Breach::Reject)]),
+        Err(SlaError::CannotReject(OrderStatus::Ready))
+    ));
+
+    // Orders are only held to deadlines between payment and finishing
+    assert!(matches!(
+        SlaPolicy::new(vec![deadline(OrderStatus::Default, Breach::Escalate)]),
+        Err(SlaError::Unpaid(OrderStatus::Default))
+    ));
+    for status in [
+        OrderStatus::Completed,
+        OrderStatus::Rejected,
+        OrderStatus::PaymentDeclined,
+        OrderStatus::Cancelled,
+        OrderStatus::DeliveryFailed,
+    ] {
+        let policy = SlaPolicy::new(vec![deadline(status.clone(), Breach::Escalate)]);
+        assert!(matches!(policy, Err(SlaError::Terminal(s)) if s == status));
+    }
+    assert!(matches!(
+        SlaPolicy::new(vec![
+            deadline(OrderStatus::Pending, Breach::Reject),
+            deadline(OrderStatus::Pending, Breach::Escalate),
+        ]),
+        Err(SlaError::Duplicate(OrderStatus::Pending))
+    ));
+
+    // Policies can be loaded from JSON, and are validated when they are
+    let policy = SlaPolicy::from_json(
+        r#"{ "deadlines": [{ "status": "Pending", "within_secs": 120, "on_breach": "Reject" }] }"#,
+    )
+    .unwrap();
+    assert_eq!(policy.deadline_for(&OrderStatus::Pending).unwrap().within_secs, 120);
+    assert!(SlaPolicy::from_json(
+        r#"{ "deadlines": [{ "status": "Ready", "within_secs": 120, "on_breach": "Reject" }] }"#,
+    )
+    .is_err());
+
+    info!("SLA policy test passed");
+}